@@ -1,5 +1,11 @@
 console.log('🛡️ [RiskOracle Guardian] Background Service Worker initialized.');
 
+const BACKEND_URL = import.meta.env.VITE_BACKEND_URL || 'http://localhost:8080';
+
+// Reintentos mientras la transacción no está minada (404 del backend)
+const CONFIRM_RETRY_MS = 15000;
+const CONFIRM_MAX_ATTEMPTS = 40;
+
 // Variables para rastrear las ventanas activas temporalmente en memoria
 let activePopups = {};
 
@@ -9,9 +15,37 @@ chrome.runtime.onMessage.addListener((message, sender, sendResponse) => {
         handleOpenPopup(message);
         // Respuesta rápida no bloqueante
         sendResponse({ status: 'popup_requested' });
+    } else if (message.type === 'TX_SENT') {
+        console.log('✍️ [RiskOracle Guardian Background] TX_SENT received:', message.txHash);
+        confirmTransaction(message.txHash, 1);
+        sendResponse({ status: 'confirmation_scheduled' });
     }
 });
 
+// Pide al backend que lea la transacción minada y añada sus contrapartes al historial del remitente.
+// El remitente lo prueba la firma, no el campo `from` de /analysis
+async function confirmTransaction(txHash, attempt) {
+    try {
+        const res = await fetch(`${BACKEND_URL}/history/confirm`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ tx_hash: txHash }),
+        });
+        const data = await res.json().catch(() => ({}));
+        if (res.status === 404 && attempt < CONFIRM_MAX_ATTEMPTS) {
+            setTimeout(() => confirmTransaction(txHash, attempt + 1), CONFIRM_RETRY_MS);
+            return;
+        }
+        if (res.ok) {
+            console.log(`📒 [RiskOracle] Tx ${txHash} confirmed, ${data.recorded ?? 0} counterparties recorded`);
+        } else {
+            console.warn(`⚠️ [RiskOracle] Tx ${txHash} not confirmed:`, data.message || `HTTP ${res.status}`);
+        }
+    } catch (e) {
+        console.error('❌ [RiskOracle] Error confirming transaction:', e.message);
+    }
+}
+
 function handleOpenPopup(message) {
     const transactionId = message.transactionId;
     const payloadStr = encodeURIComponent(JSON.stringify(message.payload || {}));
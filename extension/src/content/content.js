@@ -31,3 +31,21 @@ window.addEventListener('message', (event) => {
         }
     });
 });
+
+window.addEventListener('message', (event) => {
+    // Hash de una transacción firmada, reenviado al Background para confirmarla en el backend
+    if (event.source !== window || !event.data || event.data.type !== 'RISK_ORACLE_TX_SENT') {
+        return;
+    }
+
+    console.log('✍️ [RiskOracle Guardian] Transaction signed:', event.data.txHash);
+    chrome.runtime.sendMessage({
+        type: 'TX_SENT',
+        txHash: event.data.txHash,
+        transactionId: event.data.transactionId
+    }, () => {
+        if (chrome.runtime.lastError) {
+            console.error('❌ [RiskOracle Guardian] Error sending tx hash to background:', chrome.runtime.lastError.message);
+        }
+    });
+});
@@ -14,11 +14,12 @@ function injectProxy() {
                     // Interceptar los tres métodos clásicos
                     if (['request', 'send', 'sendAsync'].includes(prop) && typeof originalValue === 'function') {
                         return function (...args) {
+                            let transactionId = null;
                             try {
                                 const payload = args[0];
                                 // Validar si es una transacción
                                 if (payload && typeof payload === 'object' && payload.method === 'eth_sendTransaction') {
-                                    const transactionId = crypto.randomUUID();
+                                    transactionId = crypto.randomUUID();
                                     console.log('🚀 [RiskOracle Guardian] eth_sendTransaction interceptada en paralelo:', transactionId);
 
                                     // Enviar el mensaje para nuestra extensión de forma asíncrona
@@ -33,7 +34,20 @@ function injectProxy() {
                             }
 
                             // === DEVOLVER LA PROMESA ORIGINAL SIN ESPERAR (NO-BLOQUEANTE) ===
-                            return originalValue.apply(target, args);
+                            const result = originalValue.apply(target, args);
+
+                            // Cuando el usuario firma, avisar del hash para que el backend confirme la transacción
+                            // y guarde sus contrapartes en el historial de la wallet
+                            if (transactionId && result && typeof result.then === 'function') {
+                                result.then((txHash) => {
+                                    if (typeof txHash === 'string') {
+                                        window.postMessage({ type: 'RISK_ORACLE_TX_SENT', txHash, transactionId }, '*');
+                                    }
+                                }).catch(() => {
+                                    // Rechazada por el usuario: no hay nada que confirmar
+                                });
+                            }
+                            return result;
                         };
                     }
                     // Bindear el 'this' para que las llamadas internas no fallen (ej: provider.on)
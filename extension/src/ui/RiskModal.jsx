@@ -32,6 +32,8 @@ export default function RiskModal({ payload }) {
     const txParams = payload?.params?.[0] || {}
     const contractAddress = txParams.to || ''
     const callData = txParams.data || txParams.input || '0x'
+    const fromAddress = txParams.from || undefined
+    const txValue = txParams.value || undefined
 
     const handleVerify = (result) => {
         console.log('✅ [RiskOracle] World ID OK:', result)
@@ -46,7 +48,7 @@ export default function RiskModal({ payload }) {
             const res = await fetch(`${BACKEND_URL}/analysis`, {
                 method: 'POST',
                 headers: { 'Content-Type': 'application/json' },
-                body: JSON.stringify({ contract_address: contractAddress, call_data: callData }),
+                body: JSON.stringify({ contract_address: contractAddress, call_data: callData, from: fromAddress, value: txValue }),
             })
             if (!res.ok) {
                 const err = await res.json().catch(() => ({}))
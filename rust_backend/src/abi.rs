@@ -2,10 +2,86 @@ use ethabi::Contract;
 use ethers::types::Address;
 use log::{error, info};
 use reqwest::Client;
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::PathBuf;
+
+/// Directory shared by the ABI cache and the per-contract metadata caches: `ABI_DIR` (default: `ABI`).
+pub fn abi_dir() -> PathBuf {
+    PathBuf::from(env::var("ABI_DIR").ok().filter(|dir| !dir.is_empty()).unwrap_or_else(|| "ABI".to_string()))
+}
+
+// List of Chain IDs to try:
+// 11155111: Ethereum Sepolia (Priority 1)
+// 421614: Arbitrum Sepolia (Priority 2)
+pub const EXPLORER_CHAIN_IDS: [&str; 2] = ["11155111", "421614"];
+
+pub fn chain_name(chain_id: &str) -> &'static str {
+    if chain_id == "421614" { "Arbitrum Sepolia" } else { "Ethereum Sepolia" }
+}
+
+/// Calls the Etherscan V2 API for one chain and returns the `result` field.
+/// `query` is the module/action part, e.g. `module=contract&action=getsourcecode&address=0x..`.
+pub async fn explorer_request(
+    client: &Client,
+    chain_id: &str,
+    query: &str,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let api_key = env::var("ARBISCAN_API_KEY").unwrap_or_default();
+    let base = format!("https://api.etherscan.io/v2/api?chainid={}&{}", chain_id, query);
+    info!("📤 Request: {} (Chain ID: {})", base, chain_id);
+
+    let url = if api_key.is_empty() {
+        base
+    } else {
+        format!("{}&apikey={}", base, api_key)
+    };
+
+    let json: Value = client.get(&url).send().await?.json().await?;
+    if json["status"] == "1" {
+        Ok(json["result"].clone())
+    } else {
+        let message = json["message"].as_str().unwrap_or("Unknown");
+        let result = json["result"].as_str().unwrap_or_default();
+        Err(format!("{}: {}", message, result).into())
+    }
+}
+
+/// Path of a cached artifact for a contract, stored next to its ABI (`ABI/<address>.<kind>.json`).
+pub fn cache_path(contract_address: &Address, kind: &str) -> PathBuf {
+    let filename = format!("{:?}.{}.json", contract_address, kind).to_lowercase();
+    abi_dir().join(filename)
+}
+
+pub fn read_cached<T: DeserializeOwned>(contract_address: &Address, kind: &str) -> Option<T> {
+    let path = cache_path(contract_address, kind);
+    let content = fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            error!("❌ Ignoring corrupted cache file {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub fn write_cached<T: Serialize>(
+    contract_address: &Address,
+    kind: &str,
+    value: &T,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let abi_dir = abi_dir();
+    if !abi_dir.exists() {
+        info!("📁 Creating ABI directory: {}", abi_dir.display());
+        fs::create_dir_all(&abi_dir)?;
+    }
+    let path = cache_path(contract_address, kind);
+    fs::write(&path, serde_json::to_string_pretty(value)?)?;
+    info!("💾 Saved {} cache: {}", kind, path.display());
+    Ok(())
+}
 
 pub async fn fetch_abi_from_arbiscan(
     contract_address: &str,
@@ -17,14 +93,10 @@ pub async fn fetch_abi_from_arbiscan(
     let api_key = env::var("ARBISCAN_API_KEY").unwrap_or_default();
     let client = Client::new();
 
-    // List of Chain IDs to try:
-    // 11155111: Ethereum Sepolia (Priority 1)
-    // 421614: Arbitrum Sepolia (Priority 2)
-    let chain_ids = ["11155111", "421614"];
     let mut found_abis = Vec::new();
 
-    for chain_id in chain_ids {
-        let chain_name = if chain_id == "421614" { "Arbitrum Sepolia" } else { "Ethereum Sepolia" };
+    for chain_id in EXPLORER_CHAIN_IDS {
+        let chain_name = chain_name(chain_id);
         
         let url = if api_key.is_empty() {
             format!(
@@ -74,14 +146,14 @@ pub async fn fetch_abi_from_arbiscan(
 pub async fn get_or_fetch_abi(
     contract_address: &Address,
 ) -> Result<Vec<(Contract, Value)>, Box<dyn std::error::Error>> {
-    let abi_dir = "ABI";
+    let abi_dir = abi_dir();
     // Force lowercase filename to match Linux file system behavior reliably
     let abi_filename = format!("{:?}.json", contract_address).to_lowercase();
-    let abi_path = Path::new(abi_dir).join(&abi_filename);
+    let abi_path = abi_dir.join(&abi_filename);
 
-    if !Path::new(abi_dir).exists() {
-        info!("📁 Creating ABI directory: {}", abi_dir);
-        fs::create_dir_all(abi_dir)?;
+    if !abi_dir.exists() {
+        info!("📁 Creating ABI directory: {}", abi_dir.display());
+        fs::create_dir_all(&abi_dir)?;
     }
 
     if abi_path.exists() {
@@ -93,7 +165,7 @@ pub async fn get_or_fetch_abi(
         let contract = Contract::load(abi.to_string().as_bytes())?;
         
         info!("🔎 Functions loaded from local cache:");
-        for (name, _) in &contract.functions {
+        for name in contract.functions.keys() {
             info!("   - {}", name);
         }
 
@@ -114,7 +186,7 @@ pub async fn get_or_fetch_abi(
                  if let Ok(contract) = Contract::load(abi_str.as_bytes()) {
                      // Log functions for this ABI
                      info!("🔎 Functions found in downloaded ABI:");
-                     for (name, _) in &contract.functions {
+                     for name in contract.functions.keys() {
                          info!("   - {}", name);
                      }
                      contracts_and_abis.push((contract, abi.clone()));
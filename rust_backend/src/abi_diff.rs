@@ -0,0 +1,570 @@
+use ethabi::{Contract, StateMutability};
+use ethers::types::Address;
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::abi::get_or_fetch_abi;
+use crate::rules::{Finding, Severity};
+use crate::source::{find_definitions, get_or_fetch_source, mask_comments_and_strings, VerifiedSource};
+use crate::source_scan::access_guards;
+
+// Name prefixes of functions that are usually reserved to an owner or admin
+const PRIVILEGED_NAME_PREFIXES: [&str; 18] = [
+    "set", "withdraw", "upgrade", "mint", "pause", "unpause", "transferOwnership", "renounce", "rescue",
+    "sweep", "grant", "revoke", "blacklist", "emergency", "migrate", "kill", "destroy", "drain",
+];
+
+// Leading words of contract-level statements that are not state variables
+const NON_VARIABLE_KEYWORDS: [&str; 9] = [
+    "function", "modifier", "event", "error", "struct", "enum", "using", "constructor", "type",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffedFunction {
+    pub signature: String,
+    pub selector: String,
+    pub state_mutability: String,
+    pub guard: Option<String>, // Access guard found in the verified source
+    pub privileged: bool,      // Guarded in the source, or named like an admin function when there is no source
+}
+
+// A member present on both sides whose definition changed
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedMember {
+    pub signature: String,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StateVariable {
+    pub name: String,
+    pub var_type: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageConflict {
+    pub index: usize, // Position in declaration order
+    pub old: String,
+    pub new: String,
+}
+
+// Storage layout approximated from the verified source: state variables in declaration order,
+// base contracts first. Packing is ignored, so positions are not slot numbers.
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageLayoutDiff {
+    pub old_variables: usize,
+    pub new_variables: usize,
+    pub appended: Vec<String>,
+    pub removed: Vec<String>,
+    pub conflicts: Vec<StorageConflict>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContractDiff {
+    pub old_address: Address,
+    pub new_address: Address,
+    pub added_functions: Vec<DiffedFunction>,
+    pub removed_functions: Vec<DiffedFunction>,
+    pub changed_functions: Vec<ChangedMember>,
+    pub added_events: Vec<String>,
+    pub removed_events: Vec<String>,
+    pub changed_events: Vec<ChangedMember>,
+    pub permission_changes: Vec<ChangedMember>,
+    pub storage_layout: Option<StorageLayoutDiff>,
+}
+
+impl ContractDiff {
+    pub fn new_privileged_functions(&self) -> Vec<&DiffedFunction> {
+        self.added_functions.iter().filter(|f| f.privileged).collect()
+    }
+}
+
+/// Compares the ABIs (and, when both are verified, the permissions and storage layout)
+/// of two deployments, e.g. the current implementation of a proxy and the one an upgrade installs.
+pub async fn diff_contracts(
+    old_address: &Address,
+    new_address: &Address,
+) -> Result<ContractDiff, Box<dyn std::error::Error>> {
+    let old_abi = first_abi(old_address).await?;
+    let new_abi = first_abi(new_address).await?;
+
+    let old_source = source_or_none(old_address).await;
+    let new_source = source_or_none(new_address).await;
+    let old_guards = old_source.as_ref().map(access_guards);
+    let new_guards = new_source.as_ref().map(access_guards);
+
+    let old_functions = functions_by_signature(&old_abi, old_guards.as_ref());
+    let new_functions = functions_by_signature(&new_abi, new_guards.as_ref());
+
+    let added_functions: Vec<DiffedFunction> = new_functions
+        .iter()
+        .filter(|(signature, _)| !old_functions.contains_key(*signature))
+        .map(|(_, f)| f.clone())
+        .collect();
+    let removed_functions: Vec<DiffedFunction> = old_functions
+        .iter()
+        .filter(|(signature, _)| !new_functions.contains_key(*signature))
+        .map(|(_, f)| f.clone())
+        .collect();
+
+    let old_outputs = function_outputs(&old_abi);
+    let new_outputs = function_outputs(&new_abi);
+    let mut changed_functions = Vec::new();
+    let mut permission_changes = Vec::new();
+    for (signature, old) in &old_functions {
+        let new = match new_functions.get(signature) {
+            Some(new) => new,
+            None => continue,
+        };
+        let old_shape = format!("{} returns {}", old.state_mutability, old_outputs[signature]);
+        let new_shape = format!("{} returns {}", new.state_mutability, new_outputs[signature]);
+        if old_shape != new_shape {
+            changed_functions.push(ChangedMember {
+                signature: signature.clone(),
+                old: old_shape,
+                new: new_shape,
+            });
+        }
+        // Permissions can only be compared when both sides are verified
+        if old_guards.is_some() && new_guards.is_some() && old.guard != new.guard {
+            permission_changes.push(ChangedMember {
+                signature: signature.clone(),
+                old: old.guard.clone().unwrap_or_else(|| "unrestricted".to_string()),
+                new: new.guard.clone().unwrap_or_else(|| "unrestricted".to_string()),
+            });
+        }
+    }
+
+    let old_events = events_by_signature(&old_abi);
+    let new_events = events_by_signature(&new_abi);
+    let added_events = new_events.keys().filter(|s| !old_events.contains_key(*s)).cloned().collect();
+    let removed_events = old_events.keys().filter(|s| !new_events.contains_key(*s)).cloned().collect();
+    let changed_events = old_events
+        .iter()
+        .filter_map(|(signature, old)| {
+            let new = new_events.get(signature)?;
+            (old != new).then(|| ChangedMember {
+                signature: signature.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            })
+        })
+        .collect();
+
+    let storage_layout = match (&old_source, &new_source) {
+        (Some(old), Some(new)) => Some(diff_storage_layout(&storage_layout(old), &storage_layout(new))),
+        _ => None,
+    };
+
+    let diff = ContractDiff {
+        old_address: *old_address,
+        new_address: *new_address,
+        added_functions,
+        removed_functions,
+        changed_functions,
+        added_events,
+        removed_events,
+        changed_events,
+        permission_changes,
+        storage_layout,
+    };
+
+    info!(
+        "🧬 Diff {:?} -> {:?} - Functions +{} -{} ~{}, New privileged: {}, Storage conflicts: {:?}",
+        old_address,
+        new_address,
+        diff.added_functions.len(),
+        diff.removed_functions.len(),
+        diff.changed_functions.len(),
+        diff.new_privileged_functions().len(),
+        diff.storage_layout.as_ref().map(|layout| layout.conflicts.len())
+    );
+    Ok(diff)
+}
+
+async fn first_abi(address: &Address) -> Result<Contract, Box<dyn std::error::Error>> {
+    get_or_fetch_abi(address)
+        .await?
+        .into_iter()
+        .next()
+        .map(|(contract, _)| contract)
+        .ok_or_else(|| format!("No ABI available for {:?}", address).into())
+}
+
+async fn source_or_none(address: &Address) -> Option<VerifiedSource> {
+    match get_or_fetch_source(address).await {
+        Ok(source) => source,
+        Err(e) => {
+            warn!("⚠️ Verified source unavailable for {:?}: {}", address, e);
+            None
+        }
+    }
+}
+
+/// Name-based guess for functions usually reserved to an owner or admin, used without source.
+pub fn looks_privileged(name: &str) -> bool {
+    PRIVILEGED_NAME_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+fn canonical_signature(name: &str, kinds: &[String]) -> String {
+    format!("{}({})", name, kinds.join(","))
+}
+
+fn functions_by_signature(
+    contract: &Contract,
+    guards: Option<&BTreeMap<String, Option<String>>>,
+) -> BTreeMap<String, DiffedFunction> {
+    contract
+        .functions()
+        .map(|f| {
+            let kinds: Vec<String> = f.inputs.iter().map(|p| p.kind.to_string()).collect();
+            let signature = canonical_signature(&f.name, &kinds);
+            let read_only = matches!(f.state_mutability, StateMutability::View | StateMutability::Pure);
+            // Names the source does not define fall back to the name-based guess
+            let defined = guards.and_then(|g| g.get(&f.name));
+            let guard = defined.cloned().flatten();
+            let privileged = !read_only
+                && match defined {
+                    Some(guard) => guard.is_some(),
+                    None => looks_privileged(&f.name),
+                };
+            let function = DiffedFunction {
+                signature: signature.clone(),
+                selector: format!("0x{}", hex::encode(f.short_signature())),
+                state_mutability: format!("{:?}", f.state_mutability).to_lowercase(),
+                guard,
+                privileged,
+            };
+            (signature, function)
+        })
+        .collect()
+}
+
+fn function_outputs(contract: &Contract) -> BTreeMap<String, String> {
+    contract
+        .functions()
+        .map(|f| {
+            let kinds: Vec<String> = f.inputs.iter().map(|p| p.kind.to_string()).collect();
+            let outputs: Vec<String> = f.outputs.iter().map(|p| p.kind.to_string()).collect();
+            (canonical_signature(&f.name, &kinds), format!("({})", outputs.join(",")))
+        })
+        .collect()
+}
+
+// Event signature -> which parameters are indexed, e.g. "Transfer(address indexed,address indexed,uint256)"
+fn events_by_signature(contract: &Contract) -> BTreeMap<String, String> {
+    contract
+        .events()
+        .map(|e| {
+            let kinds: Vec<String> = e.inputs.iter().map(|p| p.kind.to_string()).collect();
+            let shape: Vec<String> = e
+                .inputs
+                .iter()
+                .map(|p| if p.indexed { format!("{} indexed", p.kind) } else { p.kind.to_string() })
+                .collect();
+            (canonical_signature(&e.name, &kinds), canonical_signature(&e.name, &shape))
+        })
+        .collect()
+}
+
+/// State variables of the verified contract in declaration order, base contracts first.
+pub fn storage_layout(source: &VerifiedSource) -> Vec<StateVariable> {
+    // Contract name -> (bases, own variables)
+    let mut contracts: BTreeMap<String, (Vec<String>, Vec<StateVariable>)> = BTreeMap::new();
+    for content in source.files.values() {
+        let masked = mask_comments_and_strings(content);
+        // Also matches `abstract contract`; interfaces and libraries have no storage
+        for (name, start, end) in find_definitions(&masked, "contract", None) {
+            contracts
+                .entry(name)
+                .or_insert_with(|| parse_contract(&masked[start..end]));
+        }
+    }
+
+    let mut layout = Vec::new();
+    let mut visited = BTreeSet::new();
+    collect_layout(&source.contract_name, &contracts, &mut visited, &mut layout);
+    layout
+}
+
+// Depth-first over the bases (most base first), which matches the C3 order for simple hierarchies
+fn collect_layout(
+    name: &str,
+    contracts: &BTreeMap<String, (Vec<String>, Vec<StateVariable>)>,
+    visited: &mut BTreeSet<String>,
+    layout: &mut Vec<StateVariable>,
+) {
+    if !visited.insert(name.to_string()) {
+        return;
+    }
+    if let Some((bases, variables)) = contracts.get(name) {
+        for base in bases {
+            collect_layout(base, contracts, visited, layout);
+        }
+        layout.extend(variables.iter().cloned());
+    }
+}
+
+// Bases from the `is A, B(args)` clause and the state variables declared in the body
+fn parse_contract(definition: &str) -> (Vec<String>, Vec<StateVariable>) {
+    let open = match definition.find('{') {
+        Some(open) => open,
+        None => return (Vec::new(), Vec::new()),
+    };
+    let header = &definition[..open];
+    let bases = match header.find(" is ") {
+        Some(is) => split_top_level(&header[is + 4..])
+            .into_iter()
+            .filter_map(|base| base.split('(').next().map(|b| b.trim().to_string()))
+            .filter(|base| !base.is_empty())
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let mut variables = Vec::new();
+    let mut depth = 0usize;
+    let mut statement = String::new();
+    for c in definition[open + 1..].chars() {
+        match c {
+            '{' => {
+                depth += 1;
+            }
+            '}' if depth == 0 => break,
+            '}' => {
+                depth -= 1;
+                // A block (function, struct, ...) ended: whatever preceded it was its header
+                if depth == 0 {
+                    statement.clear();
+                }
+            }
+            ';' if depth == 0 => {
+                if let Some(variable) = parse_state_variable(&statement) {
+                    variables.push(variable);
+                }
+                statement.clear();
+            }
+            _ if depth == 0 => statement.push(c),
+            _ => {}
+        }
+    }
+    (bases, variables)
+}
+
+fn parse_state_variable(statement: &str) -> Option<StateVariable> {
+    // Cut the initializer, but not the `=>` of mapping types
+    let initializer = statement
+        .match_indices('=')
+        .map(|(i, _)| i)
+        .find(|i| !statement[i + 1..].starts_with('>'));
+    let declaration = statement[..initializer.unwrap_or(statement.len())].trim();
+    let words: Vec<&str> = declaration.split_whitespace().collect();
+    let first = words.first()?;
+    if NON_VARIABLE_KEYWORDS.contains(first)
+        || words.iter().any(|w| *w == "constant" || *w == "immutable")
+        || words.len() < 2
+    {
+        return None;
+    }
+    let name = words.last()?.to_string();
+    let var_type = words[..words.len() - 1]
+        .iter()
+        .filter(|w| !matches!(**w, "public" | "private" | "internal" | "override" | "transient"))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(StateVariable { name, var_type })
+}
+
+// Splits on commas outside parentheses
+fn split_top_level(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut current = String::new();
+    for c in text.chars() {
+        match c {
+            '(' => {
+                depth += 1;
+                current.push(c);
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                current.push(c);
+            }
+            ',' if depth == 0 => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
+
+fn diff_storage_layout(old: &[StateVariable], new: &[StateVariable]) -> StorageLayoutDiff {
+    let describe = |v: &StateVariable| format!("{} {}", v.var_type, v.name);
+    let conflicts = old
+        .iter()
+        .zip(new.iter())
+        .enumerate()
+        .filter(|(_, (o, n))| o.var_type != n.var_type || o.name != n.name)
+        .map(|(index, (o, n))| StorageConflict {
+            index,
+            old: describe(o),
+            new: describe(n),
+        })
+        .collect();
+
+    StorageLayoutDiff {
+        old_variables: old.len(),
+        new_variables: new.len(),
+        appended: new.iter().skip(old.len()).map(describe).collect(),
+        removed: old.iter().skip(new.len()).map(describe).collect(),
+        conflicts,
+    }
+}
+
+/// Turns the diff into rule findings.
+pub fn diff_findings(diff: &ContractDiff) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let location = format!("{:?} -> {:?}", diff.old_address, diff.new_address);
+
+    let privileged = diff.new_privileged_functions();
+    if !privileged.is_empty() {
+        findings.push(
+            Finding::new(
+                "diff.new_privileged_functions",
+                Severity::High,
+                "New privileged functions",
+                format!(
+                    "The new version adds admin-only functions: {}",
+                    privileged
+                        .iter()
+                        .map(|f| match &f.guard {
+                            Some(guard) => format!("{} ({})", f.signature, guard),
+                            None => f.signature.clone(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            )
+            .at(location.clone()),
+        );
+    }
+
+    let describe_changes = |changes: &[&ChangedMember]| {
+        changes
+            .iter()
+            .map(|c| format!("{} ({} -> {})", c.signature, c.old, c.new))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let guard_removed: Vec<&ChangedMember> =
+        diff.permission_changes.iter().filter(|c| c.new == "unrestricted").collect();
+    let guard_changed: Vec<&ChangedMember> =
+        diff.permission_changes.iter().filter(|c| c.new != "unrestricted").collect();
+    if !guard_removed.is_empty() {
+        findings.push(
+            Finding::new(
+                "diff.guard_removed",
+                Severity::High,
+                "Access control removed",
+                format!("Anyone can now call: {}", describe_changes(&guard_removed)),
+            )
+            .at(location.clone()),
+        );
+    }
+    if !guard_changed.is_empty() {
+        findings.push(
+            Finding::new(
+                "diff.permissions_changed",
+                Severity::Medium,
+                "Access control changed",
+                format!("Functions whose guard changed: {}", describe_changes(&guard_changed)),
+            )
+            .at(location.clone()),
+        );
+    }
+
+    let became_payable: Vec<&ChangedMember> = diff
+        .changed_functions
+        .iter()
+        .filter(|c| !c.old.starts_with("payable") && c.new.starts_with("payable"))
+        .collect();
+    if !became_payable.is_empty() {
+        findings.push(
+            Finding::new(
+                "diff.became_payable",
+                Severity::Medium,
+                "Functions now accept ETH",
+                format!(
+                    "Now payable: {}",
+                    became_payable.iter().map(|c| c.signature.clone()).collect::<Vec<_>>().join(", ")
+                ),
+            )
+            .at(location.clone()),
+        );
+    }
+
+    if let Some(layout) = &diff.storage_layout {
+        if !layout.conflicts.is_empty() || !layout.removed.is_empty() {
+            findings.push(
+                Finding::new(
+                    "diff.storage_collision",
+                    Severity::High,
+                    "Storage layout is not upgrade-safe",
+                    format!(
+                        "Existing state variables were reordered, retyped or removed ({} conflict(s), {} removed), so the upgrade may corrupt balances and roles",
+                        layout.conflicts.len(),
+                        layout.removed.len()
+                    ),
+                )
+                .at(location),
+            );
+        }
+    }
+
+    findings
+}
+
+/// Renders the diff as compact text for the LLM prompt.
+pub fn format_diff_for_prompt(diff: Option<&ContractDiff>) -> String {
+    let diff = match diff {
+        Some(diff) => diff,
+        None => return "Not applicable (the call is not an upgrade)".to_string(),
+    };
+
+    let list = |items: Vec<String>| if items.is_empty() { "none".to_string() } else { items.join(", ") };
+    let mut lines = vec![
+        format!("Current implementation {:?} -> new {:?}", diff.old_address, diff.new_address),
+        format!(
+            "Added functions: {}",
+            list(diff
+                .added_functions
+                .iter()
+                .map(|f| if f.privileged { format!("{} [privileged]", f.signature) } else { f.signature.clone() })
+                .collect())
+        ),
+        format!("Removed functions: {}", list(diff.removed_functions.iter().map(|f| f.signature.clone()).collect())),
+        format!(
+            "Changed functions: {}",
+            list(diff.changed_functions.iter().map(|c| format!("{} ({} -> {})", c.signature, c.old, c.new)).collect())
+        ),
+        format!(
+            "Permission changes: {}",
+            list(diff.permission_changes.iter().map(|c| format!("{} ({} -> {})", c.signature, c.old, c.new)).collect())
+        ),
+        format!("Added events: {}", list(diff.added_events.clone())),
+        format!("Removed events: {}", list(diff.removed_events.clone())),
+    ];
+    match &diff.storage_layout {
+        Some(layout) => lines.push(format!(
+            "Storage layout: {} -> {} variables, {} conflict(s), appended: {}",
+            layout.old_variables,
+            layout.new_variables,
+            layout.conflicts.len(),
+            list(layout.appended.clone())
+        )),
+        None => lines.push("Storage layout: not compared (source not verified on both sides)".to_string()),
+    }
+    lines.join("\n")
+}
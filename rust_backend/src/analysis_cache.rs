@@ -0,0 +1,183 @@
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::{Address, U256};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::ensemble::EnsembleReport;
+use crate::prompt_template::PromptVersion;
+use crate::verdict::LlmVerdict;
+
+// Expired entries are dropped from memory once the map grows past this
+const MAX_MEMORY_ENTRIES: usize = 10_000;
+
+// A model verdict stored under the hash of everything that determines it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedVerdict {
+    pub key: String,
+    pub contract_address: Address,
+    pub chain_id: Option<u64>,
+    pub prompt_version: String, // "<template>/<version>"
+    pub model: String,          // "<provider>/<model>"
+    pub verdict: LlmVerdict,
+    pub attempts: usize,
+    #[serde(default)]
+    pub ensemble: Option<EnsembleReport>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Content address of one analysis: chain, target, calldata, value, prompt version and model.
+/// The prompt leaves out the sender, so every wallet sending the same call shares the verdict.
+pub fn cache_key(
+    chain_id: Option<u64>,
+    contract_address: &Address,
+    call_data: &[u8],
+    value: U256,
+    prompt_version: &PromptVersion,
+    provider: &str,
+    model: &str,
+) -> String {
+    let material = format!(
+        "{}|{:?}|0x{}|{}|{}/{}|{}/{}",
+        chain_id.map(|id| id.to_string()).unwrap_or_default(),
+        contract_address,
+        hex::encode(call_data),
+        value,
+        prompt_version.template,
+        prompt_version.version,
+        provider,
+        model
+    );
+    hex::encode(Sha256::digest(material.as_bytes()))
+}
+
+/// Model verdicts kept in memory and under `<dir>/<key>.json`, so a restart keeps the paid answers.
+pub struct AnalysisCache {
+    dir: PathBuf,
+    ttl: Duration,
+    entries: RwLock<HashMap<String, CachedVerdict>>,
+}
+
+impl AnalysisCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        AnalysisCache {
+            dir: dir.into(),
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Builds the cache from `ANALYSIS_CACHE_DIR` (default: `analysis_cache`) and
+    /// `ANALYSIS_CACHE_TTL_SECS` (default: 3600, 0 disables the cache).
+    pub fn from_env() -> Self {
+        let dir = env::var("ANALYSIS_CACHE_DIR").unwrap_or_else(|_| "analysis_cache".to_string());
+        let secs = env::var("ANALYSIS_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+            .unwrap_or(3600);
+        AnalysisCache::new(dir, Duration::seconds(secs.max(0)))
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.ttl > Duration::zero()
+    }
+
+    fn is_fresh(&self, entry: &CachedVerdict) -> bool {
+        Utc::now() - entry.created_at < self.ttl
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// The cached verdict for `key`, from memory or disk, while it is younger than the TTL.
+    /// Reads from disk, so async callers run it on `web::block`.
+    pub fn get(&self, key: &str) -> Option<CachedVerdict> {
+        if !self.is_enabled() {
+            return None;
+        }
+        if let Some(entry) = self.entries.read().unwrap().get(key) {
+            return self.is_fresh(entry).then(|| entry.clone());
+        }
+
+        let content = fs::read_to_string(self.entry_path(key)).ok()?;
+        let entry = match serde_json::from_str::<CachedVerdict>(&content) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("⚠️ Ignoring unreadable analysis cache entry {}: {}", key, e);
+                return None;
+            }
+        };
+        if !self.is_fresh(&entry) {
+            return None;
+        }
+        self.entries.write().unwrap().insert(key.to_string(), entry.clone());
+        Some(entry)
+    }
+
+    /// Writes to disk, so async callers run it on `web::block`.
+    pub fn insert(&self, entry: CachedVerdict) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Err(e) = self.save(&entry) {
+            error!("❌ Failed to save analysis cache entry {}: {}", entry.key, e);
+        }
+
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= MAX_MEMORY_ENTRIES {
+            let now = Utc::now();
+            entries.retain(|_, cached| now - cached.created_at < self.ttl);
+        }
+        entries.insert(entry.key.clone(), entry);
+    }
+
+    fn save(&self, entry: &CachedVerdict) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.entry_path(&entry.key), serde_json::to_string_pretty(entry)?)?;
+        Ok(())
+    }
+
+    /// Drops every verdict for `contract_address`, in memory and on disk. Returns how many were removed.
+    /// Scans the cache directory, so async callers run it on `web::block`.
+    pub fn invalidate_contract(&self, contract_address: &Address) -> io::Result<usize> {
+        let mut removed: Vec<String> = Vec::new();
+        {
+            let mut entries = self.entries.write().unwrap();
+            entries.retain(|key, entry| {
+                let matches = entry.contract_address == *contract_address;
+                if matches {
+                    removed.push(key.clone());
+                }
+                !matches
+            });
+        }
+
+        if self.dir.exists() {
+            for dir_entry in fs::read_dir(&self.dir)? {
+                let path = dir_entry?.path();
+                let Ok(content) = fs::read_to_string(&path) else {
+                    continue;
+                };
+                match serde_json::from_str::<CachedVerdict>(&content) {
+                    Ok(entry) if entry.contract_address == *contract_address => {
+                        fs::remove_file(&path)?;
+                        if !removed.contains(&entry.key) {
+                            removed.push(entry.key);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        info!("🧹 Invalidated {} cached verdict(s) for {:?}", removed.len(), contract_address);
+        Ok(removed.len())
+    }
+}
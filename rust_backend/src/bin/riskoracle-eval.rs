@@ -0,0 +1,105 @@
+use chrono::{Duration, Utc};
+use log::{error, info};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use rust_backend::analysis_cache::AnalysisCache;
+use rust_backend::config::PromptConfigStore;
+use rust_backend::eval::{
+    case_request, format_prompt_deltas, format_report, load_corpus, seed_case_artifacts, CaseResult, EvalModel,
+    EvalOptions, EvalReport, ModelMode,
+};
+use rust_backend::handlers::analyze_with_model;
+use rust_backend::history::AddressHistoryStore;
+use rust_backend::llm::build_provider;
+use rust_backend::reputation::ReputationStore;
+use rust_backend::resilience::CircuitBreakers;
+use rust_backend::AppState;
+
+/// Runs the labeled corpus through the /analysis pipeline without touching the network
+/// (except the model itself in record mode) and reports how the verdicts compare to the labels.
+/// Exits with status 1 when a case is rated below its label.
+#[actix_web::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenvy::dotenv().ok();
+    env_logger::init();
+
+    let options = EvalOptions::from_args()?;
+    let corpus = load_corpus(&options.corpus)?;
+    let corpus_dir = options.corpus.parent().unwrap_or(Path::new(".")).to_path_buf();
+
+    // Explorer artifacts come from the corpus, and nothing is left behind in the real caches
+    let work_dir = env::temp_dir().join(format!("riskoracle-eval-{}", std::process::id()));
+    env::set_var("ABI_DIR", work_dir.join("ABI"));
+    env::set_var("HISTORY_DIR", work_dir.join("history"));
+    env::remove_var("RPC_URL");
+
+    let prompt_config = Arc::new(PromptConfigStore::from_env()?);
+    let config = prompt_config.current();
+    let model = match options.mode {
+        ModelMode::Mock => "mock".to_string(),
+        ModelMode::Replay | ModelMode::Record => config.model_settings.model.clone(),
+    };
+    let reputation = Arc::new(ReputationStore::from_env());
+    let history = Arc::new(AddressHistoryStore::from_env());
+    // A TTL of 0 disables the verdict cache, every case asks the model
+    let analysis_cache = Arc::new(AnalysisCache::new(work_dir.join("analysis_cache"), Duration::zero()));
+
+    let mut results = Vec::new();
+    for case in &corpus.cases {
+        seed_case_artifacts(case, &corpus_dir)?;
+        let live = match options.mode {
+            ModelMode::Record => Some(build_provider(&config.model_settings)?),
+            ModelMode::Mock | ModelMode::Replay => None,
+        };
+        let eval_model = EvalModel {
+            mode: options.mode,
+            model: model.clone(),
+            mock_response: case.mock_response.clone(),
+            recordings: options.recordings.clone(),
+            live,
+        };
+        // Fresh circuit breakers, so a missing recording does not skip the model for the next cases
+        let state = AppState {
+            reputation: reputation.clone(),
+            history: history.clone(),
+            prompt_config: prompt_config.clone(),
+            analysis_cache: analysis_cache.clone(),
+            llm_breakers: Arc::new(CircuitBreakers::new()),
+        };
+
+        let (_, response) = analyze_with_model(&state, &case_request(case), Box::new(eval_model)).await;
+        let result = CaseResult::from_response(case, &response);
+        info!("🧾 {}: expected {}, got {:?}", result.id, result.expected, result.predicted);
+        results.push(result);
+    }
+    if let Err(e) = fs::remove_dir_all(&work_dir) {
+        error!("❌ Failed to remove {}: {}", work_dir.display(), e);
+    }
+
+    let report = EvalReport {
+        corpus: options.corpus.display().to_string(),
+        mode: options.mode.label().to_string(),
+        model,
+        created_at: Utc::now(),
+        cases: results,
+    };
+    println!("{}", format_report(&report));
+
+    if let Some(path) = &options.baseline {
+        let baseline: EvalReport = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| format!("invalid baseline report {}: {}", path.display(), e))?;
+        println!("\n{}", format_prompt_deltas(&report, &baseline));
+    }
+    if let Some(path) = &options.out {
+        fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        println!("\nReport written to {}", path.display());
+    }
+
+    if !report.false_negatives().is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,420 @@
+use ethers::types::Address;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::rules::{Finding, Severity};
+
+// Opcodes the analysis cares about
+const STOP: u8 = 0x00;
+const EQ: u8 = 0x14;
+const ISZERO: u8 = 0x15;
+const CALLVALUE: u8 = 0x34;
+const JUMP: u8 = 0x56;
+const JUMPI: u8 = 0x57;
+const JUMPDEST: u8 = 0x5b;
+const PUSH1: u8 = 0x60;
+const PUSH4: u8 = 0x63;
+const PUSH20: u8 = 0x73;
+const PUSH32: u8 = 0x7f;
+const DUP1: u8 = 0x80;
+const DUP16: u8 = 0x8f;
+const CALL: u8 = 0xf1;
+const CALLCODE: u8 = 0xf2;
+const RETURN: u8 = 0xf3;
+const DELEGATECALL: u8 = 0xf4;
+const REVERT: u8 = 0xfd;
+const INVALID: u8 = 0xfe;
+const SELFDESTRUCT: u8 = 0xff;
+
+// Upper bound of instructions visited per entry point when following jumps
+const MAX_REACHABLE_INSTRUCTIONS: usize = 20_000;
+
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub pc: usize,
+    pub opcode: u8,
+    pub push_data: Vec<u8>,
+}
+
+impl Instruction {
+    fn push_value(&self) -> Option<usize> {
+        if self.push_data.is_empty() || self.push_data.len() > 8 {
+            return None;
+        }
+        Some(self.push_data.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize))
+    }
+
+    fn is_push(&self) -> bool {
+        (PUSH1..=PUSH32).contains(&self.opcode)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryPoint {
+    pub selector: String, // 0x-prefixed 4-byte selector
+    pub offset: usize,
+    pub payable: bool,
+    pub forwards_value: bool,       // A CALL reachable from this entry reads msg.value
+    pub forwards_to_constant: bool, // ... and the reachable code pushes a hardcoded address
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BytecodeReport {
+    pub code_size: usize,
+    pub entry_points: Vec<EntryPoint>,
+    pub has_selfdestruct: bool,
+    pub has_delegatecall: bool,
+    pub has_callcode: bool,
+    pub hardcoded_addresses: Vec<Address>,
+}
+
+/// Splits runtime bytecode into instructions. The trailing Solidity CBOR metadata is skipped.
+pub fn disassemble(code: &[u8]) -> Vec<Instruction> {
+    let code = strip_metadata(code);
+    let mut instructions = Vec::new();
+    let mut pc = 0;
+
+    while pc < code.len() {
+        let opcode = code[pc];
+        let push_len = if (PUSH1..=PUSH32).contains(&opcode) {
+            (opcode - PUSH1 + 1) as usize
+        } else {
+            0
+        };
+        let end = (pc + 1 + push_len).min(code.len());
+        instructions.push(Instruction {
+            pc,
+            opcode,
+            push_data: code[pc + 1..end].to_vec(),
+        });
+        pc += 1 + push_len;
+    }
+
+    instructions
+}
+
+// solc appends `<cbor><2-byte length>`; the CBOR map starts with 0xa1..0xa5
+fn strip_metadata(code: &[u8]) -> &[u8] {
+    if code.len() < 2 {
+        return code;
+    }
+    let len = u16::from_be_bytes([code[code.len() - 2], code[code.len() - 1]]) as usize;
+    if len + 2 > code.len() {
+        return code;
+    }
+    let start = code.len() - 2 - len;
+    if (0xa1..=0xa5).contains(&code[start]) {
+        &code[..start]
+    } else {
+        code
+    }
+}
+
+/// Analyzes runtime bytecode: dispatcher entry points, dangerous opcodes and embedded addresses.
+pub fn analyze_bytecode(code: &[u8]) -> BytecodeReport {
+    let instructions = disassemble(code);
+    let index_by_pc: HashMap<usize, usize> = instructions
+        .iter()
+        .enumerate()
+        .map(|(i, ins)| (ins.pc, i))
+        .collect();
+    let jumpdests: HashSet<usize> = instructions
+        .iter()
+        .filter(|ins| ins.opcode == JUMPDEST)
+        .map(|ins| ins.pc)
+        .collect();
+
+    let mut entry_points = Vec::new();
+    let mut seen_selectors = HashSet::new();
+    for (selector, offset) in dispatcher_entries(&instructions) {
+        if !seen_selectors.insert(selector.clone()) {
+            continue;
+        }
+        let payable = is_payable_entry(&instructions, &index_by_pc, offset);
+        let (reachable, pushes_address) = reachable_opcodes(&instructions, &index_by_pc, &jumpdests, offset);
+        // A payable function that reads msg.value and performs a CALL is treated as forwarding it
+        let forwards_value = payable && reachable.contains(&CALL) && reachable.contains(&CALLVALUE);
+        entry_points.push(EntryPoint {
+            selector,
+            offset,
+            payable,
+            forwards_value,
+            forwards_to_constant: forwards_value && pushes_address,
+        });
+    }
+
+    let hardcoded_addresses: BTreeSet<Address> = instructions
+        .iter()
+        .filter(|ins| is_address_constant(ins))
+        .map(|ins| Address::from_slice(&ins.push_data))
+        .collect();
+
+    BytecodeReport {
+        code_size: code.len(),
+        entry_points,
+        has_selfdestruct: instructions.iter().any(|ins| ins.opcode == SELFDESTRUCT),
+        has_delegatecall: instructions.iter().any(|ins| ins.opcode == DELEGATECALL),
+        has_callcode: instructions.iter().any(|ins| ins.opcode == CALLCODE),
+        hardcoded_addresses: hardcoded_addresses.into_iter().collect(),
+    }
+}
+
+// 0xff..ff is the address mask emitted by solc, not a real address
+fn is_address_constant(ins: &Instruction) -> bool {
+    ins.opcode == PUSH20
+        && ins.push_data.len() == 20
+        && ins.push_data.iter().any(|b| *b != 0xff)
+        && ins.push_data.iter().any(|b| *b != 0)
+}
+
+/// Finds `PUSH4 <selector> [DUPn] EQ PUSHn <dest> JUMPI` sequences of the solc dispatcher.
+fn dispatcher_entries(instructions: &[Instruction]) -> Vec<(String, usize)> {
+    let mut entries = Vec::new();
+    for (i, ins) in instructions.iter().enumerate() {
+        if ins.opcode != PUSH4 || ins.push_data.len() != 4 {
+            continue;
+        }
+        let mut j = i + 1;
+        if instructions.get(j).map(|n| (DUP1..=DUP16).contains(&n.opcode)).unwrap_or(false) {
+            j += 1;
+        }
+        let matches = instructions.get(j).map(|n| n.opcode == EQ).unwrap_or(false)
+            && instructions.get(j + 1).map(|n| n.is_push()).unwrap_or(false)
+            && instructions.get(j + 2).map(|n| n.opcode == JUMPI).unwrap_or(false);
+        if matches {
+            if let Some(dest) = instructions[j + 1].push_value() {
+                entries.push((format!("0x{}", hex::encode(&ins.push_data)), dest));
+            }
+        }
+    }
+    entries
+}
+
+/// Non-payable solc functions start with `JUMPDEST CALLVALUE DUP1 ISZERO ...` and revert on value.
+fn is_payable_entry(instructions: &[Instruction], index_by_pc: &HashMap<usize, usize>, offset: usize) -> bool {
+    let i = match index_by_pc.get(&offset) {
+        Some(&i) => i,
+        None => return false,
+    };
+    let opcodes: Vec<u8> = instructions[i..].iter().take(4).map(|ins| ins.opcode).collect();
+    !opcodes.windows(3).any(|w| w == [CALLVALUE, DUP1, ISZERO])
+}
+
+/// Collects the opcodes reachable from an entry point by following static jumps (a push directly
+/// followed by JUMP or JUMPI) and fallthroughs, and whether that code pushes a hardcoded address.
+/// Other pushed jump destinations are not followed: return addresses and shared internal code
+/// would merge the code of unrelated selectors.
+fn reachable_opcodes(
+    instructions: &[Instruction],
+    index_by_pc: &HashMap<usize, usize>,
+    jumpdests: &HashSet<usize>,
+    entry: usize,
+) -> (HashSet<u8>, bool) {
+    let mut opcodes = HashSet::new();
+    let mut pushes_address = false;
+    let mut visited_blocks = HashSet::new();
+    let mut queue = VecDeque::from([entry]);
+    let mut budget = MAX_REACHABLE_INSTRUCTIONS;
+
+    while let Some(start) = queue.pop_front() {
+        if !visited_blocks.insert(start) {
+            continue;
+        }
+        let mut i = match index_by_pc.get(&start) {
+            Some(&i) => i,
+            None => continue,
+        };
+
+        while i < instructions.len() && budget > 0 {
+            budget -= 1;
+            let ins = &instructions[i];
+            opcodes.insert(ins.opcode);
+            pushes_address |= is_address_constant(ins);
+
+            let jumps_next = instructions
+                .get(i + 1)
+                .map(|next| next.opcode == JUMP || next.opcode == JUMPI)
+                .unwrap_or(false);
+            if ins.is_push() && jumps_next {
+                if let Some(target) = ins.push_value().filter(|t| jumpdests.contains(t)) {
+                    queue.push_back(target);
+                }
+            }
+
+            match ins.opcode {
+                JUMP | STOP | RETURN | REVERT | INVALID | SELFDESTRUCT => break,
+                JUMPI => {
+                    if let Some(next) = instructions.get(i + 1) {
+                        queue.push_back(next.pc);
+                    }
+                    break;
+                }
+                _ => {}
+            }
+
+            i += 1;
+            // A JUMPDEST starts a new block; continue through it as a fallthrough
+            if let Some(next) = instructions.get(i) {
+                if next.opcode == JUMPDEST {
+                    queue.push_back(next.pc);
+                    break;
+                }
+            }
+        }
+    }
+
+    (opcodes, pushes_address)
+}
+
+/// Turns the bytecode facts into rule findings. `called_selector` is the selector of the
+/// analyzed calldata, so a value-forwarding function that is actually being called is escalated.
+pub fn bytecode_findings(report: &BytecodeReport, called_selector: Option<&str>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if report.code_size == 0 {
+        findings.push(
+            Finding::new(
+                "bytecode.no_code",
+                Severity::Medium,
+                "Target is not a contract",
+                "The target address has no deployed code; calldata sent to it will not execute anything",
+            )
+            .at("target"),
+        );
+        return findings;
+    }
+
+    if report.has_selfdestruct {
+        findings.push(Finding::new(
+            "bytecode.selfdestruct",
+            Severity::High,
+            "SELFDESTRUCT in runtime code",
+            "The contract can destroy itself and send its balance to an arbitrary address",
+        ));
+    }
+    if report.has_callcode {
+        findings.push(Finding::new(
+            "bytecode.callcode",
+            Severity::High,
+            "CALLCODE in runtime code",
+            "Deprecated CALLCODE executes foreign code against this contract's storage",
+        ));
+    }
+    // Proxies and routers are built on these, so they add to the score without raising the verdict
+    if report.has_delegatecall {
+        findings.push(Finding::new(
+            "bytecode.delegatecall",
+            Severity::Low,
+            "DELEGATECALL in runtime code",
+            "The contract executes external code in its own context (proxy or library pattern); the logic can change",
+        ));
+    }
+    if !report.hardcoded_addresses.is_empty() {
+        findings.push(Finding::new(
+            "bytecode.hardcoded_address",
+            Severity::Info,
+            "Hardcoded addresses in bytecode",
+            format!(
+                "The code embeds {} fixed address(es): {}",
+                report.hardcoded_addresses.len(),
+                report
+                    .hardcoded_addresses
+                    .iter()
+                    .map(|a| format!("{:?}", a))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ));
+    }
+
+    // Routers, WETH and bridges forward msg.value by design; a hardcoded recipient is what stands out.
+    // Value reaching a third party in the simulation is reported by simulation.native_forwarded
+    for entry in report.entry_points.iter().filter(|e| e.forwards_value) {
+        let called = called_selector == Some(entry.selector.as_str());
+        let severity = match (entry.forwards_to_constant, called) {
+            (true, true) => Severity::Critical,
+            (true, false) => Severity::High,
+            (false, true) => Severity::Medium,
+            (false, false) => Severity::Info,
+        };
+        let recipient = if entry.forwards_to_constant {
+            " next to a hardcoded address"
+        } else {
+            ""
+        };
+        findings.push(
+            Finding::new(
+                "bytecode.value_forwarding",
+                severity,
+                "Payable function forwards msg.value",
+                if called {
+                    format!(
+                        "The called function {} is payable and passes the sent value on with a CALL{}",
+                        entry.selector, recipient
+                    )
+                } else {
+                    format!(
+                        "Payable function {} passes the sent value on with a CALL{}",
+                        entry.selector, recipient
+                    )
+                },
+            )
+            .at(format!("bytecode / selector {}", entry.selector)),
+        );
+    }
+
+    findings
+}
+
+/// Addresses embedded in the code, for the reputation checks.
+pub fn bytecode_addresses(report: &BytecodeReport) -> Vec<(Address, String)> {
+    report
+        .hardcoded_addresses
+        .iter()
+        .map(|a| (*a, "bytecode / PUSH20 constant".to_string()))
+        .collect()
+}
+
+/// Renders the bytecode facts as compact text for the LLM prompt.
+pub fn format_bytecode_for_prompt(report: Option<&BytecodeReport>) -> String {
+    let report = match report {
+        Some(report) => report,
+        None => return "Not available".to_string(),
+    };
+    if report.code_size == 0 {
+        return "No code at the target address (EOA)".to_string();
+    }
+
+    let entries = report
+        .entry_points
+        .iter()
+        .map(|e| {
+            format!(
+                "{}{}{}",
+                e.selector,
+                if e.payable { " (payable)" } else { "" },
+                if e.forwards_value { " [forwards msg.value]" } else { "" }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "Code size: {} bytes\nEntry points: {}\nSELFDESTRUCT: {}, DELEGATECALL: {}, CALLCODE: {}\nHardcoded addresses: {}",
+        report.code_size,
+        if entries.is_empty() { "none found".to_string() } else { entries },
+        report.has_selfdestruct,
+        report.has_delegatecall,
+        report.has_callcode,
+        if report.hardcoded_addresses.is_empty() {
+            "none".to_string()
+        } else {
+            report
+                .hardcoded_addresses
+                .iter()
+                .map(|a| format!("{:?}", a))
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    )
+}
@@ -1,11 +1,155 @@
+use log::{error, info, warn};
 use serde_json;
+use std::env;
 use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
 
+use crate::llm::build_provider;
+use crate::prompt_template::template_environment;
 use crate::PromptConfig;
 
-pub fn load_prompt_config() -> Result<PromptConfig, Box<dyn std::error::Error>> {
-    let config_path = "src/prompt_config.json";
-    let config_content = fs::read_to_string(config_path)?;
-    let config: PromptConfig = serde_json::from_str(&config_content)?;
+// Compiled into the binary, so it runs from any working directory
+pub const EMBEDDED_PROMPT_CONFIG: &str = include_str!("prompt_config.json");
+
+/// Parses and validates a prompt configuration; the error says what to fix.
+pub fn parse_prompt_config(content: &str) -> Result<PromptConfig, Box<dyn std::error::Error>> {
+    let config: PromptConfig =
+        serde_json::from_str(content).map_err(|e| format!("invalid prompt configuration JSON: {}", e))?;
+
+    if config.system_message.trim().is_empty() {
+        return Err("system_message must not be empty".into());
+    }
+    if config.max_output_attempts == 0 {
+        return Err("max_output_attempts must be at least 1".into());
+    }
+    template_environment(&config.prompt_templates)?;
+    for (name, template) in &config.prompt_templates {
+        if template.version.trim().is_empty() {
+            return Err(format!("prompt template \"{}\" has an empty version", name).into());
+        }
+    }
+    if config.resilience.attempt_timeout_secs == 0 || config.resilience.total_timeout_secs == 0 {
+        return Err("resilience.attempt_timeout_secs and resilience.total_timeout_secs must be at least 1".into());
+    }
+    if !(0.0..=1.0).contains(&config.ensemble.min_agreement) {
+        return Err("ensemble.min_agreement must be between 0 and 1".into());
+    }
+    if config.ensemble.samples == 0 {
+        return Err("ensemble.samples must be at least 1".into());
+    }
+    // A missing API key only fails /analysis, the other endpoints keep working
+    if let Err(e) = build_provider(&config.model_settings) {
+        warn!("⚠️ LLM provider not usable yet: {}", e);
+    }
+    if let Some(Err(e)) = config.fallback_model_settings.as_ref().map(build_provider) {
+        warn!("⚠️ Fallback LLM provider not usable yet: {}", e);
+    }
+    for model in config.ensemble.models.iter().filter(|_| config.ensemble.enabled) {
+        if let Err(e) = build_provider(model) {
+            warn!("⚠️ Ensemble member {} not usable yet: {}", model.model, e);
+        }
+    }
     Ok(config)
 }
+
+/// Override path from `--prompt-config <path>` (or `--prompt-config=<path>`), then `PROMPT_CONFIG_PATH`.
+pub fn prompt_config_path() -> Option<PathBuf> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--prompt-config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--prompt-config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    env::var("PROMPT_CONFIG_PATH").ok().filter(|path| !path.is_empty()).map(PathBuf::from)
+}
+
+// The active prompt configuration; requests hold their own Arc, so a reload never changes one in flight
+pub struct PromptConfigStore {
+    path: Option<PathBuf>, // None when running on the embedded default
+    current: RwLock<Arc<PromptConfig>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl PromptConfigStore {
+    /// Loads the override file when one is configured, otherwise the embedded default.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        let (config, modified) = match &path {
+            Some(path) => {
+                let modified = fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|e| format!("cannot read prompt configuration {}: {}", path.display(), e))?;
+                let content = fs::read_to_string(path)?;
+                let config = parse_prompt_config(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+                info!("📝 Prompt configuration loaded from {}", path.display());
+                (config, Some(modified))
+            }
+            None => {
+                let config = parse_prompt_config(EMBEDDED_PROMPT_CONFIG).map_err(|e| format!("embedded: {}", e))?;
+                info!("📝 Using the embedded prompt configuration");
+                (config, None)
+            }
+        };
+
+        Ok(PromptConfigStore {
+            path,
+            current: RwLock::new(Arc::new(config)),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// Same as `load` with the path from the command line or `PROMPT_CONFIG_PATH`.
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        PromptConfigStore::load(prompt_config_path())
+    }
+
+    pub fn current(&self) -> Arc<PromptConfig> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Swaps in the override file when it was modified and still validates.
+    /// An invalid file is reported once and the previous configuration stays active.
+    pub fn reload_if_changed(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        let modified = fs::metadata(path)?.modified()?;
+        {
+            let mut last = self.modified.lock().unwrap();
+            if *last == Some(modified) {
+                return Ok(false);
+            }
+            *last = Some(modified);
+        }
+
+        let config = parse_prompt_config(&fs::read_to_string(path)?)?;
+        *self.current.write().unwrap() = Arc::new(config);
+        info!("🔄 Prompt configuration reloaded from {}", path.display());
+        Ok(true)
+    }
+}
+
+/// Polls the override file and reloads it while the server runs.
+pub fn spawn_prompt_config_reload_task(store: Arc<PromptConfigStore>) {
+    if store.path.is_none() {
+        return;
+    }
+    let secs = env::var("PROMPT_CONFIG_RELOAD_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(5);
+
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(secs.max(1)));
+        loop {
+            interval.tick().await;
+            if let Err(e) = store.reload_if_changed() {
+                error!("❌ Keeping the previous prompt configuration: {}", e);
+            }
+        }
+    });
+}
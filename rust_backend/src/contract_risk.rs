@@ -0,0 +1,437 @@
+use chrono::{DateTime, Duration, Utc};
+use ethabi::{Contract, StateMutability};
+use ethers::providers::{Http, Provider};
+use ethers::types::Address;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+
+use crate::abi::{get_or_fetch_abi, read_cached, write_cached};
+use crate::abi_diff::looks_privileged;
+use crate::bytecode::{analyze_bytecode, bytecode_findings};
+use crate::hidden_functions::{abi_consistency_findings, abi_functions, check_abi_consistency};
+use crate::ownership::{analyze_ownership, ownership_findings, OwnershipReport};
+use crate::profile::{get_or_fetch_profile, profile_findings};
+use crate::rpc::get_code;
+use crate::rules::{risk_score, rule_risk_level, Finding, Severity};
+use crate::source::get_or_fetch_source;
+use crate::source_scan::{access_guards, scan_source};
+
+// Cache kind used next to the ABI file
+const RISK_PROFILE_CACHE_KIND: &str = "risk_profile";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    ValueTransfer,
+    Approval,
+    Admin,
+    Upgrade,
+    Pause,
+    Mint,
+    Burn,
+    View,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCapability {
+    pub signature: String,
+    pub selector: String,
+    pub capabilities: Vec<Capability>,
+    pub payable: bool,
+    pub guard: Option<String>, // Access guard found in the verified source
+    pub privileged: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivilegedRole {
+    pub name: String,            // Guard modifier, AccessControl role or "owner (inferred)"
+    pub holder: Option<Address>, // Known for the owner
+    pub functions: Vec<String>,
+}
+
+// Whole-contract profile, independent of any calldata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractRiskProfile {
+    pub address: Address,
+    pub implementation: Option<Address>, // Set when the address is a proxy
+    pub verified: bool,
+    pub functions: Vec<FunctionCapability>,
+    pub capability_counts: BTreeMap<Capability, usize>,
+    pub privileged_roles: Vec<PrivilegedRole>,
+    pub ownership: Option<OwnershipReport>,
+    pub findings: Vec<Finding>,
+    pub risk_score: u32,
+    pub risk_level: String,
+    pub generated_at: DateTime<Utc>,
+}
+
+impl ContractRiskProfile {
+    pub fn function(&self, name: &str) -> Option<&FunctionCapability> {
+        self.functions
+            .iter()
+            .find(|f| f.signature.split('(').next() == Some(name))
+    }
+}
+
+fn risk_profile_ttl() -> Duration {
+    let secs = env::var("CONTRACT_RISK_PROFILE_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(6 * 3600);
+    Duration::seconds(secs)
+}
+
+/// The cached profile while it is younger than `CONTRACT_RISK_PROFILE_TTL_SECS`, without fetching.
+pub fn cached_risk_profile(contract_address: &Address) -> Option<ContractRiskProfile> {
+    read_cached::<ContractRiskProfile>(contract_address, RISK_PROFILE_CACHE_KIND)
+        .filter(|profile| Utc::now() - profile.generated_at < risk_profile_ttl())
+}
+
+/// Returns the cached profile, or builds and caches a fresh one (always when `refresh` is set).
+pub async fn get_or_build_risk_profile(
+    contract_address: &Address,
+    provider: Option<&Provider<Http>>,
+    refresh: bool,
+) -> Result<ContractRiskProfile, Box<dyn std::error::Error>> {
+    if !refresh {
+        if let Some(profile) = cached_risk_profile(contract_address) {
+            info!("📖 Loading contract risk profile from local cache: {:?}", contract_address);
+            return Ok(profile);
+        }
+    }
+
+    let profile = build_risk_profile(contract_address, provider).await?;
+    if let Err(e) = write_cached(contract_address, RISK_PROFILE_CACHE_KIND, &profile) {
+        warn!("⚠️ Failed to cache contract risk profile: {}", e);
+    }
+    Ok(profile)
+}
+
+/// Classifies every function of the ABI (the implementation's too, for proxies), lists the
+/// privileged roles and combines the static checks into an overall risk.
+pub async fn build_risk_profile(
+    contract_address: &Address,
+    provider: Option<&Provider<Http>>,
+) -> Result<ContractRiskProfile, Box<dyn std::error::Error>> {
+    info!("🗂️ Building contract risk profile for {:?}", contract_address);
+
+    let mut contracts: Vec<Contract> = get_or_fetch_abi(contract_address)
+        .await?
+        .into_iter()
+        .map(|(contract, _)| contract)
+        .collect();
+    let mut findings = Vec::new();
+    let mut implementation = None;
+    let mut ownership = None;
+
+    if let Some(provider) = provider {
+        let code = get_code(provider, contract_address).await?;
+        let bytecode = analyze_bytecode(&code);
+        findings.extend(bytecode_findings(&bytecode, None));
+
+        let declared = contracts.first().map(abi_functions).unwrap_or_default();
+        match check_abi_consistency(provider, contract_address, &code, &bytecode, &declared).await {
+            Ok(report) => {
+                findings.extend(abi_consistency_findings(&report, None));
+                implementation = report.proxy.as_ref().map(|proxy| proxy.implementation);
+                match analyze_ownership(provider, contract_address, report.proxy.as_ref()).await {
+                    Ok(report) => {
+                        findings.extend(ownership_findings(&report));
+                        ownership = Some(report);
+                    }
+                    Err(e) => warn!("⚠️ Ownership analysis failed for {:?}: {}", contract_address, e),
+                }
+            }
+            Err(e) => warn!("⚠️ ABI cross-check failed for {:?}: {}", contract_address, e),
+        }
+    }
+
+    // Calls a proxy does not answer itself are delegated, so the implementation ABI counts too
+    if let Some(implementation) = implementation {
+        match get_or_fetch_abi(&implementation).await {
+            Ok(list) => contracts.extend(list.into_iter().map(|(contract, _)| contract)),
+            Err(e) => warn!("⚠️ No ABI for implementation {:?}: {}", implementation, e),
+        }
+    }
+
+    let source_address = implementation.unwrap_or(*contract_address);
+    let source = match get_or_fetch_source(&source_address).await {
+        Ok(source) => source,
+        Err(e) => {
+            warn!("⚠️ Verified source unavailable for {:?}: {}", source_address, e);
+            None
+        }
+    };
+    let guards = source.as_ref().map(access_guards);
+    if let Some(source) = &source {
+        findings.extend(scan_source(source, None));
+    }
+
+    match get_or_fetch_profile(contract_address, provider).await {
+        Ok(profile) => findings.extend(profile_findings(&profile)),
+        Err(e) => warn!("⚠️ Contract profile unavailable for {:?}: {}", contract_address, e),
+    }
+
+    let functions = classify_functions(&contracts, guards.as_ref());
+    let mut capability_counts = BTreeMap::new();
+    for function in &functions {
+        for capability in &function.capabilities {
+            *capability_counts.entry(*capability).or_insert(0) += 1;
+        }
+    }
+    let privileged_roles = privileged_roles(&functions, &contracts, ownership.as_ref());
+    findings.extend(capability_findings(&functions, guards.is_some()));
+
+    let profile = ContractRiskProfile {
+        address: *contract_address,
+        implementation,
+        verified: source.is_some(),
+        risk_score: risk_score(&findings),
+        risk_level: rule_risk_level(&findings)
+            .map(|level| level.to_string())
+            .unwrap_or_else(|| "Low".to_string()),
+        functions,
+        capability_counts,
+        privileged_roles,
+        ownership,
+        findings,
+        generated_at: Utc::now(),
+    };
+
+    info!(
+        "🗂️ Contract risk profile for {:?} - Functions: {}, Roles: {}, Risk: {} ({})",
+        contract_address,
+        profile.functions.len(),
+        profile.privileged_roles.len(),
+        profile.risk_level,
+        profile.risk_score
+    );
+    Ok(profile)
+}
+
+/// Capabilities of one function from its name and mutability.
+pub fn classify_function(name: &str, state_mutability: StateMutability) -> Vec<Capability> {
+    if matches!(state_mutability, StateMutability::View | StateMutability::Pure) {
+        return vec![Capability::View];
+    }
+
+    let lower = name.to_lowercase();
+    let mut capabilities = Vec::new();
+
+    if lower.starts_with("upgrade") || lower.contains("implementation") || lower == "setbeacon" {
+        capabilities.push(Capability::Upgrade);
+    }
+    if lower == "pause" || lower == "unpause" || lower.contains("setpaused") {
+        capabilities.push(Capability::Pause);
+    }
+    if lower.contains("mint") {
+        capabilities.push(Capability::Mint);
+    }
+    if lower.contains("burn") {
+        capabilities.push(Capability::Burn);
+    }
+    if ["approve", "increaseallowance", "decreaseallowance", "permit", "setapprovalforall"].contains(&lower.as_str()) {
+        capabilities.push(Capability::Approval);
+    }
+    let moves_value = state_mutability == StateMutability::Payable
+        || ["transfer", "withdraw", "send", "sweep", "rescue", "claim", "deposit", "swap", "pay"]
+            .iter()
+            .any(|hint| lower.contains(hint));
+    if moves_value && !lower.contains("ownership") {
+        capabilities.push(Capability::ValueTransfer);
+    }
+    if lower.contains("ownership") || lower.ends_with("role") || lower.starts_with("set") || lower.contains("admin") {
+        capabilities.push(Capability::Admin);
+    }
+
+    if capabilities.is_empty() {
+        capabilities.push(Capability::Other);
+    }
+    capabilities
+}
+
+fn classify_functions(contracts: &[Contract], guards: Option<&BTreeMap<String, Option<String>>>) -> Vec<FunctionCapability> {
+    let mut by_signature: BTreeMap<String, FunctionCapability> = BTreeMap::new();
+    for contract in contracts {
+        for f in contract.functions() {
+            let signature = format!(
+                "{}({})",
+                f.name,
+                f.inputs.iter().map(|p| p.kind.to_string()).collect::<Vec<_>>().join(",")
+            );
+            let mut capabilities = classify_function(&f.name, f.state_mutability);
+            let read_only = capabilities == [Capability::View];
+            // Names the source does not define fall back to the name-based guess
+            let defined = guards.and_then(|g| g.get(&f.name));
+            let guard = defined.cloned().flatten();
+            let privileged = !read_only
+                && match defined {
+                    Some(guard) => guard.is_some(),
+                    None => looks_privileged(&f.name),
+                };
+            // Anything behind an access guard is an admin capability, whatever its name
+            if privileged && !capabilities.contains(&Capability::Admin) {
+                capabilities.retain(|c| *c != Capability::Other);
+                capabilities.push(Capability::Admin);
+            }
+            by_signature.entry(signature.clone()).or_insert(FunctionCapability {
+                signature,
+                selector: format!("0x{}", hex::encode(f.short_signature())),
+                capabilities,
+                payable: f.state_mutability == StateMutability::Payable,
+                guard,
+                privileged,
+            });
+        }
+    }
+    by_signature.into_values().collect()
+}
+
+fn privileged_roles(
+    functions: &[FunctionCapability],
+    contracts: &[Contract],
+    ownership: Option<&OwnershipReport>,
+) -> Vec<PrivilegedRole> {
+    let owner = ownership.and_then(|report| report.owner_chain.first()).map(|c| c.address);
+    let mut roles: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for function in functions.iter().filter(|f| f.privileged) {
+        let role = function.guard.clone().unwrap_or_else(|| "owner (inferred)".to_string());
+        roles.entry(role).or_default().push(function.signature.clone());
+    }
+
+    // AccessControl roles exposed as `FOO_ROLE()` getters
+    for contract in contracts {
+        for f in contract.functions() {
+            if f.name.ends_with("_ROLE") && f.inputs.is_empty() {
+                roles.entry(f.name.clone()).or_default();
+            }
+        }
+    }
+
+    roles
+        .into_iter()
+        .map(|(name, functions)| {
+            let is_owner = name == "onlyOwner" || name == "owner (inferred)" || name == "msg.sender check";
+            PrivilegedRole {
+                holder: if is_owner { owner } else { None },
+                name,
+                functions,
+            }
+        })
+        .collect()
+}
+
+fn capability_findings(functions: &[FunctionCapability], verified: bool) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let names = |capability: Capability, privileged_only: bool| -> Vec<String> {
+        functions
+            .iter()
+            .filter(|f| f.capabilities.contains(&capability) && (!privileged_only || f.privileged))
+            .map(|f| f.signature.clone())
+            .collect()
+    };
+
+    let mint = names(Capability::Mint, true);
+    if !mint.is_empty() {
+        findings.push(
+            Finding::new(
+                "capability.privileged_mint",
+                Severity::Medium,
+                "Supply can be inflated",
+                format!("A privileged role can mint new tokens: {}", mint.join(", ")),
+            )
+            .at("contract"),
+        );
+    }
+
+    let pause = names(Capability::Pause, false);
+    if !pause.is_empty() {
+        findings.push(
+            Finding::new(
+                "capability.pausable",
+                Severity::Low,
+                "Contract can be paused",
+                format!("Transfers or calls can be frozen through: {}", pause.join(", ")),
+            )
+            .at("contract"),
+        );
+    }
+
+    // With verified source, sensitive functions without any guard stand out
+    if verified {
+        let unguarded: Vec<String> = functions
+            .iter()
+            .filter(|f| {
+                // Privileged without a guard means the source does not define it, not that it is open
+                f.guard.is_none()
+                    && !f.privileged
+                    && f.capabilities
+                        .iter()
+                        .any(|c| matches!(c, Capability::Upgrade | Capability::Mint | Capability::Pause))
+            })
+            .map(|f| f.signature.clone())
+            .collect();
+        if !unguarded.is_empty() {
+            findings.push(
+                Finding::new(
+                    "capability.unguarded_admin",
+                    Severity::High,
+                    "Sensitive functions without access control",
+                    format!("No access guard found on: {}", unguarded.join(", ")),
+                )
+                .at("contract"),
+            );
+        }
+    }
+
+    findings
+}
+
+/// Renders the profile as compact text for the LLM prompt, pointing out the called function.
+pub fn format_risk_profile_for_prompt(profile: Option<&ContractRiskProfile>, function_name: &str) -> String {
+    let profile = match profile {
+        Some(profile) => profile,
+        None => return "Not available (no cached contract profile)".to_string(),
+    };
+
+    let mut lines = vec![format!(
+        "Overall contract risk: {} (score {})",
+        profile.risk_level, profile.risk_score
+    )];
+    lines.push(format!(
+        "Capabilities: {}",
+        profile
+            .capability_counts
+            .iter()
+            .map(|(capability, count)| format!("{:?} x{}", capability, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ));
+    if !profile.privileged_roles.is_empty() {
+        lines.push(format!(
+            "Privileged roles: {}",
+            profile
+                .privileged_roles
+                .iter()
+                .map(|role| format!("{} ({} function(s))", role.name, role.functions.len()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    if let Some(function) = profile.function(function_name) {
+        lines.push(format!(
+            "Called function {}: {:?}{}",
+            function.signature,
+            function.capabilities,
+            function
+                .guard
+                .as_ref()
+                .map(|guard| format!(", guarded by {}", guard))
+                .unwrap_or_default()
+        ));
+    }
+    lines.join("\n")
+}
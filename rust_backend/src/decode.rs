@@ -1,11 +1,18 @@
-use ethabi::{Contract, Token};
+use ethabi::{Contract, Function, Token};
+use ethers::types::Address;
 
 pub fn decode_function_call(
     contract: &Contract,
     call_data: &str,
 ) -> Result<(String, Vec<Token>), Box<dyn std::error::Error>> {
     let call_data_bytes = hex::decode(call_data.strip_prefix("0x").unwrap_or(call_data))?;
+    decode_function_call_bytes(contract, &call_data_bytes)
+}
 
+pub fn decode_function_call_bytes(
+    contract: &Contract,
+    call_data_bytes: &[u8],
+) -> Result<(String, Vec<Token>), Box<dyn std::error::Error>> {
     if call_data_bytes.len() < 4 {
         return Err("Call data is too short".into());
     }
@@ -35,3 +42,51 @@ pub fn decode_function_call(
     )
     .into())
 }
+
+/// The ABI function whose selector matches the call data, if any.
+pub fn find_function<'a>(contract: &'a Contract, call_data_bytes: &[u8]) -> Option<&'a Function> {
+    let selector = call_data_bytes.get(..4)?;
+    contract
+        .functions()
+        .find(|function| function.short_signature() == selector)
+}
+
+// Maximum depth when following nested calldata (multicall(bytes[]) and similar)
+const MAX_NESTED_CALL_DEPTH: usize = 3;
+
+/// Collects every address contained in the decoded arguments, including addresses
+/// inside nested calls encoded as `bytes` that decode against the same contract.
+/// Each address is returned with a human readable location.
+pub fn collect_addresses(contract: &Contract, args: &[Token]) -> Vec<(Address, String)> {
+    let mut found = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        collect_from_token(contract, arg, &format!("argument {}", i), 0, &mut found);
+    }
+    found
+}
+
+fn collect_from_token(
+    contract: &Contract,
+    token: &Token,
+    location: &str,
+    depth: usize,
+    found: &mut Vec<(Address, String)>,
+) {
+    match token {
+        Token::Address(addr) => found.push((Address::from_slice(addr.as_bytes()), location.to_string())),
+        Token::Array(items) | Token::FixedArray(items) | Token::Tuple(items) => {
+            for (i, item) in items.iter().enumerate() {
+                collect_from_token(contract, item, &format!("{}[{}]", location, i), depth, found);
+            }
+        }
+        Token::Bytes(bytes) if depth < MAX_NESTED_CALL_DEPTH => {
+            if let Ok((name, nested_args)) = decode_function_call_bytes(contract, bytes) {
+                for (i, arg) in nested_args.iter().enumerate() {
+                    let nested_location = format!("{} / nested call {} argument {}", location, name, i);
+                    collect_from_token(contract, arg, &nested_location, depth + 1, found);
+                }
+            }
+        }
+        _ => {}
+    }
+}
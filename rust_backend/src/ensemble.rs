@@ -0,0 +1,274 @@
+use futures_util::future::join_all;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use crate::llm::{build_provider, LlmError, LlmProvider, ProviderKind};
+use crate::resilience::{provider_id, CircuitBreakers, ResilientProvider};
+use crate::rules::RiskLevel;
+use crate::verdict::{request_verdict, LlmVerdict, ModelFinding, VerdictError};
+use crate::{EnsembleSettings, ModelSettings, ResilienceSettings};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnsemblePolicy {
+    Majority, // Most votes; a tie goes to the more severe level
+    #[default]
+    MostSevere, // Any member can raise the verdict
+}
+
+// One model (or one seeded sample of it) taking part in the ensemble
+pub struct EnsembleMember {
+    pub id: String, // "<provider>/<model>"
+    pub sample: usize,
+    pub llm: Box<dyn LlmProvider>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsembleVote {
+    pub model: String,
+    pub sample: usize,
+    pub risk_level: Option<RiskLevel>, // None when the member gave no valid verdict
+    pub confidence: Option<f64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsembleReport {
+    pub policy: EnsemblePolicy,
+    pub risk_level: RiskLevel,
+    pub votes: Vec<EnsembleVote>,
+    pub answered: usize,
+    pub agreement: f64, // Share of the answering members that voted for risk_level
+    pub needs_human_review: bool,
+    pub review_reasons: Vec<String>,
+}
+
+// The members of an enabled ensemble with its aggregation settings
+pub struct Ensemble {
+    pub members: Vec<EnsembleMember>,
+    pub settings: EnsembleSettings,
+}
+
+/// The members configured by `ensemble`: every model (the primary one when `models` is empty),
+/// each asked `samples` times. Samples of the same model get distinct seeds where the API has one.
+/// None when ensemble mode is off or no member could be built.
+pub fn build_ensemble(settings: &EnsembleSettings, primary: &ModelSettings) -> Option<Ensemble> {
+    if !settings.enabled {
+        return None;
+    }
+    let models = if settings.models.is_empty() {
+        std::slice::from_ref(primary)
+    } else {
+        settings.models.as_slice()
+    };
+    let samples = settings.samples.max(1);
+
+    let mut members = Vec::new();
+    for model in models {
+        for sample in 0..samples {
+            let mut model = model.clone();
+            // Anthropic rejects unknown request fields and has no seed
+            if samples > 1 && model.provider != ProviderKind::Anthropic {
+                model.options.insert("seed".to_string(), json!(sample));
+            }
+            match build_provider(&model) {
+                Ok(llm) => members.push(EnsembleMember {
+                    id: provider_id(llm.as_ref()),
+                    sample,
+                    llm,
+                }),
+                Err(e) => warn!("⚠️ Skipping ensemble member {}: {}", model.model, e),
+            }
+        }
+    }
+    if members.is_empty() {
+        warn!("⚠️ Ensemble mode is enabled but no member could be built");
+        return None;
+    }
+    Some(Ensemble {
+        members,
+        settings: settings.clone(),
+    })
+}
+
+/// Aggregated level and the number of votes for it.
+pub fn aggregate(policy: EnsemblePolicy, levels: &[RiskLevel]) -> Option<(RiskLevel, usize)> {
+    let mut votes: BTreeMap<RiskLevel, usize> = BTreeMap::new();
+    for level in levels {
+        *votes.entry(*level).or_default() += 1;
+    }
+    match policy {
+        EnsemblePolicy::MostSevere => votes.into_iter().next_back(),
+        // BTreeMap iterates from Low to Critical, so max_by_key keeps the most severe of tied levels
+        EnsemblePolicy::Majority => votes.into_iter().max_by_key(|(_, count)| *count),
+    }
+}
+
+/// Merges the member verdicts: the aggregated level with the explanation of its most confident
+/// voter, the union of findings and dangerous functions, and a confidence scaled by agreement.
+pub fn combine_verdicts(
+    settings: &EnsembleSettings,
+    votes: Vec<EnsembleVote>,
+    verdicts: Vec<LlmVerdict>,
+) -> Option<(LlmVerdict, EnsembleReport)> {
+    let levels: Vec<RiskLevel> = verdicts.iter().map(|verdict| verdict.risk_level).collect();
+    let (risk_level, supporting) = aggregate(settings.policy, &levels)?;
+    let agreement = supporting as f64 / verdicts.len() as f64;
+
+    let agreeing: Vec<&LlmVerdict> = verdicts.iter().filter(|verdict| verdict.risk_level == risk_level).collect();
+    let lead = agreeing
+        .iter()
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+        .copied()?;
+    let mean_confidence = agreeing.iter().map(|verdict| verdict.confidence).sum::<f64>() / agreeing.len() as f64;
+
+    let mut findings: Vec<ModelFinding> = Vec::new();
+    let mut dangerous_functions: Vec<String> = Vec::new();
+    for verdict in &verdicts {
+        for finding in &verdict.findings {
+            if !findings.iter().any(|known| known.title.eq_ignore_ascii_case(&finding.title)) {
+                findings.push(finding.clone());
+            }
+        }
+        for function in &verdict.dangerous_functions {
+            if !dangerous_functions.contains(function) {
+                dangerous_functions.push(function.clone());
+            }
+        }
+    }
+
+    let mut review_reasons = Vec::new();
+    if levels.iter().any(|level| *level != risk_level) {
+        let mut counts: BTreeMap<RiskLevel, usize> = BTreeMap::new();
+        for level in &levels {
+            *counts.entry(*level).or_default() += 1;
+        }
+        let spread: Vec<String> = counts.iter().rev().map(|(level, count)| format!("{}x {}", count, level)).collect();
+        review_reasons.push(format!("models disagree: {}", spread.join(", ")));
+    }
+    if agreement < settings.min_agreement {
+        review_reasons.push(format!(
+            "agreement {:.0}% is below the required {:.0}%",
+            agreement * 100.0,
+            settings.min_agreement * 100.0
+        ));
+    }
+    if verdicts.len() < votes.len() {
+        review_reasons.push(format!("only {} of {} members answered", verdicts.len(), votes.len()));
+    }
+
+    let verdict = LlmVerdict {
+        risk_level,
+        explanation: lead.explanation.clone(),
+        findings,
+        dangerous_functions,
+        confidence: mean_confidence * agreement,
+    };
+    let report = EnsembleReport {
+        policy: settings.policy,
+        risk_level,
+        answered: verdicts.len(),
+        votes,
+        agreement,
+        needs_human_review: !review_reasons.is_empty(),
+        review_reasons,
+    };
+    Some((verdict, report))
+}
+
+impl Ensemble {
+    /// Stable description of the ensemble, used in the cache key and `verdict_source`.
+    pub fn id(&self) -> String {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for member in &self.members {
+            *counts.entry(member.id.as_str()).or_default() += 1;
+        }
+        let models: Vec<String> = counts.iter().map(|(id, count)| format!("{} x{}", id, count)).collect();
+        format!("ensemble({:?}): {}", self.settings.policy, models.join(", "))
+    }
+
+    /// Asks every member concurrently, each within the deadline and behind its circuit breaker.
+    pub async fn run(
+        &self,
+        resilience: &ResilienceSettings,
+        breakers: &CircuitBreakers,
+        system_message: &str,
+        prompt: &str,
+        max_output_attempts: usize,
+        deadline: Instant,
+    ) -> Result<(LlmVerdict, EnsembleReport), LlmError> {
+        let requests = self.members.iter().map(|member| async move {
+            if !breakers.allows(&member.id) {
+                return Err(format!("circuit open for {}", member.id));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let resilient = ResilientProvider {
+                inner: member.llm.as_ref(),
+                settings: resilience,
+            };
+            let request = request_verdict(&resilient, system_message, prompt, max_output_attempts, None);
+            match actix_web::rt::time::timeout(remaining, request).await {
+                Ok(Ok((verdict, _))) => {
+                    breakers.record_success(&member.id);
+                    Ok(verdict)
+                }
+                Ok(Err(VerdictError::Unparseable(output))) => {
+                    breakers.record_success(&member.id);
+                    Err(format!("unparseable output: {}", output.validation_error))
+                }
+                Ok(Err(VerdictError::Llm(e))) => {
+                    breakers.record_failure(&member.id, resilience);
+                    Err(e.to_string())
+                }
+                Err(_) => {
+                    breakers.record_failure(&member.id, resilience);
+                    Err("no verdict before the deadline".to_string())
+                }
+            }
+        });
+        let results = join_all(requests).await;
+
+        let mut votes = Vec::new();
+        let mut verdicts = Vec::new();
+        for (member, result) in self.members.iter().zip(results) {
+            match result {
+                Ok(verdict) => {
+                    votes.push(EnsembleVote {
+                        model: member.id.clone(),
+                        sample: member.sample,
+                        risk_level: Some(verdict.risk_level),
+                        confidence: Some(verdict.confidence),
+                        error: None,
+                    });
+                    verdicts.push(verdict);
+                }
+                Err(error) => {
+                    warn!("⚠️ Ensemble member {} #{} failed: {}", member.id, member.sample, error);
+                    votes.push(EnsembleVote {
+                        model: member.id.clone(),
+                        sample: member.sample,
+                        risk_level: None,
+                        confidence: None,
+                        error: Some(error),
+                    });
+                }
+            }
+        }
+
+        let errors: Vec<String> = votes.iter().filter_map(|vote| vote.error.clone()).collect();
+        let (verdict, report) = combine_verdicts(&self.settings, votes, verdicts)
+            .ok_or_else(|| LlmError::Request(format!("no ensemble member answered: {}", errors.join("; "))))?;
+        info!(
+            "🗳️ Ensemble verdict {} - {}/{} answered, agreement {:.0}%, human review: {}",
+            report.risk_level,
+            report.answered,
+            report.votes.len(),
+            report.agreement * 100.0,
+            report.needs_human_review
+        );
+        Ok((verdict, report))
+    }
+}
@@ -0,0 +1,563 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::Address;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::abi::{abi_dir, write_cached};
+use crate::llm::{LlmError, LlmProvider};
+use crate::profile::{ContractProfile, PROFILE_CACHE_KIND};
+use crate::resilience::VerdictPath;
+use crate::rules::RiskLevel;
+use crate::source::{VerifiedSource, SOURCE_CACHE_KIND};
+use crate::{AnalysisRequest, AnalysisResponse};
+
+const LEVELS: [RiskLevel; 4] = [RiskLevel::Low, RiskLevel::Medium, RiskLevel::High, RiskLevel::Critical];
+
+// The labeled corpus, `eval/corpus.json` by default
+#[derive(Debug, Clone, Deserialize)]
+pub struct Corpus {
+    pub cases: Vec<EvalCase>,
+}
+
+// One labeled call: everything the pipeline would otherwise fetch from the explorer is part of the case
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvalCase {
+    pub id: String,
+    pub chain_id: u64,
+    pub contract_address: String,
+    pub abi: Value,
+    pub call_data: String,
+    #[serde(default)]
+    pub value: Option<String>, // Native value in wei
+    #[serde(default)]
+    pub from: Option<String>,
+    pub expected_level: RiskLevel,
+    #[serde(default)]
+    pub source: Option<CaseSource>,
+    #[serde(default)]
+    pub profile: CaseProfile,
+    #[serde(default)]
+    pub mock_response: Option<Value>, // Answer of the mock model: a verdict object, or raw text
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CaseSource {
+    pub file: String, // Relative to the corpus file
+    pub contract_name: String,
+    #[serde(default)]
+    pub compiler_version: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CaseProfile {
+    pub age_days: Option<f64>,
+    pub verified: Option<bool>, // Defaults to whether the case has a source
+    pub tx_count: Option<u64>,
+}
+
+pub fn load_corpus(path: &Path) -> Result<Corpus, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path).map_err(|e| format!("cannot read corpus {}: {}", path.display(), e))?;
+    let corpus: Corpus = serde_json::from_str(&content).map_err(|e| format!("invalid corpus {}: {}", path.display(), e))?;
+    if corpus.cases.is_empty() {
+        return Err(format!("corpus {} has no cases", path.display()).into());
+    }
+    Ok(corpus)
+}
+
+/// Writes the ABI, verified source and contract profile of the case into the ABI cache directory,
+/// so the pipeline finds them locally instead of asking the explorer.
+pub fn seed_case_artifacts(case: &EvalCase, corpus_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let address: Address = case
+        .contract_address
+        .parse()
+        .map_err(|e| format!("case {}: invalid contract address: {}", case.id, e))?;
+    let dir = abi_dir();
+    fs::create_dir_all(&dir)?;
+    fs::write(
+        dir.join(format!("{:?}.json", address).to_lowercase()),
+        serde_json::to_string_pretty(&case.abi)?,
+    )?;
+
+    if let Some(source) = &case.source {
+        let path = corpus_dir.join(&source.file);
+        let code = fs::read_to_string(&path).map_err(|e| format!("case {}: cannot read {}: {}", case.id, path.display(), e))?;
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let verified = VerifiedSource {
+            address,
+            chain_id: case.chain_id.to_string(),
+            contract_name: source.contract_name.clone(),
+            compiler_version: source.compiler_version.clone(),
+            language: if file_name.ends_with(".vy") { "Vyper" } else { "Solidity" }.to_string(),
+            optimization_used: false,
+            runs: None,
+            evm_version: None,
+            settings: None,
+            files: BTreeMap::from([(file_name, code)]),
+            fetched_at: Utc::now(),
+        };
+        write_cached(&address, SOURCE_CACHE_KIND, &verified)?;
+    }
+
+    let profile = ContractProfile {
+        address,
+        chain_id: Some(case.chain_id.to_string()),
+        code_size: None,
+        deployer: None,
+        creation_tx: None,
+        creation_block: None,
+        created_at: case
+            .profile
+            .age_days
+            .map(|days| Utc::now() - Duration::seconds((days * 86_400.0) as i64)),
+        verified: case.profile.verified.or(Some(case.source.is_some())),
+        contract_name: case.source.as_ref().map(|source| source.contract_name.clone()),
+        compiler_version: None,
+        tx_count: case.profile.tx_count,
+        tx_count_capped: false,
+        deployer_first_seen: None,
+        deployer_nonce: None,
+        sources: vec!["corpus".to_string()],
+        fetched_at: Utc::now(),
+    };
+    write_cached(&address, PROFILE_CACHE_KIND, &profile)?;
+    Ok(())
+}
+
+pub fn case_request(case: &EvalCase) -> AnalysisRequest {
+    AnalysisRequest {
+        contract_address: case.contract_address.clone(),
+        call_data: case.call_data.clone(),
+        from: case.from.clone(),
+        value: case.value.clone(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelMode {
+    Mock,   // Answers with the `mock_response` of the case
+    Replay, // Answers with the recorded output for the exact prompt
+    Record, // Asks the configured model and records its output
+}
+
+impl ModelMode {
+    pub fn parse(value: &str) -> Option<ModelMode> {
+        match value {
+            "mock" => Some(ModelMode::Mock),
+            "replay" => Some(ModelMode::Replay),
+            "record" => Some(ModelMode::Record),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ModelMode::Mock => "mock",
+            ModelMode::Replay => "replay",
+            ModelMode::Record => "record",
+        }
+    }
+}
+
+// A recorded model answer, stored as `<recordings>/<key>.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub model: String,
+    pub prompt_sha256: String,
+    pub output: String,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// Recordings are keyed by model and exact prompt, so any template change needs a new recording.
+pub fn recording_key(model: &str, system_message: &str, prompt: &str) -> String {
+    hex::encode(Sha256::digest(format!("{}\n{}\n{}", model, system_message, prompt).as_bytes()))
+}
+
+/// The model the pipeline talks to during an evaluation.
+pub struct EvalModel {
+    pub mode: ModelMode,
+    pub model: String,
+    pub mock_response: Option<Value>,
+    pub recordings: PathBuf,
+    pub live: Option<Box<dyn LlmProvider>>, // The configured model, in record mode
+}
+
+impl EvalModel {
+    fn mock_output(&self) -> String {
+        match &self.mock_response {
+            Some(Value::String(text)) => text.clone(),
+            Some(verdict) => verdict.to_string(),
+            None => json!({
+                "risk_level": "Low",
+                "explanation": "Mock verdict",
+                "findings": [],
+                "dangerous_functions": [],
+                "confidence": 0.5
+            })
+            .to_string(),
+        }
+    }
+
+    async fn answer(&self, system_message: &str, prompt: &str, schema: Option<&Value>) -> Result<String, LlmError> {
+        if self.mode == ModelMode::Mock {
+            return Ok(self.mock_output());
+        }
+
+        let key = recording_key(&self.model, system_message, prompt);
+        let path = self.recordings.join(format!("{}.json", key));
+        if self.mode == ModelMode::Replay {
+            let content = fs::read_to_string(&path).map_err(|_| {
+                LlmError::Config(format!("no recording {} for this prompt, run with --llm record", path.display()))
+            })?;
+            let recording: Recording =
+                serde_json::from_str(&content).map_err(|e| LlmError::Parse(format!("{}: {}", path.display(), e)))?;
+            return Ok(recording.output);
+        }
+
+        let live = self
+            .live
+            .as_ref()
+            .ok_or_else(|| LlmError::Config("record mode needs the configured model".to_string()))?;
+        let output = match schema {
+            Some(schema) => live.complete_json(system_message, prompt, schema).await?,
+            None => live.complete(system_message, prompt).await?,
+        };
+        let recording = Recording {
+            model: self.model.clone(),
+            prompt_sha256: hex::encode(Sha256::digest(prompt.as_bytes())),
+            output: output.clone(),
+            recorded_at: Utc::now(),
+        };
+        let saved = fs::create_dir_all(&self.recordings)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string_pretty(&recording).map_err(|e| e.to_string()))
+            .and_then(|content| fs::write(&path, content).map_err(|e| e.to_string()));
+        match saved {
+            Ok(()) => info!("💾 Recorded model output {}", path.display()),
+            Err(e) => return Err(LlmError::Config(format!("cannot write {}: {}", path.display(), e))),
+        }
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for EvalModel {
+    fn name(&self) -> &str {
+        match self.mode {
+            ModelMode::Mock => "Mock",
+            ModelMode::Replay => "Replay",
+            ModelMode::Record => "Record",
+        }
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn complete(&self, system_message: &str, prompt: &str) -> Result<String, LlmError> {
+        self.answer(system_message, prompt, None).await
+    }
+
+    async fn complete_json(&self, system_message: &str, prompt: &str, schema: &Value) -> Result<String, LlmError> {
+        self.answer(system_message, prompt, Some(schema)).await
+    }
+}
+
+// Outcome of one case
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub id: String,
+    pub expected: RiskLevel,
+    pub predicted: Option<RiskLevel>, // None when the analysis failed
+    pub model_level: Option<RiskLevel>,
+    pub verdict_path: Option<VerdictPath>,
+    pub prompt_template: Option<String>,
+    pub prompt_version: Option<String>,
+    pub rule_ids: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl CaseResult {
+    pub fn from_response(case: &EvalCase, response: &AnalysisResponse) -> Self {
+        let verdict_path = response.verdict_source.as_ref().map(|source| source.path);
+        let error = match (response.status.as_str(), verdict_path) {
+            // The model is what is evaluated, a rules-only verdict means it never answered (e.g. no recording)
+            ("success", Some(VerdictPath::RulesOnly)) => Some(
+                response
+                    .explanation
+                    .as_deref()
+                    .and_then(|explanation| explanation.lines().next())
+                    .unwrap_or("No model verdict available")
+                    .replace("**", ""),
+            ),
+            ("success", _) => None,
+            _ => Some(format!(
+                "{}{}",
+                response.message.clone().unwrap_or_default(),
+                response.details.as_ref().map(|details| format!(": {}", details)).unwrap_or_default()
+            )),
+        };
+        let predicted = match error {
+            Some(_) => None,
+            None => response.risk_level.as_deref().and_then(RiskLevel::parse),
+        };
+        CaseResult {
+            id: case.id.clone(),
+            expected: case.expected_level,
+            predicted,
+            model_level: response.verdict.as_ref().map(|verdict| verdict.risk_level),
+            verdict_path,
+            prompt_template: response.prompt_version.as_ref().map(|version| version.template.clone()),
+            prompt_version: response.prompt_version.as_ref().map(|version| version.version.clone()),
+            rule_ids: response
+                .findings
+                .iter()
+                .flatten()
+                .map(|finding| finding.rule_id.clone())
+                .collect(),
+            error,
+        }
+    }
+
+    pub fn is_correct(&self) -> bool {
+        self.predicted == Some(self.expected)
+    }
+
+    /// Rated below the label, or no verdict at all: the user would not have been warned enough.
+    pub fn is_false_negative(&self) -> bool {
+        self.predicted.map(|level| level < self.expected).unwrap_or(true)
+    }
+
+    pub fn is_false_positive(&self) -> bool {
+        self.predicted.map(|level| level > self.expected).unwrap_or(false)
+    }
+}
+
+// Results of one run, written with --out and read back with --baseline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub corpus: String,
+    pub mode: String,
+    pub model: String,
+    pub created_at: DateTime<Utc>,
+    pub cases: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    pub fn correct(&self) -> usize {
+        self.cases.iter().filter(|case| case.is_correct()).count()
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        if self.cases.is_empty() {
+            return 0.0;
+        }
+        self.correct() as f64 / self.cases.len() as f64
+    }
+
+    pub fn false_negatives(&self) -> Vec<&CaseResult> {
+        self.cases.iter().filter(|case| case.is_false_negative()).collect()
+    }
+
+    /// Rows are the expected levels, columns the predicted ones plus "none" for failed analyses.
+    pub fn confusion_matrix(&self) -> [[usize; 5]; 4] {
+        let mut matrix = [[0; 5]; 4];
+        for case in &self.cases {
+            let row = LEVELS.iter().position(|level| *level == case.expected).unwrap_or(0);
+            let column = case
+                .predicted
+                .and_then(|predicted| LEVELS.iter().position(|level| *level == predicted))
+                .unwrap_or(4);
+            matrix[row][column] += 1;
+        }
+        matrix
+    }
+}
+
+fn case_label(case: &CaseResult) -> String {
+    let predicted = case.predicted.map(|level| level.to_string()).unwrap_or_else(|| "no verdict".to_string());
+    let prompt = match (&case.prompt_template, &case.prompt_version) {
+        (Some(template), Some(version)) => format!("{}/{}", template, version),
+        _ => "no prompt".to_string(),
+    };
+    let path = case
+        .verdict_path
+        .and_then(|path| serde_json::to_value(path).ok())
+        .and_then(|path| path.as_str().map(str::to_string))
+        .unwrap_or_else(|| "none".to_string());
+    let mut label = format!(
+        "{}: expected {}, got {} ({}, verdict from {})",
+        case.id, case.expected, predicted, prompt, path
+    );
+    if let Some(error) = &case.error {
+        label.push_str(&format!(" - {}", error));
+    }
+    label
+}
+
+/// Accuracy, confusion matrix and the cases rated below their label.
+pub fn format_report(report: &EvalReport) -> String {
+    let mut lines = vec![
+        format!(
+            "Corpus {} - {} case(s), model {} ({})",
+            report.corpus,
+            report.cases.len(),
+            report.model,
+            report.mode
+        ),
+        format!(
+            "Accuracy: {}/{} ({:.1}%), false negatives: {}, false positives: {}",
+            report.correct(),
+            report.cases.len(),
+            report.accuracy() * 100.0,
+            report.false_negatives().len(),
+            report.cases.iter().filter(|case| case.is_false_positive()).count()
+        ),
+        String::new(),
+        "Confusion matrix (rows: expected, columns: predicted)".to_string(),
+        format!("{:<10}{:>8}{:>8}{:>8}{:>10}{:>8}", "", "Low", "Medium", "High", "Critical", "None"),
+    ];
+    for (level, row) in LEVELS.iter().zip(report.confusion_matrix()) {
+        lines.push(format!(
+            "{:<10}{:>8}{:>8}{:>8}{:>10}{:>8}",
+            level.to_string(),
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4]
+        ));
+    }
+
+    let false_negatives = report.false_negatives();
+    lines.push(String::new());
+    if false_negatives.is_empty() {
+        lines.push("False negatives: none".to_string());
+    } else {
+        lines.push("False negatives:".to_string());
+        lines.extend(false_negatives.iter().map(|case| format!("- {}", case_label(case))));
+    }
+    lines.join("\n")
+}
+
+/// Accuracy per prompt template against a previous run, with the cases that changed outcome.
+pub fn format_prompt_deltas(report: &EvalReport, baseline: &EvalReport) -> String {
+    // Template -> (versions seen, cases)
+    fn by_template(report: &EvalReport) -> BTreeMap<String, (BTreeSet<String>, Vec<&CaseResult>)> {
+        let mut templates: BTreeMap<String, (BTreeSet<String>, Vec<&CaseResult>)> = BTreeMap::new();
+        for case in &report.cases {
+            let template = case.prompt_template.clone().unwrap_or_else(|| "none".to_string());
+            let entry = templates.entry(template).or_default();
+            entry.0.extend(case.prompt_version.clone());
+            entry.1.push(case);
+        }
+        templates
+    }
+    fn describe(versions: &BTreeSet<String>, cases: &[&CaseResult]) -> String {
+        let versions: Vec<&str> = versions.iter().map(String::as_str).collect();
+        let correct = cases.iter().filter(|case| case.is_correct()).count();
+        format!("{} {}/{}", versions.join("+"), correct, cases.len())
+    }
+
+    let current = by_template(report);
+    let previous = by_template(baseline);
+    let mut lines = vec![format!(
+        "Prompt versions against the baseline of {} - accuracy {:.1}% -> {:.1}% ({:+.1} pts)",
+        baseline.created_at.format("%Y-%m-%d %H:%M"),
+        baseline.accuracy() * 100.0,
+        report.accuracy() * 100.0,
+        (report.accuracy() - baseline.accuracy()) * 100.0
+    )];
+
+    let templates: BTreeSet<&String> = current.keys().chain(previous.keys()).collect();
+    for template in templates {
+        let line = match (previous.get(template), current.get(template)) {
+            (Some((old_versions, old_cases)), Some((new_versions, new_cases))) => {
+                let changed = |fixed: bool| -> Vec<String> {
+                    new_cases
+                        .iter()
+                        .filter(|case| {
+                            old_cases
+                                .iter()
+                                .find(|old| old.id == case.id)
+                                .map(|old| old.is_correct() != case.is_correct() && case.is_correct() == fixed)
+                                .unwrap_or(false)
+                        })
+                        .map(|case| case.id.clone())
+                        .collect()
+                };
+                let (fixed, regressed) = (changed(true), changed(false));
+                let mut line = format!(
+                    "- {}: {} -> {}",
+                    template,
+                    describe(old_versions, old_cases),
+                    describe(new_versions, new_cases)
+                );
+                if !fixed.is_empty() {
+                    line.push_str(&format!(", fixed: {}", fixed.join(", ")));
+                }
+                if !regressed.is_empty() {
+                    line.push_str(&format!(", regressed: {}", regressed.join(", ")));
+                }
+                line
+            }
+            (None, Some((versions, cases))) => format!("- {}: new, {}", template, describe(versions, cases)),
+            (Some((versions, cases)), None) => format!("- {}: no longer used, was {}", template, describe(versions, cases)),
+            (None, None) => continue,
+        };
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+// Command line of riskoracle-eval; `--prompt-config` is read by the prompt configuration store itself
+#[derive(Debug, Clone)]
+pub struct EvalOptions {
+    pub corpus: PathBuf,
+    pub mode: ModelMode,
+    pub recordings: PathBuf,
+    pub out: Option<PathBuf>,
+    pub baseline: Option<PathBuf>,
+}
+
+impl EvalOptions {
+    pub fn from_args() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut options = EvalOptions {
+            corpus: PathBuf::from("eval/corpus.json"),
+            mode: ModelMode::Mock,
+            recordings: PathBuf::from("eval/recordings"),
+            out: None,
+            baseline: None,
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg.clone(), None),
+            };
+            let mut value = || inline.clone().or_else(|| args.next()).ok_or_else(|| format!("{} needs a value", name));
+            match name.as_str() {
+                "--corpus" => options.corpus = PathBuf::from(value()?),
+                "--llm" => {
+                    let mode = value()?;
+                    options.mode = ModelMode::parse(&mode).ok_or_else(|| format!("--llm must be mock, replay or record, not {}", mode))?;
+                }
+                "--recordings" => options.recordings = PathBuf::from(value()?),
+                "--out" => options.out = Some(PathBuf::from(value()?)),
+                "--baseline" => options.baseline = Some(PathBuf::from(value()?)),
+                "--prompt-config" => {
+                    value()?;
+                }
+                _ => return Err(format!("unknown argument {}", arg).into()),
+            }
+        }
+        Ok(options)
+    }
+}
@@ -1,18 +1,49 @@
-use actix_web::{web, HttpResponse, Responder};
-use ethers::types::Address;
+use actix_web::http::header::{AUTHORIZATION, CACHE_CONTROL};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use ethabi::StateMutability;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{Address, H256, U256};
 use log::{error, info, warn};
-use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
-    Client,
-};
 use serde_json::{json, Value};
 use std::env;
-use url::Url;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 
 use crate::abi::get_or_fetch_abi;
-use crate::config::load_prompt_config;
-use crate::decode::decode_function_call;
-use crate::{AnalysisRequest, AnalysisResponse, ChainlinkAuditRequest, ChainlinkAuditResponse, DecodeRequest, DecodeResponse};
+use crate::analysis_cache::{cache_key, CachedVerdict};
+use crate::abi_diff::{diff_contracts, diff_findings, format_diff_for_prompt};
+use crate::contract_risk::{cached_risk_profile, format_risk_profile_for_prompt, get_or_build_risk_profile};
+use crate::decode::{collect_addresses, decode_function_call, find_function};
+use crate::ensemble::{build_ensemble, Ensemble, EnsembleReport};
+use crate::bytecode::{analyze_bytecode, bytecode_addresses, bytecode_findings, format_bytecode_for_prompt};
+use crate::honeypot::{check_honeypot, format_honeypot_for_prompt, honeypot_candidate, honeypot_findings};
+use crate::llm::{build_provider, LlmError, LlmProvider};
+use crate::injection::{abi_texts, argument_texts, injection_findings, source_texts, UntrustedText};
+use crate::hidden_functions::{
+    abi_consistency_findings, abi_functions, check_abi_consistency, format_abi_consistency_for_prompt,
+};
+use crate::profile::{format_profile_for_prompt, get_or_fetch_profile, profile_findings};
+use crate::prompt_template::{chain_label, function_category, render_prompt, typed_arguments};
+use crate::rpc::{get_code, rpc_provider};
+use crate::simulation::{
+    format_simulation_for_prompt, simulate_transaction, simulation_addresses, simulation_findings,
+    SimulationReport, DEFAULT_SIMULATION_SENDER,
+};
+use crate::ownership::{analyze_ownership, format_ownership_for_prompt, ownership_findings, upgrade_call};
+use crate::proxy::{read_address_slot, EIP1967_IMPLEMENTATION_SLOT};
+use crate::poisoning::{check_checksum, detect_lookalikes, KnownAddress};
+use crate::rules::{combine_risk_level, format_findings_for_prompt, risk_score, Finding, Severity};
+use crate::source::{extract_function_excerpts, format_source_for_prompt, get_or_fetch_source};
+use crate::source_scan::scan_source;
+use crate::sse::{event_stream, AnalysisEvent};
+use crate::token_metadata::{detect_token_amounts, format_units, formatted_arguments};
+use crate::wallet_exposure::{wallet_exposure, wallet_exposure_findings};
+use crate::resilience::{provider_id, ResilientProvider, VerdictPath, VerdictSource};
+use crate::verdict::{request_verdict, rules_only_verdict, verdict_schema, LlmVerdict, VerdictError, VerdictProgress};
+use crate::valuation::{format_value_for_prompt, scale_findings_by_value, value_at_risk, value_findings, ValueAtRisk};
+use crate::{AbiDiffRequest, AbiDiffResponse, AnalysisRequest, AppState, AnalysisResponse, CacheInvalidationResponse, ResilienceSettings, TransactionConfirmationRequest, TransactionConfirmationResponse, ChainlinkAuditRequest, ChainlinkAuditResponse, ContractRiskProfileRequest, ContractRiskProfileResponse, DecodeRequest, DecodeResponse};
 
 pub async fn decode_handler(req: web::Json<DecodeRequest>) -> impl Responder {
     info!(
@@ -32,6 +63,8 @@ pub async fn decode_handler(req: web::Json<DecodeRequest>) -> impl Responder {
                 status: "error".to_string(),
                 function_name: None,
                 arguments: None,
+                formatted_arguments: None,
+                token_amounts: None,
                 message: Some(format!("Invalid contract address: {}", e)),
                 details: None,
                 abi: None,
@@ -47,6 +80,8 @@ pub async fn decode_handler(req: web::Json<DecodeRequest>) -> impl Responder {
                 status: "error".to_string(),
                 function_name: None,
                 arguments: None,
+                formatted_arguments: None,
+                token_amounts: None,
                 message: Some("Failed to fetch or load the ABI".to_string()),
                 details: Some(e.to_string()),
                 abi: None,
@@ -55,10 +90,20 @@ pub async fn decode_handler(req: web::Json<DecodeRequest>) -> impl Responder {
     };
 
     let mut last_error = "No ABI found".to_string();
+    let call_data_bytes =
+        hex::decode(req.call_data.strip_prefix("0x").unwrap_or(&req.call_data)).unwrap_or_default();
+    let provider = rpc_provider();
 
     for (contract, abi) in contracts_and_abis {
         match decode_function_call(&contract, &req.call_data) {
             Ok((name, args)) => {
+                // Token metadata needs a node; without one the raw arguments are returned as before
+                let token_amounts = match (&provider, find_function(&contract, &call_data_bytes)) {
+                    (Some(provider), Some(function)) => {
+                        detect_token_amounts(provider, function, &args, &contract_address).await
+                    }
+                    _ => Vec::new(),
+                };
                 let args_str: Vec<String> = args.into_iter().map(|arg| format!("{:?}", arg)).collect();
                 info!(
                     "✅ Decode successful - Function: {}, Arguments: {:?}",
@@ -67,6 +112,8 @@ pub async fn decode_handler(req: web::Json<DecodeRequest>) -> impl Responder {
                 return HttpResponse::Ok().json(DecodeResponse {
                     status: "success".to_string(),
                     function_name: Some(name),
+                    formatted_arguments: Some(formatted_arguments(&args_str, &token_amounts)),
+                    token_amounts: Some(token_amounts),
                     arguments: Some(args_str),
                     message: None,
                     details: None,
@@ -86,37 +133,109 @@ pub async fn decode_handler(req: web::Json<DecodeRequest>) -> impl Responder {
         status: "error".to_string(),
         function_name: None,
         arguments: None,
+        formatted_arguments: None,
+        token_amounts: None,
         message: Some("Failed to decode call data".to_string()),
         details: Some(format!("Last error: {}", last_error)),
         abi: None,
     })
 }
 
-pub async fn analysis_handler(req: web::Json<AnalysisRequest>) -> impl Responder {
-    info!(
-        "📥 Analysis request received - Contract: {}",
-        req.contract_address
-    );
+// Everything gathered before the model is asked; `response` already carries the analysis artifacts
+struct PreparedAnalysis {
+    llm: Box<dyn LlmProvider>,
+    system_message: String,
+    prompt: String,
+    max_output_attempts: usize,
+    fallback: Option<Box<dyn LlmProvider>>,
+    resilience: ResilienceSettings,
+    ensemble: Option<Ensemble>, // Set when ensemble mode is enabled
+    cache_key: String,
+    contract_address: Address,
+    chain_id: Option<u64>,
+    model: String, // "<provider>/<model>", or the ensemble description
+    wallet_findings: Vec<Finding>, // Left out of the shared prompt, added to the explanation instead
+    response: AnalysisResponse,
+}
 
-    let deepseek_api_key = match env::var("DEEPSEEK_API_KEY") {
-        Ok(key) => key,
-        Err(_) => {
+// What obtain_verdict settled on
+struct VerdictOutcome {
+    result: Result<(LlmVerdict, usize), VerdictError>,
+    source: VerdictSource,
+    ensemble: Option<EnsembleReport>,
+}
+
+// Stands in for the wallet sections of custom templates, the cached verdict is shared across senders
+const WALLET_CONTEXT_NOTE: &str = "Not included (the verdict is shared by every sender of this call)";
+
+// Simulation of the call from `sender`, or None when the node could not run it
+async fn simulate_call(
+    provider: &Provider<Http>,
+    sender: Address,
+    contract_address: Address,
+    call_data: &[u8],
+    value: U256,
+) -> Option<SimulationReport> {
+    match simulate_transaction(provider, sender, contract_address, call_data, value).await {
+        Ok(report) => Some(report),
+        Err(e) => {
+            warn!("⚠️ Simulation from {:?} failed for {}: {}", sender, contract_address, e);
+            None
+        }
+    }
+}
+
+/// Runs the decode and every local check, then builds the prompt. Progress goes to `events` when
+/// the request came in on /analysis/stream. `model` replaces the configured models, including the
+/// fallback and the ensemble (used by the offline evaluation).
+async fn prepare_analysis(
+    state: &AppState,
+    req: &AnalysisRequest,
+    model: Option<Box<dyn LlmProvider>>,
+    events: Option<&UnboundedSender<AnalysisEvent>>,
+) -> Result<PreparedAnalysis, (StatusCode, AnalysisResponse)> {
+    // The configuration active when the request arrived, even if it is reloaded meanwhile
+    let prompt_config = state.prompt_config.current();
+    let model_override = model.is_some();
+
+    let llm = match model {
+        Some(llm) => Ok(llm),
+        None => build_provider(&prompt_config.model_settings),
+    };
+    let llm = match llm {
+        Ok(llm) => llm,
+        Err(e) => {
             error!(
-                "❌ DEEPSEEK_API_KEY not configured for contract analysis: {}",
-                req.contract_address
+                "❌ LLM provider not configured for contract analysis: {} - {}",
+                req.contract_address, e
             );
-            return HttpResponse::InternalServerError().json(AnalysisResponse {
-                status: "error".to_string(),
-                function_name: None,
-                arguments: None,
-                risk_level: None,
-                explanation: None,
-                message: Some("DEEPSEEK_API_KEY not configured".to_string()),
-                details: Some("Make sure to set the DEEPSEEK_API_KEY environment variable in your .env file".to_string()),
-            });
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                AnalysisResponse {
+                    status: "error".to_string(),
+                    message: Some("LLM provider not configured".to_string()),
+                    details: Some(format!(
+                        "{}. Check model_settings in the prompt configuration and the API key in your .env file",
+                        e
+                    )),
+                    ..Default::default()
+                },
+            ));
         }
     };
 
+    // Secondary provider used while the primary one is failing
+    let fallback = match prompt_config.fallback_model_settings.as_ref().filter(|_| !model_override) {
+        Some(settings) => match build_provider(settings) {
+            Ok(llm) => Some(llm),
+            Err(e) => {
+                warn!("⚠️ Fallback LLM provider not configured: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
     // Parse contract address
     let contract_address = match req.contract_address.parse::<Address>() {
         Ok(addr) => addr,
@@ -125,47 +244,120 @@ pub async fn analysis_handler(req: web::Json<AnalysisRequest>) -> impl Responder
                 "❌ Invalid contract address in analysis: {} - Error: {}",
                 req.contract_address, e
             );
-            return HttpResponse::BadRequest().json(AnalysisResponse {
-                status: "error".to_string(),
-                function_name: None,
-                arguments: None,
-                risk_level: None,
-                explanation: None,
-                message: Some(format!("Invalid contract address: {}", e)),
-                details: None,
-            });
+            return Err((
+                StatusCode::BAD_REQUEST,
+                AnalysisResponse {
+                    status: "error".to_string(),
+                    function_name: None,
+                    arguments: None,
+                    risk_level: None,
+                    explanation: None,
+                    message: Some(format!("Invalid contract address: {}", e)),
+                    details: None,
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+
+    // Parse the optional sender address
+    let sender = match req.from.as_deref().map(|from| from.parse::<Address>()) {
+        None => None,
+        Some(Ok(addr)) => Some(addr),
+        Some(Err(e)) => {
+            warn!("❌ Invalid sender address in analysis: {:?} - Error: {}", req.from, e);
+            return Err((
+                StatusCode::BAD_REQUEST,
+                AnalysisResponse {
+                    status: "error".to_string(),
+                    message: Some(format!("Invalid sender address: {}", e)),
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+
+    // Parse the optional native value (wei, decimal or 0x-prefixed hex)
+    let value = match parse_wei(req.value.as_deref()) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("❌ Invalid value in analysis: {:?} - Error: {}", req.value, e);
+            return Err((
+                StatusCode::BAD_REQUEST,
+                AnalysisResponse {
+                    status: "error".to_string(),
+                    message: Some(format!("Invalid value: {}", e)),
+                    ..Default::default()
+                },
+            ));
         }
     };
 
+    // Optional JSON-RPC node for bytecode analysis and simulation
+    let provider = rpc_provider();
+
     // Get or fetch ABI (returns a list of potential contracts)
     let contracts_and_abis = match get_or_fetch_abi(&contract_address).await {
         Ok(list) => list,
+        // Unverified contracts can still be analyzed from their bytecode
+        Err(e) if provider.is_some() => {
+            warn!(
+                "⚠️ No ABI for {} ({}), continuing with bytecode analysis only",
+                contract_address, e
+            );
+            Vec::new()
+        }
         Err(e) => {
             error!(
                 "❌ Failed to fetch ABI for analysis of {}: {}",
                 contract_address, e
             );
-            return HttpResponse::InternalServerError().json(AnalysisResponse {
-                status: "error".to_string(),
-                function_name: None,
-                arguments: None,
-                risk_level: None,
-                explanation: None,
-                message: Some("Failed to fetch or load the ABI".to_string()),
-                details: Some(e.to_string()),
-            });
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                AnalysisResponse {
+                    status: "error".to_string(),
+                    function_name: None,
+                    arguments: None,
+                    risk_level: None,
+                    explanation: None,
+                    message: Some("Failed to fetch or load the ABI".to_string()),
+                    details: Some(e.to_string()),
+                    ..Default::default()
+                },
+            ));
         }
     };
 
     // Decode function call - Loop until one works
     let mut function_name: String = "".to_string();
     let mut arguments: Vec<String> = Vec::new();
+    let mut token_amounts = Vec::new();
+    let mut decoded_call = None;
     let mut decode_success = false;
     let mut last_decode_error = String::new();
+    // Every address involved in the call, starting with the target itself
+    let mut addresses = vec![(contract_address, "target".to_string())];
 
-    for (contract, _) in contracts_and_abis {
-        match decode_function_call(&contract, &req.call_data) {
+    // Functions declared by the ABI that decoded the call (or the first ABI), for the code cross-check
+    let mut declared_functions = contracts_and_abis
+        .first()
+        .map(|(contract, _)| abi_functions(contract))
+        .unwrap_or_default();
+
+    let call_data_bytes =
+        hex::decode(req.call_data.strip_prefix("0x").unwrap_or(&req.call_data)).unwrap_or_default();
+
+    for (contract, _) in &contracts_and_abis {
+        match decode_function_call(contract, &req.call_data) {
             Ok((name, args)) => {
+                addresses.extend(collect_addresses(contract, &args));
+                declared_functions = abi_functions(contract);
+                if let Some(function) = find_function(contract, &call_data_bytes) {
+                    if let Some(provider) = &provider {
+                        token_amounts = detect_token_amounts(provider, function, &args, &contract_address).await;
+                    }
+                    decoded_call = Some((function.clone(), args.clone()));
+                }
                 let args_str: Vec<String> = args.into_iter().map(|arg| format!("{:?}", arg)).collect();
                 function_name = name;
                 arguments = args_str;
@@ -177,180 +369,1173 @@ pub async fn analysis_handler(req: web::Json<AnalysisRequest>) -> impl Responder
             }
         }
     }
+    let formatted_arguments = formatted_arguments(&arguments, &token_amounts);
 
-    if !decode_success {
-        error!("❌ Failed to decode call data in analysis: {}", last_decode_error);
-        return HttpResponse::InternalServerError().json(AnalysisResponse {
-            status: "error".to_string(),
-            function_name: None,
-            arguments: None,
-            risk_level: None,
-            explanation: None,
-            message: Some("Failed to decode call data".to_string()),
-            details: Some(last_decode_error),
-        });
+    let called_selector = call_data_bytes
+        .get(..4)
+        .map(|selector| format!("0x{}", hex::encode(selector)));
+
+    if !decode_success && provider.is_some() && called_selector.is_some() {
+        warn!(
+            "⚠️ Could not decode call data ({}), analyzing selector {} from bytecode",
+            last_decode_error,
+            called_selector.as_deref().unwrap_or_default()
+        );
+        function_name = format!(
+            "unknown function {}",
+            called_selector.as_deref().unwrap_or_default()
+        );
+        decode_success = true;
     }
 
-    // Load prompt configuration
-    let prompt_config = match load_prompt_config() {
-        Ok(config) => config,
-        Err(e) => {
-            error!("❌ Failed to load prompt configuration: {}", e);
-            return HttpResponse::InternalServerError().json(AnalysisResponse {
+    if !decode_success {
+        error!("❌ Failed to decode call data in analysis: {}", last_decode_error);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            AnalysisResponse {
                 status: "error".to_string(),
-                function_name: Some(function_name),
-                arguments: Some(arguments),
+                function_name: None,
+                arguments: None,
                 risk_level: None,
                 explanation: None,
-                message: Some("Failed to load prompt configuration".to_string()),
-                details: Some(e.to_string()),
+                message: Some("Failed to decode call data".to_string()),
+                details: Some(last_decode_error),
+                ..Default::default()
+            },
+        ));
+    }
+
+    if let Some(events) = events {
+        let _ = events.send(AnalysisEvent::Decode {
+            function_name: function_name.clone(),
+            arguments: arguments.clone(),
+            formatted_arguments: formatted_arguments.clone(),
+            token_amounts: token_amounts.clone(),
+        });
+    }
+
+    let mut findings = state.reputation.check_addresses(&addresses);
+    // Findings about the sender's own wallet; the prompt is shared by every sender of the same call
+    let mut wallet_findings: Vec<Finding> = Vec::new();
+
+    // Address poisoning: compare against our labeled address book and the sender's counterparties
+    findings.extend(check_checksum(&req.contract_address, "target"));
+    if let Some(from) = &req.from {
+        wallet_findings.extend(check_checksum(from, "sender"));
+    }
+    let known_addresses: Vec<KnownAddress> = state
+        .reputation
+        .snapshot()
+        .values()
+        .filter(|entry| entry.category.is_trusted())
+        .map(|entry| KnownAddress {
+            address: entry.address,
+            label: entry.label.clone(),
+        })
+        .collect();
+    let lookalikes = detect_lookalikes(&addresses, &known_addresses);
+    if let Some(sender) = sender {
+        // The wallet history may be read from disk, off the async workers
+        let history = state.history.clone();
+        let counterparties = web::block(move || history.counterparties(&sender))
+            .await
+            .unwrap_or_else(|e| {
+                error!("❌ Failed to read the address history of {:?}: {}", sender, e);
+                Vec::new()
             });
+        let counterparties: Vec<KnownAddress> = counterparties
+            .into_iter()
+            .map(|address| KnownAddress {
+                address,
+                label: "previous counterparty of the sender".to_string(),
+            })
+            .collect();
+        let flagged: Vec<Option<String>> = lookalikes.iter().map(|f| f.location.clone()).collect();
+        wallet_findings.extend(
+            detect_lookalikes(&addresses, &counterparties)
+                .into_iter()
+                .filter(|f| !flagged.contains(&f.location)),
+        );
+    }
+
+    // Only stage counterparties that did not raise a reputation or lookalike finding,
+    // they reach the history once the transaction is confirmed on chain
+    if let Some(sender) = sender {
+        let suspicious: Vec<&str> = findings
+            .iter()
+            .chain(lookalikes.iter())
+            .chain(wallet_findings.iter())
+            .filter(|f| f.severity > Severity::Info)
+            .filter_map(|f| f.location.as_deref())
+            .collect();
+        let clean: Vec<Address> = addresses
+            .iter()
+            .filter(|(_, location)| !suspicious.contains(&location.as_str()))
+            .map(|(address, _)| *address)
+            .collect();
+        state.history.stage(&sender, &contract_address, &call_data_bytes, &clean);
+    }
+    findings.extend(lookalikes);
+
+    if !findings.is_empty() {
+        info!("🚩 {} address finding(s) for {}", findings.len(), contract_address);
+    }
+
+    // Static analysis of the deployed runtime code
+    let code = match &provider {
+        Some(provider) => match get_code(provider, &contract_address).await {
+            Ok(code) => Some(code),
+            Err(e) => {
+                warn!("⚠️ Failed to fetch code for {}: {}", contract_address, e);
+                None
+            }
+        },
+        None => None,
+    };
+    let bytecode = code.as_ref().map(|code| analyze_bytecode(code));
+
+    if let Some(report) = &bytecode {
+        let bytecode_rule_findings = bytecode_findings(report, called_selector.as_deref());
+        info!(
+            "🔬 Bytecode analysis - Size: {} bytes, Entry points: {}, Findings: {}",
+            report.code_size,
+            report.entry_points.len(),
+            bytecode_rule_findings.len()
+        );
+        findings.extend(bytecode_rule_findings);
+        findings.extend(state.reputation.check_addresses(&bytecode_addresses(report)));
+    }
+
+    // Hidden functions: selectors in the deployed code (or behind a proxy) versus the published ABI
+    let abi_consistency = match (&provider, &code, &bytecode) {
+        (Some(provider), Some(code), Some(report)) if report.code_size > 0 && !declared_functions.is_empty() => {
+            match check_abi_consistency(provider, &contract_address, code, report, &declared_functions).await {
+                Ok(consistency) => Some(consistency),
+                Err(e) => {
+                    warn!("⚠️ ABI cross-check failed for {}: {}", contract_address, e);
+                    None
+                }
+            }
         }
+        _ => None,
     };
 
-    let api_url = match Url::parse("https://api.deepseek.com/chat/completions") {
-        Ok(url) => url,
+    if let Some(report) = &abi_consistency {
+        findings.extend(abi_consistency_findings(report, called_selector.as_deref()));
+    }
+
+    // A cached whole-contract profile (from /contract-profile) saves the ownership lookups
+    let risk_profile = cached_risk_profile(&contract_address);
+
+    // Who owns the target and who can upgrade it
+    let ownership = match (&provider, &risk_profile) {
+        (_, Some(profile)) if profile.ownership.is_some() => {
+            info!("📖 Reusing ownership from the cached contract risk profile");
+            profile.ownership.clone()
+        }
+        (Some(provider), _) => {
+            let proxy = abi_consistency.as_ref().and_then(|report| report.proxy.as_ref());
+            match analyze_ownership(provider, &contract_address, proxy).await {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    warn!("⚠️ Ownership analysis failed for {}: {}", contract_address, e);
+                    None
+                }
+            }
+        }
+        (None, _) => None,
+    };
+
+    if let Some(report) = &ownership {
+        findings.extend(ownership_findings(report));
+    }
+
+    // For upgrade calls, compare the current implementation with the one being installed
+    let upgrade_diff = match (&provider, upgrade_call(&call_data_bytes, &contract_address)) {
+        (Some(provider), Some((proxy, new_implementation))) => {
+            match read_address_slot(provider, &proxy, EIP1967_IMPLEMENTATION_SLOT).await {
+                Ok(Some(current)) => match diff_contracts(&current, &new_implementation).await {
+                    Ok(diff) => Some(diff),
+                    Err(e) => {
+                        warn!("⚠️ Could not diff {:?} against {:?}: {}", current, new_implementation, e);
+                        None
+                    }
+                },
+                Ok(None) => None,
+                Err(e) => {
+                    warn!("⚠️ Could not read the implementation of {:?}: {}", proxy, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    if let Some(diff) = &upgrade_diff {
+        findings.extend(diff_findings(diff));
+    }
+
+    // Contract metadata: age, deployer, verification and activity
+    let contract_profile = match get_or_fetch_profile(&contract_address, provider.as_ref()).await {
+        Ok(profile) => Some(profile),
         Err(e) => {
-            error!("❌ Failed to build DeepSeek API URL: {}", e);
-            return HttpResponse::InternalServerError().json(AnalysisResponse {
-                status: "error".to_string(),
-                function_name: Some(function_name),
-                arguments: Some(arguments),
-                risk_level: None,
-                explanation: None,
-                message: Some("Internal error building API URL".to_string()),
-                details: Some(e.to_string()),
-            });
+            warn!("⚠️ Contract profile unavailable for {}: {}", contract_address, e);
+            None
+        }
+    };
+
+    if let Some(profile) = &contract_profile {
+        findings.extend(profile_findings(profile));
+    }
+
+    // Verified source of the contract that actually runs the code (the implementation behind a proxy)
+    let source_address = abi_consistency
+        .as_ref()
+        .and_then(|report| report.proxy.as_ref())
+        .map(|proxy| proxy.implementation)
+        .unwrap_or(contract_address);
+    let verified_source = match get_or_fetch_source(&source_address).await {
+        Ok(source) => source,
+        Err(e) => {
+            warn!("⚠️ Verified source unavailable for {:?}: {}", source_address, e);
+            None
+        }
+    };
+
+    if let Some(source) = &verified_source {
+        findings.extend(scan_source(source, Some(function_name.as_str())));
+    }
+
+    // Simulate the call against the configured node to get the real asset changes
+    let default_sender: Address = DEFAULT_SIMULATION_SENDER
+        .parse()
+        .expect("valid default simulation sender");
+    let simulation = match &provider {
+        Some(provider) => {
+            simulate_call(provider, sender.unwrap_or(default_sender), contract_address, &call_data_bytes, value).await
+        }
+        None => {
+            info!("ℹ️ RPC_URL not configured, skipping simulation");
+            None
+        }
+    };
+    // The prompt sees the call from the default sender, so no wallet's holdings reach a shared verdict
+    let shared_simulation = match (&provider, sender) {
+        (Some(provider), Some(sender)) if sender != default_sender => {
+            simulate_call(provider, default_sender, contract_address, &call_data_bytes, value).await
+        }
+        _ => simulation.clone(),
+    };
+
+    // Buy/sell probes for the ERC-20 the call approves, transfers or swaps into
+    let honeypot = match (&provider, honeypot_candidate(&function_name, &contract_address, simulation.as_ref())) {
+        (Some(provider), Some(token)) => {
+            // The source scan only covers the target, so its fee findings apply when the token is the target
+            let owner_adjustable_fees =
+                token == contract_address && findings.iter().any(|f| f.rule_id == "source.fee_control");
+            match check_honeypot(provider, token, owner_adjustable_fees).await {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    warn!("⚠️ Honeypot check skipped for {:?}: {}", token, e);
+                    None
+                }
+            }
         }
+        _ => None,
     };
 
-    let client = Client::new();
+    if let Some(report) = &honeypot {
+        findings.extend(honeypot_findings(report));
+    }
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        AUTHORIZATION,
-        HeaderValue::from_str(&format!("Bearer {}", deepseek_api_key)).unwrap(),
+    // Names, strings and comments the contract controls, checked for text aimed at the model
+    let mut untrusted_texts = vec![UntrustedText::new("called function name", &function_name)];
+    for (contract, _) in &contracts_and_abis {
+        untrusted_texts.extend(abi_texts(contract));
+    }
+    // Both implementations of an upgrade reach the prompt through the diff; their ABIs are cached by now
+    if let Some(diff) = &upgrade_diff {
+        for (address, side) in [(diff.old_address, "current implementation"), (diff.new_address, "new implementation")] {
+            for (contract, _) in get_or_fetch_abi(&address).await.unwrap_or_default() {
+                untrusted_texts.extend(abi_texts(&contract).into_iter().map(|mut text| {
+                    text.location = format!("{} {}", side, text.location);
+                    text
+                }));
+            }
+        }
+    }
+    if let Some((_, args)) = &decoded_call {
+        untrusted_texts.extend(argument_texts(args));
+    }
+    untrusted_texts.extend(token_amounts.iter().map(|amount| UntrustedText::new("token symbol", &amount.symbol)));
+    if let Some(name) = contract_profile.as_ref().and_then(|profile| profile.contract_name.as_deref()) {
+        untrusted_texts.push(UntrustedText::new("explorer contract name", name));
+    }
+    if let Some(source) = &verified_source {
+        untrusted_texts.extend(source_texts(source));
+    }
+    findings.extend(injection_findings(&untrusted_texts));
+
+    // The prompt gets the findings that hold for every sender, the response also the wallet's own
+    let mut prompt_findings = findings.clone();
+    if let Some(report) = &shared_simulation {
+        prompt_findings.extend(simulation_findings(report, &contract_address));
+        prompt_findings.extend(state.reputation.check_addresses(&simulation_addresses(report)));
+    }
+
+    if let Some(report) = &simulation {
+        let simulation_rule_findings = simulation_findings(report, &contract_address);
+        info!(
+            "🧪 Simulation finished - Success: {}, Asset changes: {}, Findings: {}",
+            report.success,
+            report.asset_changes.len(),
+            simulation_rule_findings.len()
+        );
+        findings.extend(simulation_rule_findings);
+        findings.extend(state.reputation.check_addresses(&simulation_addresses(report)));
+    }
+
+    // What the sender holds right now of every token the call approves or sends
+    let wallet_exposure = match (&provider, sender) {
+        (Some(provider), Some(sender)) => {
+            let decoded = decoded_call.as_ref().map(|(function, args)| (function, args.as_slice()));
+            match wallet_exposure(provider, sender, &function_name, decoded, &token_amounts, simulation.as_ref()).await {
+                Ok(report) => Some(report),
+                Err(e) => {
+                    warn!("⚠️ Could not read the balances and allowances of {:?}: {}", sender, e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    if let Some(report) = &wallet_exposure {
+        wallet_findings.extend(wallet_exposure_findings(report));
+    }
+
+    // USD value of what the call transfers or approves; scales the value-bearing findings
+    let value_at_risk = value_at_risk(
+        provider.as_ref(),
+        wallet_exposure.as_ref(),
+        value,
+        &function_name,
+        &token_amounts,
+        simulation.as_ref(),
+    )
+    .await;
+    scale_findings_by_value(&mut findings, &value_at_risk);
+    scale_findings_by_value(&mut wallet_findings, &value_at_risk);
+    wallet_findings.extend(value_findings(&value_at_risk));
+    findings.extend(wallet_findings.iter().cloned());
+
+    if let Some(events) = events {
+        let _ = events.send(AnalysisEvent::Findings {
+            findings: findings.clone(),
+            risk_score: risk_score(&findings),
+        });
+    }
+
+    // Only the definitions relevant to the decoded function are sent, within the token budget
+    let source_excerpts = match &verified_source {
+        Some(source) if !function_name.starts_with("unknown function") => {
+            extract_function_excerpts(source, &function_name, prompt_config.source_token_budget)
+        }
+        _ => Vec::new(),
+    };
+
+    // Chain of the configured node, or the explorer chain the contract profile was found on
+    let chain_id = match &provider {
+        Some(provider) => provider.get_chainid().await.ok().map(|id| id.as_u64()),
+        None => None,
+    }
+    .or_else(|| {
+        contract_profile
+            .as_ref()
+            .and_then(|profile| profile.chain_id.as_deref())
+            .and_then(|id| id.parse().ok())
+    });
+
+    let state_mutability = match (&decoded_call, value.is_zero()) {
+        (Some((function, _)), _) => function.state_mutability,
+        (None, false) => StateMutability::Payable,
+        (None, true) => StateMutability::NonPayable,
+    };
+    let category = function_category(
+        &function_name,
+        state_mutability,
+        upgrade_call(&call_data_bytes, &contract_address).is_some(),
     );
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-
-    // Construct the prompt for the LLM using the config
-    let prompt = prompt_config
-        .user_prompt_template
-        .replace("{contract_address}", &req.contract_address)
-        .replace("{function_name}", &function_name)
-        .replace("{arguments}", &format!("{:?}", arguments));
-
-    let body = json!({
-        "model": prompt_config.model_settings.model,
-        "messages": [
-            {"role": "system", "content": prompt_config.system_message},
-            {"role": "user", "content": prompt}
-        ],
-        "stream": prompt_config.model_settings.stream
+
+    // Variables available to the prompt templates: typed values plus the prompt-ready sections
+    let context = json!({
+        "contract_address": req.contract_address,
+        "chain": {
+            "id": chain_id,
+            "name": chain_id.map(chain_label),
+        },
+        "function": {
+            "name": function_name,
+            "category": category.label(),
+            "selector": called_selector,
+        },
+        "arguments": typed_arguments(
+            decoded_call.as_ref().map(|(function, args)| (function, args.as_slice())),
+            &arguments,
+            &formatted_arguments,
+        ),
+        "token_amounts": token_amounts,
+        "value": {
+            "wei": value.to_string(),
+            "eth": format_units(value, 18),
+        },
+        "findings": prompt_findings,
+        "risk_score": risk_score(&prompt_findings),
+        "contract_profile": contract_profile,
+        "source_excerpts": source_excerpts,
+        "simulation": shared_simulation,
+        "honeypot": honeypot,
+        "ownership": ownership,
+        "upgrade_diff": upgrade_diff,
+        "text": {
+            "findings": format_findings_for_prompt(&prompt_findings),
+            "contract_profile": format_profile_for_prompt(contract_profile.as_ref()),
+            "contract_risk": format_risk_profile_for_prompt(risk_profile.as_ref(), &function_name),
+            "ownership": format_ownership_for_prompt(ownership.as_ref()),
+            "upgrade_diff": format_diff_for_prompt(upgrade_diff.as_ref()),
+            "simulation": format_simulation_for_prompt(shared_simulation.as_ref()),
+            "bytecode": format_bytecode_for_prompt(bytecode.as_ref()),
+            "abi_consistency": format_abi_consistency_for_prompt(abi_consistency.as_ref()),
+            "source": format_source_for_prompt(verified_source.as_ref(), &source_excerpts),
+            "honeypot": format_honeypot_for_prompt(honeypot.as_ref()),
+            // Kept for custom templates; the wallet context is added after the cached verdict
+            "value_at_risk": WALLET_CONTEXT_NOTE,
+            "wallet_exposure": WALLET_CONTEXT_NOTE,
+        },
+        "response_schema": verdict_schema().to_string(),
     });
 
+    let (prompt, prompt_version) = match render_prompt(&prompt_config.prompt_templates, category, &context) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            error!("❌ Failed to render the prompt template: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                AnalysisResponse {
+                    status: "error".to_string(),
+                    function_name: Some(function_name),
+                    arguments: Some(arguments),
+                    message: Some("Failed to render the prompt template".to_string()),
+                    details: Some(e.to_string()),
+                    ..Default::default()
+                },
+            ));
+        }
+    };
+
     info!(
-        "📤 Sending request to DeepSeek API - Function: {}",
+        "📤 Sending analysis prompt to {} - Function: {}",
+        llm.name(),
         function_name
     );
 
-    let response = client
-        .post(api_url)
-        .headers(headers)
-        .json(&body)
-        .send()
-        .await;
+    let ensemble = match model_override {
+        true => None,
+        false => build_ensemble(&prompt_config.ensemble, &prompt_config.model_settings),
+    };
+    // The ensemble is cached under its own description, so its verdicts never mix with single-model ones
+    let (cache_provider, cache_model) = match &ensemble {
+        Some(ensemble) => ("ensemble".to_string(), ensemble.id()),
+        None => (llm.name().to_string(), llm.model().to_string()),
+    };
+    let cache_key = cache_key(
+        chain_id,
+        &contract_address,
+        &call_data_bytes,
+        value,
+        &prompt_version,
+        &cache_provider,
+        &cache_model,
+    );
+    let model = match ensemble {
+        Some(_) => cache_model,
+        None => format!("{}/{}", cache_provider, cache_model),
+    };
 
-    match response {
-        Ok(res) => {
-            let status = res.status();
-            info!("📥 DeepSeek response - Status: {}", status);
-            let full_response: Result<Value, _> = res.json().await;
-
-            match full_response {
-                Ok(json_response) => {
-                    let content = json_response["choices"][0]["message"]["content"]
-                        .as_str()
-                        .unwrap_or("");
-
-                    // Log full content for debugging
-                    info!("📄 Full LLM response content: {}", content);
-
-                    let risk_level = content
-                        .lines()
-                        .find(|line| {
-                            line.starts_with(&prompt_config.response_format.risk_level_prefix)
-                        })
-                        .and_then(|line| line.split(":").nth(1))
-                        .map(|s| s.trim().to_string());
-
-                    let explanation = if let Some(start) =
-                        content.find(&prompt_config.response_format.explanation_prefix)
-                    {
-                        let after_prefix =
-                            start + prompt_config.response_format.explanation_prefix.len();
-                        if content[after_prefix..].starts_with(':') {
-                            Some(content[(after_prefix + 1)..].trim().to_string())
-                        } else {
-                            Some(content[after_prefix..].trim().to_string())
-                        }
-                    } else {
-                        None
-                    };
-
-                    if status.is_success() {
-                        info!("✅ Analysis completed successfully - Function: {}, Risk level: {:?}", function_name, risk_level);
-                        HttpResponse::Ok().json(AnalysisResponse {
-                            status: "success".to_string(),
-                            function_name: Some(function_name),
-                            arguments: Some(arguments),
-                            risk_level,
-                            explanation,
-                            message: Some("Risk analysis completed".to_string()),
-                            details: None,
-                        })
-                    } else {
-                        error!(
-                            "❌ DeepSeek API error - Status: {}, Response: {}",
-                            status, json_response
-                        );
-                        HttpResponse::InternalServerError().json(AnalysisResponse {
-                            status: "error".to_string(),
-                            function_name: Some(function_name),
-                            arguments: Some(arguments),
-                            risk_level: None,
-                            explanation: None,
-                            message: Some(format!(
-                                "DeepSeek API error (HTTP status: {})",
-                                status
-                            )),
-                            details: Some(json_response.to_string()),
-                        })
-                    }
+    Ok(PreparedAnalysis {
+        llm,
+        system_message: prompt_config.system_message.clone(),
+        prompt,
+        max_output_attempts: prompt_config.max_output_attempts,
+        fallback,
+        resilience: prompt_config.resilience.clone(),
+        ensemble,
+        cache_key,
+        contract_address,
+        chain_id,
+        model,
+        wallet_findings,
+        response: AnalysisResponse {
+            status: "success".to_string(),
+            function_name: Some(function_name),
+            arguments: Some(arguments),
+            formatted_arguments: Some(formatted_arguments),
+            token_amounts: Some(token_amounts),
+            ownership,
+            upgrade_diff,
+            risk_score: Some(risk_score(&findings)),
+            findings: Some(findings),
+            simulation,
+            bytecode,
+            abi_consistency,
+            contract_profile,
+            source_excerpts: Some(source_excerpts),
+            honeypot,
+            value_at_risk: Some(value_at_risk),
+            wallet_exposure,
+            prompt_version: Some(prompt_version),
+            ..Default::default()
+        },
+    })
+}
+
+// Saves a model verdict in the analysis cache, off the async workers since it writes to disk
+async fn store_verdict(state: &AppState, entry: CachedVerdict) {
+    let cache = state.analysis_cache.clone();
+    let key = entry.key.clone();
+    if let Err(e) = web::block(move || cache.insert(entry)).await {
+        error!("❌ Failed to save analysis cache entry {}: {}", key, e);
+    }
+}
+
+/// Returns the cached verdict, or asks the ensemble, or the primary model and then the fallback one
+/// while their circuits are closed, all within the total deadline. Without any answer the verdict
+/// comes from the rule findings when `rules_only_fallback` is enabled.
+async fn obtain_verdict(
+    state: &AppState,
+    prepared: &PreparedAnalysis,
+    on_progress: Option<&(dyn Fn(VerdictProgress) + Send + Sync)>,
+) -> VerdictOutcome {
+    // Cache entries may be read from disk, off the async workers
+    let cache = state.analysis_cache.clone();
+    let key = prepared.cache_key.clone();
+    let cached = web::block(move || cache.get(&key)).await.unwrap_or_else(|e| {
+        error!("❌ Failed to read the analysis cache: {}", e);
+        None
+    });
+    if let Some(cached) = cached {
+        info!(
+            "♻️ Reusing cached verdict for {:?} from {} ({}, {})",
+            prepared.contract_address, cached.created_at, cached.prompt_version, cached.model
+        );
+        let source = VerdictSource {
+            path: VerdictPath::Cache,
+            model: Some(cached.model),
+        };
+        return VerdictOutcome {
+            result: Ok((cached.verdict, cached.attempts)),
+            source,
+            ensemble: cached.ensemble,
+        };
+    }
+
+    let settings = &prepared.resilience;
+    let deadline = Instant::now() + Duration::from_secs(settings.total_timeout_secs.max(1));
+    let mut last_error: Option<(LlmError, VerdictSource)> = None;
+
+    if let Some(ensemble) = &prepared.ensemble {
+        let ensemble = ensemble
+            .run(
+                settings,
+                &state.llm_breakers,
+                &prepared.system_message,
+                &prepared.prompt,
+                prepared.max_output_attempts,
+                deadline,
+            )
+            .await;
+        let source = VerdictSource {
+            path: VerdictPath::Ensemble,
+            model: Some(prepared.model.clone()),
+        };
+        match ensemble {
+            Ok((verdict, report)) => {
+                if let Some(prompt_version) = &prepared.response.prompt_version {
+                    store_verdict(state, CachedVerdict {
+                        key: prepared.cache_key.clone(),
+                        contract_address: prepared.contract_address,
+                        chain_id: prepared.chain_id,
+                        prompt_version: format!("{}/{}", prompt_version.template, prompt_version.version),
+                        model: prepared.model.clone(),
+                        verdict: verdict.clone(),
+                        attempts: 1,
+                        ensemble: Some(report.clone()),
+                        created_at: Utc::now(),
+                    })
+                    .await;
                 }
-                Err(e) => {
-                    error!("❌ Failed to parse DeepSeek JSON: {}", e);
-                    HttpResponse::InternalServerError().json(AnalysisResponse {
-                        status: "error".to_string(),
-                        function_name: Some(function_name),
-                        arguments: Some(arguments),
-                        risk_level: None,
-                        explanation: None,
-                        message: Some("Failed to parse DeepSeek JSON response".to_string()),
-                        details: Some(e.to_string()),
+                return VerdictOutcome {
+                    result: Ok((verdict, 1)),
+                    source,
+                    ensemble: Some(report),
+                };
+            }
+            Err(e) => {
+                error!("❌ Ensemble failed: {}", e);
+                last_error = Some((e, source));
+            }
+        }
+    }
+
+    // In ensemble mode the members already were the candidates
+    let candidates = match prepared.ensemble {
+        Some(_) => Vec::new(),
+        None => vec![
+            (VerdictPath::Primary, Some(prepared.llm.as_ref())),
+            (VerdictPath::Fallback, prepared.fallback.as_deref()),
+        ],
+    };
+    for (path, llm) in candidates {
+        let Some(llm) = llm else {
+            continue;
+        };
+        let id = provider_id(llm);
+        let source = VerdictSource {
+            path,
+            model: Some(id.clone()),
+        };
+        // Checked before the breaker, so a half-open trial is not handed out and then dropped
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        if !state.llm_breakers.allows(&id) {
+            warn!("⏭️ Circuit open for {}, skipping it", id);
+            last_error = Some((LlmError::Request(format!("circuit open for {}", id)), source));
+            continue;
+        }
+        if let (Some(on_progress), Some((error, _))) = (on_progress, &last_error) {
+            on_progress(VerdictProgress::Switched {
+                model: id.clone(),
+                reason: error.to_string(),
+            });
+        }
+
+        let resilient = ResilientProvider { inner: llm, settings };
+        let request = request_verdict(
+            &resilient,
+            &prepared.system_message,
+            &prepared.prompt,
+            prepared.max_output_attempts,
+            on_progress,
+        );
+        let outcome = match actix_web::rt::time::timeout(remaining, request).await {
+            Ok(outcome) => outcome,
+            Err(_) => Err(VerdictError::Llm(LlmError::Request(format!(
+                "no verdict within the {}s deadline",
+                settings.total_timeout_secs
+            )))),
+        };
+
+        match outcome {
+            Err(VerdictError::Llm(e)) => {
+                error!("❌ {} failed: {}", id, e);
+                state.llm_breakers.record_failure(&id, settings);
+                last_error = Some((e, source));
+            }
+            outcome => {
+                state.llm_breakers.record_success(&id);
+                // Fallback answers are not cached, so the primary takes over again once it recovers
+                if let (Ok((verdict, attempts)), Some(prompt_version), VerdictPath::Primary) =
+                    (&outcome, &prepared.response.prompt_version, path)
+                {
+                    store_verdict(state, CachedVerdict {
+                        key: prepared.cache_key.clone(),
+                        contract_address: prepared.contract_address,
+                        chain_id: prepared.chain_id,
+                        prompt_version: format!("{}/{}", prompt_version.template, prompt_version.version),
+                        model: prepared.model.clone(),
+                        verdict: verdict.clone(),
+                        attempts: *attempts,
+                        ensemble: None,
+                        created_at: Utc::now(),
                     })
+                    .await;
                 }
+                return VerdictOutcome {
+                    result: outcome,
+                    source,
+                    ensemble: None,
+                };
             }
         }
+    }
+
+    let (error, source) = last_error.unwrap_or_else(|| {
+        let error = LlmError::Request(format!("no verdict within the {}s deadline", settings.total_timeout_secs));
+        let source = VerdictSource {
+            path: VerdictPath::Primary,
+            model: Some(provider_id(prepared.llm.as_ref())),
+        };
+        (error, source)
+    });
+
+    if settings.rules_only_fallback {
+        warn!("⚠️ No model available ({}), answering from the rule findings only", error);
+        if let Some(on_progress) = on_progress {
+            on_progress(VerdictProgress::Switched {
+                model: "rules_only".to_string(),
+                reason: error.to_string(),
+            });
+        }
+        let findings = prepared.response.findings.as_deref().unwrap_or_default();
+        let source = VerdictSource {
+            path: VerdictPath::RulesOnly,
+            model: None,
+        };
+        return VerdictOutcome {
+            result: Ok((rules_only_verdict(findings, &error.to_string()), 0)),
+            source,
+            ensemble: None,
+        };
+    }
+    VerdictOutcome {
+        result: Err(VerdictError::Llm(error)),
+        source,
+        ensemble: None,
+    }
+}
+
+// The (possibly cached) explanation plus what this call means for the sender's own wallet
+fn with_wallet_context(explanation: &str, wallet_findings: &[Finding], value_at_risk: Option<&ValueAtRisk>) -> String {
+    let mut sections = vec![explanation.to_string()];
+    if let Some(report) = value_at_risk.filter(|report| !report.exposures.is_empty()) {
+        sections.push(format!("**Value at risk for your wallet**\n{}", format_value_for_prompt(Some(report))));
+    }
+    if !wallet_findings.is_empty() {
+        sections.push(format!(
+            "**Findings for your wallet**\n{}",
+            format_findings_for_prompt(wallet_findings)
+        ));
+    }
+    sections.join("\n\n")
+}
+
+/// Turns the model outcome into the final response and its HTTP status.
+fn finish_analysis(
+    prepared: PreparedAnalysis,
+    outcome: VerdictOutcome,
+) -> (StatusCode, AnalysisResponse) {
+    let PreparedAnalysis {
+        llm,
+        wallet_findings,
+        response,
+        ..
+    } = prepared;
+    let VerdictOutcome {
+        result,
+        source,
+        ensemble,
+    } = outcome;
+    // Errors are reported against the provider that produced them
+    let name = source.model.clone().unwrap_or_else(|| llm.name().to_string());
+
+    match result {
+        Ok((verdict, attempts)) => {
+            let risk_level = combine_risk_level(
+                Some(verdict.risk_level.to_string()),
+                response.findings.as_deref().unwrap_or_default(),
+            );
+            info!(
+                "✅ Analysis completed successfully - Function: {:?}, Risk level: {:?}, Attempts: {}",
+                response.function_name, risk_level, attempts
+            );
+            (
+                StatusCode::OK,
+                AnalysisResponse {
+                    risk_level,
+                    explanation: Some(with_wallet_context(
+                        &verdict.explanation,
+                        &wallet_findings,
+                        response.value_at_risk.as_ref(),
+                    )),
+                    message: Some("Risk analysis completed".to_string()),
+                    verdict: Some(verdict),
+                    cache_hit: Some(source.path == VerdictPath::Cache),
+                    verdict_source: Some(source),
+                    ensemble,
+                    ..response
+                },
+            )
+        }
+        Err(VerdictError::Unparseable(output)) => {
+            error!(
+                "❌ {} output unparseable after {} attempt(s): {}",
+                name,
+                output.attempts,
+                output.validation_error
+            );
+            (
+                StatusCode::BAD_GATEWAY,
+                AnalysisResponse {
+                    status: "error".to_string(),
+                    function_name: response.function_name,
+                    arguments: response.arguments,
+                    message: Some(format!(
+                        "{} response did not match the verdict schema after {} attempt(s)",
+                        name,
+                        output.attempts
+                    )),
+                    details: Some(output.validation_error.clone()),
+                    risk_score: response.risk_score,
+                    findings: response.findings,
+                    unparseable: Some(output),
+                    prompt_version: response.prompt_version,
+                    verdict_source: Some(source.clone()),
+                    ..Default::default()
+                },
+            )
+        }
+        Err(VerdictError::Llm(e)) => {
+            let message = match &e {
+                LlmError::Status { status, .. } => format!("{} API error (HTTP status: {})", name, status),
+                LlmError::Parse(_) => format!("Failed to parse {} response", name),
+                LlmError::Request(_) | LlmError::Config(_) => format!("Failed to call {} API", name),
+            };
+            let details = match e {
+                LlmError::Status { body, .. } => body,
+                other => other.to_string(),
+            };
+            error!("❌ {}: {}", message, details);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                AnalysisResponse {
+                    status: "error".to_string(),
+                    function_name: response.function_name,
+                    arguments: response.arguments,
+                    message: Some(message),
+                    details: Some(details),
+                    prompt_version: response.prompt_version,
+                    verdict_source: Some(source.clone()),
+                    ..Default::default()
+                },
+            )
+        }
+    }
+}
+
+/// The /analysis pipeline with `model` answering instead of the configured models.
+/// Used by riskoracle-eval to run the corpus against a mock or recorded model.
+pub async fn analyze_with_model(
+    state: &AppState,
+    req: &AnalysisRequest,
+    model: Box<dyn LlmProvider>,
+) -> (StatusCode, AnalysisResponse) {
+    let prepared = match prepare_analysis(state, req, Some(model), None).await {
+        Ok(prepared) => prepared,
+        Err(failure) => return failure,
+    };
+    let outcome = obtain_verdict(state, &prepared, None).await;
+    finish_analysis(prepared, outcome)
+}
+
+pub async fn analysis_handler(
+    state: web::Data<AppState>,
+    req: web::Json<AnalysisRequest>,
+) -> impl Responder {
+    info!(
+        "📥 Analysis request received - Contract: {}",
+        req.contract_address
+    );
+
+    let prepared = match prepare_analysis(&state, &req, None, None).await {
+        Ok(prepared) => prepared,
+        Err((status, response)) => return HttpResponse::build(status).json(response),
+    };
+
+    let outcome = obtain_verdict(&state, &prepared, None).await;
+    let (status, response) = finish_analysis(prepared, outcome);
+    HttpResponse::build(status).json(response)
+}
+
+/// Handler for the /analysis/stream endpoint.
+/// Same analysis as /analysis, sent as Server-Sent Events: decode, findings, LLM tokens, then the verdict.
+pub async fn analysis_stream_handler(
+    state: web::Data<AppState>,
+    req: web::Json<AnalysisRequest>,
+) -> impl Responder {
+    info!(
+        "📥 Streaming analysis request received - Contract: {}",
+        req.contract_address
+    );
+
+    let (events, receiver) = unbounded_channel();
+    let req = req.into_inner();
+
+    actix_web::rt::spawn(async move {
+        let prepared = match prepare_analysis(&state, &req, None, Some(&events)).await {
+            Ok(prepared) => prepared,
+            Err((_, response)) => {
+                let _ = events.send(AnalysisEvent::Error(Box::new(response)));
+                return;
+            }
+        };
+
+        // A closed stream (client gone) only drops the events; the analysis still completes
+        let on_progress = |progress: VerdictProgress| {
+            let _ = events.send(match progress {
+                VerdictProgress::Token(text) => AnalysisEvent::Token { text },
+                VerdictProgress::Rejected { attempt, error } => AnalysisEvent::Retry { attempt, error },
+                VerdictProgress::Switched { model, reason } => AnalysisEvent::Fallback { model, reason },
+            });
+        };
+        let outcome = obtain_verdict(&state, &prepared, Some(&on_progress)).await;
+        let (status, response) = finish_analysis(prepared, outcome);
+        let _ = events.send(if status.is_success() {
+            AnalysisEvent::Verdict(Box::new(response))
+        } else {
+            AnalysisEvent::Error(Box::new(response))
+        });
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .streaming(event_stream(receiver))
+}
+
+/// Handler for DELETE /admin/analysis-cache/{contract_address}.
+/// Drops the cached verdicts of a contract, e.g. after an upgrade. Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+pub async fn invalidate_analysis_cache_handler(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> impl Responder {
+    let contract_address = path.into_inner();
+
+    let admin_token = env::var("ADMIN_TOKEN").unwrap_or_default();
+    let presented = http_req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if admin_token.is_empty() || presented != Some(admin_token.as_str()) {
+        warn!("❌ Rejected analysis cache invalidation for {}", contract_address);
+        return HttpResponse::Unauthorized().json(CacheInvalidationResponse {
+            status: "error".to_string(),
+            contract_address: Some(contract_address),
+            removed: None,
+            message: Some("Missing or invalid admin token (set ADMIN_TOKEN to enable admin endpoints)".to_string()),
+        });
+    }
+
+    let address = match contract_address.parse::<Address>() {
+        Ok(addr) => addr,
         Err(e) => {
-            error!("❌ Failed to call DeepSeek API: {}", e);
-            HttpResponse::InternalServerError().json(AnalysisResponse {
+            return HttpResponse::BadRequest().json(CacheInvalidationResponse {
                 status: "error".to_string(),
-                function_name: Some(function_name),
-                arguments: Some(arguments),
-                risk_level: None,
-                explanation: None,
-                message: Some("Failed to call DeepSeek API".to_string()),
+                contract_address: Some(contract_address),
+                removed: None,
+                message: Some(format!("Invalid contract address: {}", e)),
+            });
+        }
+    };
+
+    // The invalidation scans the cache directory, off the async workers
+    let cache = state.analysis_cache.clone();
+    let invalidated = match web::block(move || cache.invalidate_contract(&address)).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match invalidated {
+        Ok(removed) => HttpResponse::Ok().json(CacheInvalidationResponse {
+            status: "success".to_string(),
+            contract_address: Some(contract_address),
+            removed: Some(removed),
+            message: None,
+        }),
+        Err(e) => {
+            error!("❌ Failed to invalidate the analysis cache for {}: {}", contract_address, e);
+            HttpResponse::InternalServerError().json(CacheInvalidationResponse {
+                status: "error".to_string(),
+                contract_address: Some(contract_address),
+                removed: None,
+                message: Some(e.to_string()),
+            })
+        }
+    }
+}
+
+/// Handler for POST /history/confirm.
+/// Adds the counterparties staged by the analysis of a mined transaction to its sender's history.
+/// The transaction is read from the node, so only a wallet that signed the call can extend its history.
+pub async fn confirm_transaction_handler(
+    state: web::Data<AppState>,
+    req: web::Json<TransactionConfirmationRequest>,
+) -> impl Responder {
+    info!("📥 Transaction confirmation received - Hash: {}", req.tx_hash);
+
+    let error = |status: StatusCode, wallet: Option<Address>, message: String| {
+        HttpResponse::build(status).json(TransactionConfirmationResponse {
+            status: "error".to_string(),
+            wallet: wallet.map(|wallet| format!("{:?}", wallet)),
+            recorded: None,
+            message: Some(message),
+        })
+    };
+
+    let hash = match req.tx_hash.parse::<H256>() {
+        Ok(hash) => hash,
+        Err(e) => return error(StatusCode::BAD_REQUEST, None, format!("Invalid transaction hash: {}", e)),
+    };
+    let provider = match rpc_provider() {
+        Some(provider) => provider,
+        None => {
+            return error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                None,
+                "RPC_URL is not configured, transactions cannot be confirmed".to_string(),
+            )
+        }
+    };
+
+    let transaction = match provider.get_transaction(hash).await {
+        Ok(Some(transaction)) => transaction,
+        Ok(None) => return error(StatusCode::NOT_FOUND, None, "Transaction not found".to_string()),
+        Err(e) => {
+            error!("❌ Failed to fetch transaction {:?}: {}", hash, e);
+            return error(StatusCode::BAD_GATEWAY, None, format!("Failed to fetch the transaction: {}", e));
+        }
+    };
+    let wallet = transaction.from;
+    match provider.get_transaction_receipt(hash).await {
+        Ok(Some(receipt)) if receipt.status == Some(1u64.into()) => {}
+        Ok(Some(_)) => return error(StatusCode::CONFLICT, Some(wallet), "Transaction reverted".to_string()),
+        // Same status as an unknown hash, callers retry both until the transaction is mined
+        Ok(None) => return error(StatusCode::NOT_FOUND, Some(wallet), "Transaction is not mined yet".to_string()),
+        Err(e) => {
+            error!("❌ Failed to fetch receipt of {:?}: {}", hash, e);
+            return error(StatusCode::BAD_GATEWAY, Some(wallet), format!("Failed to fetch the receipt: {}", e));
+        }
+    }
+
+    let staged = transaction
+        .to
+        .and_then(|target| state.history.take_staged(&wallet, &target, transaction.input.as_ref()));
+    let counterparties = match staged {
+        Some(counterparties) => counterparties,
+        None => {
+            info!("ℹ️ No analysis staged for {:?}, history of {:?} unchanged", hash, wallet);
+            return HttpResponse::Ok().json(TransactionConfirmationResponse {
+                status: "success".to_string(),
+                wallet: Some(format!("{:?}", wallet)),
+                recorded: Some(0),
+                message: Some("No recent analysis of this transaction".to_string()),
+            });
+        }
+    };
+
+    // The history is written to disk, off the async workers
+    let recorded = counterparties.len();
+    let history = state.history.clone();
+    if let Err(e) = web::block(move || history.record(&wallet, &counterparties)).await {
+        error!("❌ Failed to record the counterparties of {:?}: {}", hash, e);
+        return error(StatusCode::INTERNAL_SERVER_ERROR, Some(wallet), e.to_string());
+    }
+    info!("📒 Recorded {} counterparties for {:?}", recorded, wallet);
+
+    HttpResponse::Ok().json(TransactionConfirmationResponse {
+        status: "success".to_string(),
+        wallet: Some(format!("{:?}", wallet)),
+        recorded: Some(recorded),
+        message: None,
+    })
+}
+
+pub async fn abi_diff_handler(req: web::Json<AbiDiffRequest>) -> impl Responder {
+    info!(
+        "📥 ABI diff request received - Old: {}, New: {}",
+        req.old_address, req.new_address
+    );
+
+    let (old_address, new_address) = match (req.old_address.parse::<Address>(), req.new_address.parse::<Address>()) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("❌ Invalid address in ABI diff request: {}", e);
+            return HttpResponse::BadRequest().json(AbiDiffResponse {
+                status: "error".to_string(),
+                diff: None,
+                findings: None,
+                message: Some(format!("Invalid address: {}", e)),
+                details: None,
+            });
+        }
+    };
+
+    match diff_contracts(&old_address, &new_address).await {
+        Ok(diff) => {
+            let findings = diff_findings(&diff);
+            info!("✅ ABI diff completed - Findings: {}", findings.len());
+            HttpResponse::Ok().json(AbiDiffResponse {
+                status: "success".to_string(),
+                diff: Some(diff),
+                findings: Some(findings),
+                message: None,
+                details: None,
+            })
+        }
+        Err(e) => {
+            error!("❌ Failed to diff {} and {}: {}", old_address, new_address, e);
+            HttpResponse::InternalServerError().json(AbiDiffResponse {
+                status: "error".to_string(),
+                diff: None,
+                findings: None,
+                message: Some("Failed to compare the contracts".to_string()),
+                details: Some(e.to_string()),
+            })
+        }
+    }
+}
+
+pub async fn contract_profile_handler(req: web::Json<ContractRiskProfileRequest>) -> impl Responder {
+    info!(
+        "📥 Contract profile request received - Contract: {}",
+        req.contract_address
+    );
+
+    let contract_address = match req.contract_address.parse::<Address>() {
+        Ok(addr) => addr,
+        Err(e) => {
+            warn!("❌ Invalid contract address: {} - Error: {}", req.contract_address, e);
+            return HttpResponse::BadRequest().json(ContractRiskProfileResponse {
+                status: "error".to_string(),
+                profile: None,
+                message: Some(format!("Invalid contract address: {}", e)),
+                details: None,
+            });
+        }
+    };
+
+    let provider = rpc_provider();
+    match get_or_build_risk_profile(&contract_address, provider.as_ref(), req.refresh).await {
+        Ok(profile) => {
+            info!(
+                "✅ Contract profile ready - Risk level: {}, Score: {}",
+                profile.risk_level, profile.risk_score
+            );
+            HttpResponse::Ok().json(ContractRiskProfileResponse {
+                status: "success".to_string(),
+                profile: Some(profile),
+                message: None,
+                details: None,
+            })
+        }
+        Err(e) => {
+            error!("❌ Failed to build contract profile for {}: {}", contract_address, e);
+            HttpResponse::InternalServerError().json(ContractRiskProfileResponse {
+                status: "error".to_string(),
+                profile: None,
+                message: Some("Failed to build the contract profile".to_string()),
                 details: Some(e.to_string()),
             })
         }
@@ -418,7 +1603,7 @@ pub async fn chainlink_audit_handler(
     info!("🚀 Launching CRE CLI: cre workflow simulate risk_oracle_wf1");
 
     let simulate_result = tokio::process::Command::new("cre")
-        .args(&[
+        .args([
             "workflow",
             "simulate",
             "risk_oracle_wf1",
@@ -577,3 +1762,13 @@ pub async fn chainlink_audit_handler(
     }
 }
 
+// Parses a wei amount given as decimal or 0x-prefixed hex; missing means zero
+fn parse_wei(value: Option<&str>) -> Result<U256, Box<dyn std::error::Error>> {
+    match value.map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        None => Ok(U256::zero()),
+        Some(v) => match v.strip_prefix("0x") {
+            Some(hex_value) => Ok(U256::from_str_radix(hex_value, 16)?),
+            None => Ok(U256::from_dec_str(v)?),
+        },
+    }
+}
@@ -0,0 +1,233 @@
+use ethabi::Contract;
+use ethers::providers::{Http, Provider};
+use ethers::types::Address;
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::abi::get_or_fetch_abi;
+use crate::bytecode::{analyze_bytecode, BytecodeReport};
+use crate::proxy::{detect_proxy, ProxyInfo};
+use crate::rpc::get_code;
+use crate::rules::{Finding, Severity};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AbiFunction {
+    pub selector: String, // 0x-prefixed
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AbiConsistencyReport {
+    pub proxy: Option<ProxyInfo>,
+    pub code_selectors: usize,
+    pub undeclared_selectors: Vec<String>,     // Dispatched by the code, absent from every ABI
+    pub missing_from_code: Vec<AbiFunction>,   // Declared in the ABI, not dispatched by the code
+    pub proxy_clashes: Vec<String>,            // Dispatched by both the proxy and its implementation
+}
+
+/// Selectors and signatures of every function in an ABI.
+pub fn abi_functions(contract: &Contract) -> Vec<AbiFunction> {
+    contract
+        .functions()
+        .map(|f| AbiFunction {
+            selector: format!("0x{}", hex::encode(f.short_signature())),
+            signature: f.signature().split(':').next().unwrap_or_default().to_string(),
+        })
+        .collect()
+}
+
+/// Cross-checks the ABI against the selectors of the runtime dispatcher. For proxies, the
+/// implementation's code and (when verified) its ABI are included, and selectors dispatched by
+/// both the proxy and the implementation are reported as clashes.
+pub async fn check_abi_consistency(
+    provider: &Provider<Http>,
+    address: &Address,
+    code: &[u8],
+    bytecode: &BytecodeReport,
+    declared: &[AbiFunction],
+) -> Result<AbiConsistencyReport, Box<dyn std::error::Error>> {
+    let proxy_selectors: BTreeSet<String> = bytecode
+        .entry_points
+        .iter()
+        .map(|e| e.selector.clone())
+        .collect();
+
+    let mut declared_by_selector: BTreeMap<String, AbiFunction> = declared
+        .iter()
+        .map(|f| (f.selector.clone(), f.clone()))
+        .collect();
+    let mut code_selectors = proxy_selectors.clone();
+    let mut proxy_clashes = Vec::new();
+
+    let proxy = detect_proxy(provider, address, code).await?;
+    if let Some(info) = &proxy {
+        let implementation_code = get_code(provider, &info.implementation).await?;
+        let implementation_selectors: BTreeSet<String> = analyze_bytecode(&implementation_code)
+            .entry_points
+            .into_iter()
+            .map(|e| e.selector)
+            .collect();
+
+        proxy_clashes = proxy_selectors
+            .intersection(&implementation_selectors)
+            .cloned()
+            .collect();
+        code_selectors.extend(implementation_selectors);
+
+        // Calls the proxy does not dispatch itself are delegated, so the implementation ABI counts too
+        match get_or_fetch_abi(&info.implementation).await {
+            Ok(list) => {
+                for (contract, _) in list {
+                    for function in abi_functions(&contract) {
+                        declared_by_selector.entry(function.selector.clone()).or_insert(function);
+                    }
+                }
+            }
+            Err(e) => warn!(
+                "⚠️ No ABI for implementation {:?}: {}",
+                info.implementation, e
+            ),
+        }
+    }
+
+    // Without a recognizable dispatcher (e.g. Vyper, hand-written code) nothing can be compared
+    let (undeclared_selectors, missing_from_code) = if code_selectors.is_empty() {
+        warn!("⚠️ No dispatcher selectors found for {:?}, skipping ABI cross-check", address);
+        (Vec::new(), Vec::new())
+    } else {
+        (
+            code_selectors
+                .iter()
+                .filter(|s| !declared_by_selector.contains_key(*s))
+                .cloned()
+                .collect(),
+            declared_by_selector
+                .values()
+                .filter(|f| !code_selectors.contains(&f.selector))
+                .cloned()
+                .collect(),
+        )
+    };
+
+    info!(
+        "🕵️ ABI cross-check for {:?} - Undeclared: {}, Missing from code: {}, Proxy clashes: {}",
+        address,
+        undeclared_selectors.len(),
+        missing_from_code.len(),
+        proxy_clashes.len()
+    );
+
+    Ok(AbiConsistencyReport {
+        proxy,
+        code_selectors: code_selectors.len(),
+        undeclared_selectors,
+        missing_from_code,
+        proxy_clashes,
+    })
+}
+
+/// Turns the ABI/code mismatches into rule findings.
+pub fn abi_consistency_findings(report: &AbiConsistencyReport, called_selector: Option<&str>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if !report.undeclared_selectors.is_empty() {
+        let called_hidden = called_selector
+            .map(|s| report.undeclared_selectors.iter().any(|u| u == s))
+            .unwrap_or(false);
+        findings.push(
+            Finding::new(
+                "abi.undeclared_selectors",
+                if called_hidden { Severity::Critical } else { Severity::High },
+                "Hidden functions in deployed code",
+                format!(
+                    "The code dispatches {} selector(s) missing from the published ABI: {}{}",
+                    report.undeclared_selectors.len(),
+                    report.undeclared_selectors.join(", "),
+                    if called_hidden { ". The analyzed call targets one of them" } else { "" }
+                ),
+            )
+            .at("bytecode"),
+        );
+    }
+
+    if !report.missing_from_code.is_empty() {
+        findings.push(
+            Finding::new(
+                "abi.missing_from_code",
+                Severity::Medium,
+                "ABI declares functions absent from the code",
+                format!(
+                    "The published ABI does not match the deployed code; not dispatched: {}",
+                    report
+                        .missing_from_code
+                        .iter()
+                        .map(|f| format!("{} ({})", f.signature, f.selector))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            )
+            .at("bytecode"),
+        );
+    }
+
+    if !report.proxy_clashes.is_empty() {
+        findings.push(
+            Finding::new(
+                "abi.proxy_selector_clash",
+                Severity::High,
+                "Proxy/implementation selector clash",
+                format!(
+                    "The proxy itself answers {} selector(s) that the implementation also defines, so those implementation functions are unreachable or shadowed: {}",
+                    report.proxy_clashes.len(),
+                    report.proxy_clashes.join(", ")
+                ),
+            )
+            .at("proxy"),
+        );
+    }
+
+    findings
+}
+
+/// Renders the cross-check as compact text for the LLM prompt.
+pub fn format_abi_consistency_for_prompt(report: Option<&AbiConsistencyReport>) -> String {
+    let report = match report {
+        Some(report) => report,
+        None => return "Not available".to_string(),
+    };
+
+    let mut lines = Vec::new();
+    if let Some(proxy) = &report.proxy {
+        lines.push(format!(
+            "Proxy: {:?} -> implementation {:?}",
+            proxy.kind, proxy.implementation
+        ));
+    }
+    lines.push(format!("Selectors dispatched by the code: {}", report.code_selectors));
+    lines.push(format!(
+        "Undeclared (hidden) selectors: {}",
+        if report.undeclared_selectors.is_empty() {
+            "none".to_string()
+        } else {
+            report.undeclared_selectors.join(", ")
+        }
+    ));
+    lines.push(format!(
+        "ABI functions missing from code: {}",
+        if report.missing_from_code.is_empty() {
+            "none".to_string()
+        } else {
+            report
+                .missing_from_code
+                .iter()
+                .map(|f| f.signature.clone())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    ));
+    if !report.proxy_clashes.is_empty() {
+        lines.push(format!("Proxy selector clashes: {}", report.proxy_clashes.join(", ")));
+    }
+    lines.join("\n")
+}
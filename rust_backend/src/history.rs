@@ -0,0 +1,192 @@
+use chrono::{DateTime, Duration, Utc};
+use ethers::types::Address;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+// Counterparties of an analyzed call wait this long for the transaction to be confirmed
+const PENDING_TTL_MINUTES: i64 = 60;
+// Expired staged calls are dropped once the map grows past this
+const MAX_PENDING_CALLS: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CounterpartyRecord {
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub interactions: u32,
+}
+
+// Every address a wallet has interacted with through the analysis endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WalletHistory {
+    pub counterparties: HashMap<Address, CounterpartyRecord>,
+}
+
+// Counterparties of an analyzed call, recorded only once the sender's transaction is mined
+struct PendingCall {
+    counterparties: Vec<Address>,
+    staged_at: DateTime<Utc>,
+}
+
+/// Identifies a call by sender, target and calldata, the fields an analysis and the mined
+/// transaction have in common.
+pub fn call_key(wallet: &Address, target: &Address, call_data: &[u8]) -> String {
+    let material = format!("{:?}|{:?}|0x{}", wallet, target, hex::encode(call_data));
+    hex::encode(Sha256::digest(material.as_bytes()))
+}
+
+/// Per-wallet counterparty history kept by the backend itself.
+/// Wallets are loaded lazily from `<dir>/<wallet>.json` and written back on every update.
+pub struct AddressHistoryStore {
+    dir: PathBuf,
+    wallets: RwLock<HashMap<Address, WalletHistory>>,
+    pending: Mutex<HashMap<String, PendingCall>>,
+}
+
+impl AddressHistoryStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        AddressHistoryStore {
+            dir: dir.into(),
+            wallets: RwLock::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Builds the store from `HISTORY_DIR` (default: `history`).
+    pub fn from_env() -> Self {
+        let dir = env::var("HISTORY_DIR").unwrap_or_else(|_| "history".to_string());
+        AddressHistoryStore::new(dir)
+    }
+
+    /// Addresses the wallet has interacted with before.
+    pub fn counterparties(&self, wallet: &Address) -> Vec<Address> {
+        self.ensure_loaded(wallet);
+        self.wallets
+            .read()
+            .unwrap()
+            .get(wallet)
+            .map(|h| h.counterparties.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Keeps the counterparties of an analyzed call until its transaction is confirmed.
+    /// The `from` of an analysis request is not authenticated, so nothing is recorded yet.
+    pub fn stage(&self, wallet: &Address, target: &Address, call_data: &[u8], counterparties: &[Address]) {
+        if counterparties.is_empty() {
+            return;
+        }
+        let now = Utc::now();
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= MAX_PENDING_CALLS {
+            pending.retain(|_, call| now - call.staged_at < Duration::minutes(PENDING_TTL_MINUTES));
+            if pending.len() >= MAX_PENDING_CALLS {
+                warn!("⚠️ Too many unconfirmed analyses, not staging counterparties of {:?}", wallet);
+                return;
+            }
+        }
+        pending.insert(
+            call_key(wallet, target, call_data),
+            PendingCall {
+                counterparties: counterparties.to_vec(),
+                staged_at: now,
+            },
+        );
+    }
+
+    /// Removes and returns the counterparties staged for a call, unless they expired.
+    pub fn take_staged(&self, wallet: &Address, target: &Address, call_data: &[u8]) -> Option<Vec<Address>> {
+        let call = self.pending.lock().unwrap().remove(&call_key(wallet, target, call_data))?;
+        (Utc::now() - call.staged_at < Duration::minutes(PENDING_TTL_MINUTES)).then_some(call.counterparties)
+    }
+
+    /// Records the given counterparties for the wallet and persists the history.
+    /// Writes to disk, so async callers run it on `web::block`.
+    pub fn record(&self, wallet: &Address, counterparties: &[Address]) {
+        if counterparties.is_empty() {
+            return;
+        }
+        self.ensure_loaded(wallet);
+
+        let now = Utc::now();
+        let snapshot = {
+            let mut wallets = self.wallets.write().unwrap();
+            let history = wallets.entry(*wallet).or_default();
+            for counterparty in counterparties {
+                if counterparty == wallet {
+                    continue;
+                }
+                history
+                    .counterparties
+                    .entry(*counterparty)
+                    .and_modify(|r| {
+                        r.last_seen = now;
+                        r.interactions += 1;
+                    })
+                    .or_insert(CounterpartyRecord {
+                        first_seen: now,
+                        last_seen: now,
+                        interactions: 1,
+                    });
+            }
+            history.clone()
+        };
+
+        if let Err(e) = self.save(wallet, &snapshot) {
+            error!("❌ Failed to save address history for {:?}: {}", wallet, e);
+        }
+    }
+
+    fn ensure_loaded(&self, wallet: &Address) {
+        if self.wallets.read().unwrap().contains_key(wallet) {
+            return;
+        }
+
+        let path = self.wallet_path(wallet);
+        let history = if path.exists() {
+            match fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|s| serde_json::from_str::<WalletHistory>(&s).map_err(|e| e.to_string()))
+            {
+                Ok(history) => {
+                    info!(
+                        "📖 Loaded {} counterparties for {:?}",
+                        history.counterparties.len(),
+                        wallet
+                    );
+                    history
+                }
+                Err(e) => {
+                    error!("❌ Corrupted address history {}: {}", path.display(), e);
+                    WalletHistory::default()
+                }
+            }
+        } else {
+            WalletHistory::default()
+        };
+
+        self.wallets
+            .write()
+            .unwrap()
+            .entry(*wallet)
+            .or_insert(history);
+    }
+
+    fn save(&self, wallet: &Address, history: &WalletHistory) -> Result<(), Box<dyn std::error::Error>> {
+        if !Path::new(&self.dir).exists() {
+            info!("📁 Creating address history directory: {}", self.dir.display());
+            fs::create_dir_all(&self.dir)?;
+        }
+        fs::write(self.wallet_path(wallet), serde_json::to_string_pretty(history)?)?;
+        Ok(())
+    }
+
+    fn wallet_path(&self, wallet: &Address) -> PathBuf {
+        // Same lowercase naming as the ABI cache
+        self.dir.join(format!("{:?}.json", wallet).to_lowercase())
+    }
+}
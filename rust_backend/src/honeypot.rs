@@ -0,0 +1,501 @@
+use ethabi::{ParamType, Token};
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, H256, I256, U256};
+use ethers::utils::keccak256;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::env;
+
+use crate::rpc::{call_address, call_uint, encode_call, eth_call, eth_call_with_overrides};
+use crate::rules::{Finding, Severity};
+use crate::simulation::{simulate_with_overrides, AssetKind, SimulationReport};
+
+// Fresh addresses used for the probes, so no whitelist or prior state applies to them
+const PROBE_HOLDER: &str = "0x00000000000000000000000000000000005151bb";
+const PROBE_RECIPIENT: &str = "0x00000000000000000000000000000000005151cc";
+
+// Storage slots tried when looking for the balance and allowance mappings
+const MAX_SLOT_PROBE: u64 = 20;
+// ETH spent on the simulated buy
+const BUY_AMOUNT_WEI: u128 = 100_000_000_000_000_000; // 0.1 ETH
+// Tax (percent) above which selling is considered practically blocked
+const HONEYPOT_TAX_PERCENT: f64 = 50.0;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TradeResult {
+    pub success: bool,
+    pub revert_reason: Option<String>,
+    pub expected: String, // Base units the trade should deliver without any tax
+    pub received: String,
+    pub tax_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HoneypotReport {
+    pub token: Address,
+    pub router: Option<Address>,
+    pub balance_slot: Option<u64>,
+    pub transfer: Option<TradeResult>, // Wallet-to-wallet transfer
+    pub buy: Option<TradeResult>,      // Router buy with ETH
+    pub sell: Option<TradeResult>,     // Router sell for ETH
+    pub max_transfer_limited: bool,    // Large transfers revert while small ones go through
+    pub owner_adjustable_fees: bool,   // From the source scanner
+    pub is_honeypot: bool,
+    pub verdict: String,
+}
+
+/// UniswapV2-compatible router used for the buy/sell probes (`DEX_ROUTER`).
+fn dex_router() -> Option<Address> {
+    let router = env::var("DEX_ROUTER").ok().filter(|r| !r.trim().is_empty())?;
+    match router.trim().parse() {
+        Ok(address) => Some(address),
+        Err(e) => {
+            warn!("⚠️ Invalid DEX_ROUTER '{}': {}", router, e);
+            None
+        }
+    }
+}
+
+/// Tries a transfer, a buy and a sell of the token against the node using state overrides,
+/// measuring the effective tax of each and whether selling is possible at all.
+pub async fn check_honeypot(
+    provider: &Provider<Http>,
+    token: Address,
+    owner_adjustable_fees: bool,
+) -> Result<HoneypotReport, Box<dyn std::error::Error>> {
+    let holder: Address = PROBE_HOLDER.parse()?;
+    let recipient: Address = PROBE_RECIPIENT.parse()?;
+
+    let total_supply = call_uint(provider, &token, "totalSupply()", &[])
+        .await
+        .map_err(|e| format!("{:?} does not look like an ERC-20 token: {}", token, e))?;
+    info!("🍯 Honeypot check for token {:?} (total supply: {})", token, total_supply);
+
+    let small_amount = (total_supply / U256::from(10_000)).max(U256::one());
+    // Divided first, totalSupply() is whatever the token returns and may be close to U256::MAX
+    let large_amount = total_supply / U256::from(100) * U256::from(3);
+    let balance_slot = find_balance_slot(provider, &token, &holder).await;
+
+    let mut report = HoneypotReport {
+        token,
+        router: dex_router(),
+        balance_slot,
+        transfer: None,
+        buy: None,
+        sell: None,
+        max_transfer_limited: false,
+        owner_adjustable_fees,
+        is_honeypot: false,
+        verdict: String::new(),
+    };
+
+    // Wallet-to-wallet transfers, with the holder's balance written straight into storage
+    if let Some(slot) = balance_slot {
+        let overrides = token_overrides(&token, &[(balance_storage_key(&holder, slot), total_supply)]);
+        let transfer_data = encode_call(
+            "transfer(address,uint256)",
+            &[Token::Address(recipient), Token::Uint(small_amount)],
+        );
+        let simulation =
+            simulate_with_overrides(provider, holder, token, &transfer_data, U256::zero(), overrides.clone()).await?;
+        let received = token_delta(&simulation, &recipient, &token);
+        report.transfer = Some(trade_result(&simulation, small_amount, received));
+
+        if simulation.success && !large_amount.is_zero() {
+            let large_data = encode_call(
+                "transfer(address,uint256)",
+                &[Token::Address(recipient), Token::Uint(large_amount)],
+            );
+            let large =
+                simulate_with_overrides(provider, holder, token, &large_data, U256::zero(), overrides).await?;
+            report.max_transfer_limited = !large.success;
+        }
+    } else {
+        warn!("⚠️ Balance slot of {:?} not found, skipping transfer probes", token);
+    }
+
+    if let Some(router) = report.router {
+        match router_probes(provider, router, token, holder, balance_slot, small_amount).await {
+            Ok((buy, sell)) => {
+                report.buy = buy;
+                report.sell = sell;
+            }
+            Err(e) => warn!("⚠️ Router probes for {:?} failed: {}", token, e),
+        }
+    }
+
+    let (is_honeypot, verdict) = verdict(&report);
+    report.is_honeypot = is_honeypot;
+    report.verdict = verdict;
+
+    info!(
+        "🍯 Honeypot check for {:?} - Honeypot: {}, {}",
+        token, report.is_honeypot, report.verdict
+    );
+    Ok(report)
+}
+
+async fn router_probes(
+    provider: &Provider<Http>,
+    router: Address,
+    token: Address,
+    holder: Address,
+    balance_slot: Option<u64>,
+    fallback_amount: U256,
+) -> Result<(Option<TradeResult>, Option<TradeResult>), Box<dyn std::error::Error>> {
+    let weth = call_address(provider, &router, "WETH()").await?;
+    let deadline = U256::from(u64::MAX);
+
+    // Buy: ETH -> token
+    let buy_value = U256::from(BUY_AMOUNT_WEI);
+    let buy_path = Token::Array(vec![Token::Address(weth), Token::Address(token)]);
+    let expected_tokens = amounts_out(provider, &router, buy_value, buy_path.clone()).await?;
+    let buy_data = encode_call(
+        "swapExactETHForTokensSupportingFeeOnTransferTokens(uint256,address[],address,uint256)",
+        &[Token::Uint(U256::zero()), buy_path, Token::Address(holder), Token::Uint(deadline)],
+    );
+    let buy_simulation =
+        simulate_with_overrides(provider, holder, router, &buy_data, buy_value, Map::new()).await?;
+    let bought = token_delta(&buy_simulation, &holder, &token);
+    let buy = trade_result(&buy_simulation, expected_tokens, bought);
+
+    // Sell: token -> ETH, with balance and router allowance written into storage
+    let slot = match balance_slot {
+        Some(slot) => slot,
+        None => return Ok((Some(buy), None)),
+    };
+    let sell_amount = if bought.is_zero() { fallback_amount } else { bought };
+    // Without the router allowance the sell would revert in transferFrom whatever the token does
+    let allowance_key = match find_allowance_key(provider, &token, &holder, &router).await {
+        Some(key) => key,
+        None => {
+            warn!("⚠️ Allowance slot of {:?} not found, skipping the sell probe", token);
+            return Ok((Some(buy), None));
+        }
+    };
+    let storage = vec![
+        (balance_storage_key(&holder, slot), sell_amount),
+        (allowance_key, U256::MAX),
+    ];
+
+    let sell_path = Token::Array(vec![Token::Address(token), Token::Address(weth)]);
+    let expected_eth = amounts_out(provider, &router, sell_amount, sell_path.clone()).await?;
+    let sell_data = encode_call(
+        "swapExactTokensForETHSupportingFeeOnTransferTokens(uint256,uint256,address[],address,uint256)",
+        &[
+            Token::Uint(sell_amount),
+            Token::Uint(U256::zero()),
+            sell_path,
+            Token::Address(holder),
+            Token::Uint(deadline),
+        ],
+    );
+    let sell_simulation = simulate_with_overrides(
+        provider,
+        holder,
+        router,
+        &sell_data,
+        U256::zero(),
+        token_overrides(&token, &storage),
+    )
+    .await?;
+    let received_eth = native_delta(&sell_simulation, &holder);
+    let sell = trade_result(&sell_simulation, expected_eth, received_eth);
+
+    Ok((Some(buy), Some(sell)))
+}
+
+fn verdict(report: &HoneypotReport) -> (bool, String) {
+    let reverted = |trade: &Option<TradeResult>| trade.as_ref().map(|t| !t.success).unwrap_or(false);
+    let tax = |trade: &Option<TradeResult>| trade.as_ref().and_then(|t| t.tax_percent).unwrap_or(0.0);
+
+    if reverted(&report.sell) && !reverted(&report.buy) {
+        return (true, "Buying works but selling reverts".to_string());
+    }
+    if reverted(&report.transfer) {
+        return (true, "Plain transfers revert, holders cannot move the token".to_string());
+    }
+    if tax(&report.sell) >= HONEYPOT_TAX_PERCENT {
+        return (true, format!("Sell tax of {:.1}% makes selling pointless", tax(&report.sell)));
+    }
+
+    // Without debug_traceCall a successful trade has no measurable tax, which is not a reason to flag the token
+    let untraced = [&report.buy, &report.sell, &report.transfer]
+        .into_iter()
+        .any(|trade| trade.as_ref().map(|t| t.success && t.tax_percent.is_none()).unwrap_or(false));
+    let max_tax = [tax(&report.buy), tax(&report.sell), tax(&report.transfer)]
+        .into_iter()
+        .fold(0.0, f64::max);
+    let mut notes = vec![if untraced && max_tax == 0.0 {
+        "Tax not measurable (the node has no debug_traceCall)".to_string()
+    } else {
+        format!("Max measured tax {:.1}%", max_tax)
+    }];
+    if report.sell.is_none() {
+        notes.push("sell not tested".to_string());
+    }
+    if report.max_transfer_limited {
+        notes.push("large transfers are limited".to_string());
+    }
+    (false, notes.join(", "))
+}
+
+/// Turns the honeypot probes into rule findings.
+pub fn honeypot_findings(report: &HoneypotReport) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let location = format!("token {:?}", report.token);
+
+    if report.is_honeypot {
+        findings.push(
+            Finding::new(
+                "honeypot.sell_blocked",
+                Severity::Critical,
+                "Honeypot token",
+                format!("{}. Tokens bought cannot be sold back", report.verdict),
+            )
+            .at(location.clone()),
+        );
+    }
+
+    let taxes: Vec<(&str, f64)> = [("buy", &report.buy), ("sell", &report.sell), ("transfer", &report.transfer)]
+        .into_iter()
+        .filter_map(|(name, trade)| trade.as_ref().and_then(|t| t.tax_percent).map(|tax| (name, tax)))
+        .filter(|(_, tax)| *tax >= 1.0)
+        .collect();
+    if let Some(max_tax) = taxes.iter().map(|(_, tax)| *tax).reduce(f64::max) {
+        let severity = if max_tax >= 25.0 {
+            Severity::High
+        } else if max_tax >= 10.0 {
+            Severity::Medium
+        } else {
+            Severity::Low
+        };
+        findings.push(
+            Finding::new(
+                "honeypot.transfer_tax",
+                severity,
+                "Token charges a transfer tax",
+                format!(
+                    "Measured tax: {}{}",
+                    taxes
+                        .iter()
+                        .map(|(name, tax)| format!("{} {:.1}%", name, tax))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    if report.owner_adjustable_fees { ". The owner can change it at any time" } else { "" }
+                ),
+            )
+            .at(location.clone()),
+        );
+    }
+
+    if report.max_transfer_limited {
+        findings.push(
+            Finding::new(
+                "honeypot.max_transfer_limit",
+                Severity::Medium,
+                "Max wallet or transaction limit",
+                "Transferring 3% of the supply reverts while small transfers succeed, so large positions may not be sellable at once",
+            )
+            .at(location),
+        );
+    }
+
+    findings
+}
+
+/// Renders the probes as compact text for the LLM prompt.
+pub fn format_honeypot_for_prompt(report: Option<&HoneypotReport>) -> String {
+    let report = match report {
+        Some(report) => report,
+        None => return "Not available (no ERC-20 involved or no RPC node configured)".to_string(),
+    };
+
+    let describe = |trade: &Option<TradeResult>| match trade {
+        None => "not tested".to_string(),
+        Some(t) if !t.success => format!(
+            "REVERTED ({})",
+            t.revert_reason.clone().unwrap_or_else(|| "no reason".to_string())
+        ),
+        Some(t) => match t.tax_percent {
+            Some(tax) => format!("ok, tax {:.1}%", tax),
+            None => "ok, tax not measured".to_string(),
+        },
+    };
+
+    [
+        format!("Token: {:?}", report.token),
+        format!("Honeypot: {} ({})", report.is_honeypot, report.verdict),
+        format!("Buy: {}", describe(&report.buy)),
+        format!("Sell: {}", describe(&report.sell)),
+        format!("Transfer: {}", describe(&report.transfer)),
+        format!("Max transfer limited: {}", report.max_transfer_limited),
+        format!("Owner-adjustable fees: {}", report.owner_adjustable_fees),
+    ]
+    .join("\n")
+}
+
+fn trade_result(simulation: &SimulationReport, expected: U256, received: U256) -> TradeResult {
+    // Without a trace there are no balance changes, so `received` would read as zero
+    let tax_percent = (simulation.success && simulation.traced && !expected.is_zero()).then(|| {
+        let expected = u256_to_f64(expected);
+        ((expected - u256_to_f64(received)) / expected * 100.0).clamp(0.0, 100.0)
+    });
+    TradeResult {
+        success: simulation.success,
+        revert_reason: simulation.revert_reason.clone(),
+        expected: expected.to_string(),
+        received: received.to_string(),
+        tax_percent,
+    }
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    value.to_string().parse().unwrap_or(f64::MAX)
+}
+
+// Positive balance change of `holder` in the simulation, zero otherwise
+fn asset_gain(simulation: &SimulationReport, holder: &Address, asset: AssetKind, token: Option<&Address>) -> U256 {
+    simulation
+        .asset_changes
+        .iter()
+        .filter(|c| c.address == *holder && c.asset == asset && c.token.as_ref() == token)
+        .filter_map(|c| I256::from_dec_str(&c.delta).ok())
+        .filter(|delta| delta.is_positive())
+        .map(|delta| delta.into_raw())
+        .fold(U256::zero(), |sum, delta| sum.saturating_add(delta))
+}
+
+fn token_delta(simulation: &SimulationReport, holder: &Address, token: &Address) -> U256 {
+    asset_gain(simulation, holder, AssetKind::Erc20, Some(token))
+}
+
+fn native_delta(simulation: &SimulationReport, holder: &Address) -> U256 {
+    asset_gain(simulation, holder, AssetKind::Native, None)
+}
+
+async fn amounts_out(
+    provider: &Provider<Http>,
+    router: &Address,
+    amount_in: U256,
+    path: Token,
+) -> Result<U256, Box<dyn std::error::Error>> {
+    let output = eth_call(
+        provider,
+        router,
+        &encode_call("getAmountsOut(uint256,address[])", &[Token::Uint(amount_in), path]),
+    )
+    .await
+    .map_err(|e| format!("no liquidity pair on the router: {}", e))?;
+    match ethabi::decode(&[ParamType::Array(Box::new(ParamType::Uint(256)))], &output)?.first() {
+        Some(Token::Array(amounts)) => match amounts.last() {
+            Some(Token::Uint(amount)) => Ok(*amount),
+            _ => Err("getAmountsOut returned no amounts".into()),
+        },
+        _ => Err("unexpected getAmountsOut output".into()),
+    }
+}
+
+fn u256_word(value: U256) -> H256 {
+    let mut word = [0u8; 32];
+    value.to_big_endian(&mut word);
+    H256::from(word)
+}
+
+// Solidity layout of `mapping(address => uint256)` at `slot`
+fn balance_storage_key(holder: &Address, slot: u64) -> H256 {
+    let mut preimage = H256::from(*holder).as_bytes().to_vec();
+    preimage.extend_from_slice(u256_word(U256::from(slot)).as_bytes());
+    H256::from(keccak256(preimage))
+}
+
+// Solidity layout of `mapping(address => mapping(address => uint256))` at `slot`
+fn allowance_storage_key(owner: &Address, spender: &Address, slot: u64) -> H256 {
+    let inner = balance_storage_key(owner, slot);
+    let mut preimage = H256::from(*spender).as_bytes().to_vec();
+    preimage.extend_from_slice(inner.as_bytes());
+    H256::from(keccak256(preimage))
+}
+
+fn token_overrides(token: &Address, storage: &[(H256, U256)]) -> Map<String, Value> {
+    let state_diff: Map<String, Value> = storage
+        .iter()
+        .map(|(key, value)| (format!("{:?}", key), json!(u256_word(*value))))
+        .collect();
+    let mut overrides = Map::new();
+    overrides.insert(format!("{:?}", token), json!({ "stateDiff": state_diff }));
+    overrides
+}
+
+// Writes a marker value into candidate slots until balanceOf returns it
+async fn find_balance_slot(provider: &Provider<Http>, token: &Address, holder: &Address) -> Option<u64> {
+    let marker = U256::from(0x5151_5151u64);
+    for slot in 0..MAX_SLOT_PROBE {
+        let overrides = token_overrides(token, &[(balance_storage_key(holder, slot), marker)]);
+        let output = eth_call_with_overrides(
+            provider,
+            None,
+            token,
+            &encode_call("balanceOf(address)", &[Token::Address(*holder)]),
+            Value::Object(overrides),
+        )
+        .await
+        .ok()?;
+        if output.len() >= 32 && U256::from_big_endian(&output[..32]) == marker {
+            return Some(slot);
+        }
+    }
+    None
+}
+
+async fn find_allowance_key(
+    provider: &Provider<Http>,
+    token: &Address,
+    owner: &Address,
+    spender: &Address,
+) -> Option<H256> {
+    let marker = U256::from(0x5151_5151u64);
+    for slot in 0..MAX_SLOT_PROBE {
+        let key = allowance_storage_key(owner, spender, slot);
+        let overrides = token_overrides(token, &[(key, marker)]);
+        let output = eth_call_with_overrides(
+            provider,
+            None,
+            token,
+            &encode_call("allowance(address,address)", &[Token::Address(*owner), Token::Address(*spender)]),
+            Value::Object(overrides),
+        )
+        .await
+        .ok()?;
+        if output.len() >= 32 && U256::from_big_endian(&output[..32]) == marker {
+            return Some(key);
+        }
+    }
+    None
+}
+
+/// The ERC-20 worth probing for this call: the target itself for approve/transfer-like calls,
+/// or the token the sender receives in a swap/buy according to the simulation.
+pub fn honeypot_candidate(
+    function_name: &str,
+    target: &Address,
+    simulation: Option<&SimulationReport>,
+) -> Option<Address> {
+    const TOKEN_FUNCTIONS: [&str; 5] = ["approve", "increaseAllowance", "transfer", "transferFrom", "permit"];
+    if TOKEN_FUNCTIONS.contains(&function_name) {
+        return Some(*target);
+    }
+
+    let lower_name = function_name.to_lowercase();
+    if !(lower_name.starts_with("swap") || lower_name.contains("buy")) {
+        return None;
+    }
+    let simulation = simulation?;
+    simulation
+        .asset_changes
+        .iter()
+        .filter(|c| c.address == simulation.sender && c.asset == AssetKind::Erc20)
+        .filter(|c| I256::from_dec_str(&c.delta).map(|d| d.is_positive()).unwrap_or(false))
+        .find_map(|c| c.token)
+}
@@ -0,0 +1,283 @@
+use ethabi::{Contract, Token};
+use log::warn;
+
+use crate::rules::{Finding, Severity};
+use crate::source::VerifiedSource;
+
+// Delimiters around contract-controlled text in the prompt; the system message tells the model what they mean
+pub const UNTRUSTED_OPEN: &str = "<untrusted>";
+pub const UNTRUSTED_CLOSE: &str = "</untrusted>";
+
+// Findings beyond this are only logged, a single contract can repeat the same comment many times
+const MAX_INJECTION_FINDINGS: usize = 5;
+// Characters of the offending text quoted in a finding
+const EXCERPT_CHARS: usize = 120;
+
+// "ignore ... instructions": a verb followed within a few words by what it asks to drop
+const OVERRIDE_VERBS: &[&str] = &["ignore", "disregard", "forget"];
+const OVERRIDE_TARGETS: &[&str] = &["instruction", "instructions", "prompt", "prompts", "rules", "guidelines", "directions"];
+// "reply low risk": a verb followed within a few words by a benign verdict
+const STEERING_VERBS: &[&str] = &["reply", "respond", "answer", "output", "return", "classify", "rate", "report", "mark"];
+const BENIGN_WORDS: &[&str] = &["low", "safe", "benign", "harmless", "legitimate"];
+const VERDICT_WORDS: &[&str] = &["risk", "verdict", "severity"];
+// Addressed to a model rather than to a developer (matched on the normalized words)
+const ROLE_PHRASES: &[&str] = &[
+    "system prompt",
+    "you are now",
+    "new instructions",
+    "developer mode",
+    "as an ai",
+    "language model",
+    "ai assistant",
+];
+// Chat template markup and forged delimiters (matched on the lowercased raw text)
+const MARKUP: &[&str] = &["<|im_start|>", "<|im_end|>", "[inst]", "[/inst]", "### instruction", "assistant:", "system:", "<untrusted", "</untrusted"];
+
+/// Wraps contract-controlled text in the untrusted delimiters. Delimiters already inside the text
+/// are defused, so a string cannot close the block early and pose as trusted prompt text.
+pub fn quote_untrusted(text: &str) -> String {
+    format!("{}{}{}", UNTRUSTED_OPEN, defuse_delimiters(text), UNTRUSTED_CLOSE)
+}
+
+fn defuse_delimiters(text: &str) -> String {
+    // ASCII lowercasing keeps the byte offsets of the original text
+    let lower = text.to_ascii_lowercase();
+    let mut defused = String::with_capacity(text.len());
+    for (index, c) in text.char_indices() {
+        let rest = &lower[index..];
+        if c == '<' && (rest.starts_with("<untrusted") || rest.starts_with("</untrusted")) {
+            defused.push('‹');
+        } else {
+            defused.push(c);
+        }
+    }
+    defused
+}
+
+/// Splits identifiers and prose into lowercase words: `ignorePreviousInstructions` and
+/// "Ignore previous instructions!" both become `ignore previous instructions`.
+fn words(text: &str) -> Vec<String> {
+    let mut spaced = String::with_capacity(text.len() + 8);
+    let mut previous: Option<char> = None;
+    for c in text.chars() {
+        if !c.is_alphanumeric() {
+            spaced.push(' ');
+        } else {
+            if c.is_uppercase() && previous.map(|p| p.is_lowercase() || p.is_ascii_digit()).unwrap_or(false) {
+                spaced.push(' ');
+            }
+            spaced.extend(c.to_lowercase());
+        }
+        previous = Some(c);
+    }
+    spaced.split_whitespace().map(str::to_string).collect()
+}
+
+fn followed_within(words: &[String], verbs: &[&str], targets: &[&str], distance: usize) -> bool {
+    words.iter().enumerate().any(|(index, word)| {
+        verbs.contains(&word.as_str())
+            && words[index + 1..]
+                .iter()
+                .take(distance)
+                .any(|next| targets.contains(&next.as_str()))
+    })
+}
+
+/// The kind of instruction-like content in `text`, if any.
+pub fn detect_injection(text: &str) -> Option<&'static str> {
+    let lower = text.to_lowercase();
+    if MARKUP.iter().any(|markup| lower.contains(markup)) {
+        return Some("chat markup or forged delimiters");
+    }
+
+    let words = words(text);
+    let joined = format!(" {} ", words.join(" "));
+    if followed_within(&words, OVERRIDE_VERBS, OVERRIDE_TARGETS, 4) {
+        return Some("instruction override");
+    }
+    if ROLE_PHRASES.iter().any(|phrase| joined.contains(&format!(" {} ", phrase))) {
+        return Some("text addressed to the model");
+    }
+    if joined.contains(" risk level low ") || joined.contains(" risk level none ") {
+        return Some("verdict injection");
+    }
+    if followed_within(&words, STEERING_VERBS, BENIGN_WORDS, 4)
+        && words.iter().any(|word| VERDICT_WORDS.contains(&word.as_str()))
+    {
+        return Some("verdict steering");
+    }
+    None
+}
+
+/// Comments and string literals of a source file with their 1-based line, the parts of the code
+/// that are free text. Vyper also uses `#` comments.
+pub fn source_text_fragments(code: &str, vyper: bool) -> Vec<(usize, String)> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut fragments = Vec::new();
+    let mut line = 1;
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        let next = chars.get(index + 1).copied();
+        let start_line = line;
+        let (end, text_start, text_end) = if (c == '/' && next == Some('/')) || (vyper && c == '#') {
+            let end = chars[index..].iter().position(|&c| c == '\n').map(|p| index + p).unwrap_or(chars.len());
+            (end, index + if c == '#' { 1 } else { 2 }, end)
+        } else if c == '/' && next == Some('*') {
+            let close = (index + 2..chars.len().saturating_sub(1)).find(|&i| chars[i] == '*' && chars[i + 1] == '/');
+            match close {
+                Some(close) => (close + 2, index + 2, close),
+                None => (chars.len(), index + 2, chars.len()),
+            }
+        } else if c == '"' || c == '\'' {
+            let mut end = index + 1;
+            while end < chars.len() && chars[end] != c && chars[end] != '\n' {
+                end += if chars[end] == '\\' { 2 } else { 1 };
+            }
+            let end = end.min(chars.len());
+            (end + 1, index + 1, end)
+        } else {
+            if c == '\n' {
+                line += 1;
+            }
+            index += 1;
+            continue;
+        };
+
+        let end = end.min(chars.len());
+        let text: String = chars[text_start.min(end)..text_end.min(end)].iter().collect();
+        line += chars[index..end].iter().filter(|&&c| c == '\n').count();
+        if text.trim().chars().any(char::is_alphabetic) {
+            fragments.push((start_line, text.trim().to_string()));
+        }
+        index = end;
+    }
+    fragments
+}
+
+// A contract-controlled string that ends up in the prompt, and where it came from
+#[derive(Debug, Clone)]
+pub struct UntrustedText {
+    pub location: String,                // e.g. "ABI function name", "argument 1"
+    pub source: Option<(String, usize)>, // File and line for source comments and string literals
+    pub text: String,
+}
+
+impl UntrustedText {
+    pub fn new(location: &str, text: impl Into<String>) -> Self {
+        UntrustedText {
+            location: location.to_string(),
+            source: None,
+            text: text.into(),
+        }
+    }
+}
+
+/// Names declared by an ABI: functions, events, errors and their parameters.
+pub fn abi_texts(contract: &Contract) -> Vec<UntrustedText> {
+    let mut texts = Vec::new();
+    for function in contract.functions() {
+        texts.push(UntrustedText::new("ABI function name", &function.name));
+        for param in function.inputs.iter().chain(function.outputs.iter()) {
+            texts.push(UntrustedText::new("ABI parameter name", &param.name));
+        }
+    }
+    for event in contract.events() {
+        texts.push(UntrustedText::new("ABI event name", &event.name));
+        for param in &event.inputs {
+            texts.push(UntrustedText::new("ABI parameter name", &param.name));
+        }
+    }
+    for error in contract.errors() {
+        texts.push(UntrustedText::new("ABI error name", &error.name));
+    }
+    texts
+}
+
+/// Strings inside the decoded arguments, including the ones nested in arrays and tuples.
+pub fn argument_texts(tokens: &[Token]) -> Vec<UntrustedText> {
+    fn collect(token: &Token, location: &str, texts: &mut Vec<UntrustedText>) {
+        match token {
+            Token::String(text) => texts.push(UntrustedText::new(location, text)),
+            Token::Bytes(bytes) => {
+                if let Ok(text) = std::str::from_utf8(bytes) {
+                    texts.push(UntrustedText::new(location, text));
+                }
+            }
+            Token::Array(items) | Token::FixedArray(items) | Token::Tuple(items) => {
+                for item in items {
+                    collect(item, location, texts);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut texts = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        collect(token, &format!("argument {}", index), &mut texts);
+    }
+    texts
+}
+
+/// Contract name, file paths, comments and string literals of the verified source.
+pub fn source_texts(source: &VerifiedSource) -> Vec<UntrustedText> {
+    let vyper = source.language.eq_ignore_ascii_case("vyper");
+    let mut texts = vec![UntrustedText::new("verified contract name", &source.contract_name)];
+    for (file, code) in &source.files {
+        texts.push(UntrustedText::new("source file path", file));
+        for (line, text) in source_text_fragments(code, vyper) {
+            texts.push(UntrustedText {
+                location: "source comment or string".to_string(),
+                source: Some((file.clone(), line)),
+                text,
+            });
+        }
+    }
+    texts
+}
+
+/// One High finding per untrusted text with instruction-like content.
+pub fn injection_findings(texts: &[UntrustedText]) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+    let mut seen: Vec<&str> = Vec::new();
+    let mut total = 0;
+
+    for untrusted in texts {
+        if seen.contains(&untrusted.text.as_str()) {
+            continue;
+        }
+        let Some(kind) = detect_injection(&untrusted.text) else {
+            continue;
+        };
+        seen.push(&untrusted.text);
+        total += 1;
+        if findings.len() >= MAX_INJECTION_FINDINGS {
+            continue;
+        }
+
+        // The offending text is quoted, since findings are part of the prompt too
+        let excerpt: String = untrusted.text.chars().take(EXCERPT_CHARS).collect();
+        let finding = Finding::new(
+            "prompt_injection.instruction_like",
+            Severity::High,
+            "Prompt injection attempt",
+            format!(
+                "The {} contains text aimed at the analysis model ({}): {}. Legitimate contracts have no reason to address it",
+                untrusted.location,
+                kind,
+                quote_untrusted(&excerpt)
+            ),
+        );
+        findings.push(match &untrusted.source {
+            Some((file, line)) => finding.in_source(file, *line, *line),
+            None => finding.at(untrusted.location.clone()),
+        });
+    }
+
+    if total > 0 {
+        warn!("🛡️ {} prompt injection attempt(s) in the contract data", total);
+    }
+    findings
+}
@@ -1,25 +1,130 @@
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::abi_diff::ContractDiff;
+use crate::analysis_cache::AnalysisCache;
+use crate::bytecode::BytecodeReport;
+use crate::config::PromptConfigStore;
+use crate::contract_risk::ContractRiskProfile;
+use crate::ensemble::{EnsemblePolicy, EnsembleReport};
+use crate::hidden_functions::AbiConsistencyReport;
+use crate::history::AddressHistoryStore;
+use crate::llm::ProviderKind;
+use crate::honeypot::HoneypotReport;
+use crate::ownership::OwnershipReport;
+use crate::profile::ContractProfile;
+use crate::prompt_template::{PromptTemplate, PromptVersion};
+use crate::reputation::ReputationStore;
+use crate::resilience::{CircuitBreakers, VerdictSource};
+use crate::rules::Finding;
+use crate::simulation::SimulationReport;
+use crate::source::SourceExcerpt;
+use crate::token_metadata::TokenAmount;
+use crate::valuation::ValueAtRisk;
+use crate::verdict::{LlmVerdict, UnparseableOutput};
+use crate::wallet_exposure::WalletExposure;
+
+// Shared state handed to every handler through web::Data
+pub struct AppState {
+    pub reputation: Arc<ReputationStore>,
+    pub history: Arc<AddressHistoryStore>,
+    pub prompt_config: Arc<PromptConfigStore>,
+    pub analysis_cache: Arc<AnalysisCache>,
+    pub llm_breakers: Arc<CircuitBreakers>,
+}
 
 // Struct for the prompt configuration
 #[derive(Deserialize)]
 pub struct PromptConfig {
     pub system_message: String,
-    pub user_prompt_template: String,
-    pub response_format: ResponseFormat,
+    pub prompt_templates: BTreeMap<String, PromptTemplate>, // "default" plus optional approval, transfer, admin, upgrade
     pub model_settings: ModelSettings,
+    #[serde(default = "default_source_token_budget")]
+    pub source_token_budget: usize, // Approximate tokens of verified source sent to the model
+    #[serde(default = "default_max_output_attempts")]
+    pub max_output_attempts: usize, // Responses rejected by the schema validation before giving up
+    #[serde(default)]
+    pub fallback_model_settings: Option<ModelSettings>, // Used while the primary provider is failing
+    #[serde(default)]
+    pub resilience: ResilienceSettings,
+    #[serde(default)]
+    pub ensemble: EnsembleSettings,
 }
 
-#[derive(Deserialize)]
-pub struct ResponseFormat {
-    pub risk_level_prefix: String,
-    pub explanation_prefix: String,
+fn default_source_token_budget() -> usize {
+    1500
 }
 
-#[derive(Deserialize)]
+fn default_max_output_attempts() -> usize {
+    3
+}
+
+// Deadlines, retries and circuit breaker of the LLM calls
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ResilienceSettings {
+    pub attempt_timeout_secs: u64, // One HTTP call to the provider
+    pub total_timeout_secs: u64,   // Everything spent on a verdict, across retries and providers
+    pub max_retries: u32,          // Extra attempts on 429, 5xx and timeouts
+    pub retry_backoff_ms: u64,     // Base of the jittered exponential backoff
+    pub breaker_failure_threshold: u32, // Failed verdict requests in a row that open the circuit
+    pub breaker_cooldown_secs: u64,
+    pub rules_only_fallback: bool, // Answer from the local findings when no model is available
+}
+
+impl Default for ResilienceSettings {
+    fn default() -> Self {
+        ResilienceSettings {
+            attempt_timeout_secs: 60,
+            total_timeout_secs: 150,
+            max_retries: 2,
+            retry_backoff_ms: 500,
+            breaker_failure_threshold: 3,
+            breaker_cooldown_secs: 60,
+            rules_only_fallback: true,
+        }
+    }
+}
+
+// Optional multi-model mode: the same prompt goes to every member and the levels are aggregated
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct EnsembleSettings {
+    pub enabled: bool,
+    pub models: Vec<ModelSettings>, // Empty: the primary model_settings
+    pub samples: usize,             // Requests per model, with distinct seeds
+    pub policy: EnsemblePolicy,     // "majority" or "most_severe"
+    pub min_agreement: f64,         // Below this share of matching votes the verdict needs human review
+}
+
+impl Default for EnsembleSettings {
+    fn default() -> Self {
+        EnsembleSettings {
+            enabled: false,
+            models: Vec::new(),
+            samples: 1,
+            policy: EnsemblePolicy::MostSevere,
+            min_agreement: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
 pub struct ModelSettings {
     pub model: String,
     pub stream: bool,
+    #[serde(default)]
+    pub provider: ProviderKind, // deepseek, openai_compatible, anthropic, ollama or llama_cpp
+    #[serde(default)]
+    pub base_url: Option<String>, // Overrides the provider's default endpoint
+    #[serde(default)]
+    pub api_key_env: Option<String>, // Environment variable holding the key, e.g. "OPENAI_API_KEY"
+    #[serde(default)]
+    pub auth_header: Option<String>, // "Authorization" sends "Bearer <key>", any other header the bare key
+    #[serde(default)]
+    pub options: Map<String, Value>, // Extra request fields, e.g. temperature or max_tokens
 }
 
 // Struct for the incoming JSON request of the /decode endpoint
@@ -35,6 +140,8 @@ pub struct DecodeResponse {
     pub status: String, // "success" or "error"
     pub function_name: Option<String>,
     pub arguments: Option<Vec<String>>, // Represent arguments as strings for simplicity
+    pub formatted_arguments: Option<Vec<String>>, // Arguments with token amounts in readable units
+    pub token_amounts: Option<Vec<TokenAmount>>,  // Amounts detected among the arguments
     pub message: Option<String>,
     pub details: Option<String>, // For additional error info
     pub abi: Option<Value>,      // Include ABI in successful response for analysis endpoint
@@ -45,18 +152,100 @@ pub struct DecodeResponse {
 pub struct AnalysisRequest {
     pub contract_address: String,
     pub call_data: String,
+    #[serde(default)]
+    pub from: Option<String>, // Sender wallet, used for the per-wallet address history
+    #[serde(default)]
+    pub value: Option<String>, // Native value in wei (decimal or 0x hex)
 }
 
 // Struct for the outgoing JSON response of the /analysis endpoint
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
 pub struct AnalysisResponse {
     pub status: String,                 // "success" or "error"
     pub function_name: Option<String>,  // Include decoded function name
     pub arguments: Option<Vec<String>>, // Include decoded arguments
+    pub formatted_arguments: Option<Vec<String>>, // Arguments with token amounts in readable units
+    pub token_amounts: Option<Vec<TokenAmount>>,  // Amounts detected among the arguments, with token metadata
+    pub ownership: Option<OwnershipReport>, // Owner and upgrade control of the target
+    pub upgrade_diff: Option<ContractDiff>, // Current vs new implementation for upgrade calls
     pub risk_level: Option<String>,     // e.g., "Low", "Medium", "High", "Caution", "Unknown"
     pub explanation: Option<String>,    // Explanation from the LLM
     pub message: Option<String>,
     pub details: Option<String>, // For additional error info
+    pub findings: Option<Vec<Finding>>, // Deterministic findings from the local checks
+    pub risk_score: Option<u32>,        // 0-100 score accumulated from the findings
+    pub simulation: Option<SimulationReport>, // Asset changes from the RPC simulation
+    pub bytecode: Option<BytecodeReport>,     // Static analysis of the runtime code
+    pub abi_consistency: Option<AbiConsistencyReport>, // Bytecode selectors vs published ABI
+    pub contract_profile: Option<ContractProfile>,     // Age, deployer, verification, activity
+    pub source_excerpts: Option<Vec<SourceExcerpt>>,   // Verified source sent to the model
+    pub honeypot: Option<HoneypotReport>,              // Buy/sell/transfer probes of the ERC-20 involved
+    pub value_at_risk: Option<ValueAtRisk>,            // USD value of the transfers and approvals
+    pub wallet_exposure: Option<WalletExposure>,       // Sender balances and allowances for the tokens involved
+    pub verdict: Option<LlmVerdict>,                   // Validated JSON verdict of the model
+    pub unparseable: Option<UnparseableOutput>,        // Set when no response passed validation
+    pub prompt_version: Option<PromptVersion>,         // Template and version the prompt was rendered from
+    pub cache_hit: Option<bool>,                       // true when the verdict came from the analysis cache
+    pub verdict_source: Option<VerdictSource>,         // Primary or fallback model, ensemble, rules only, or cache
+    pub ensemble: Option<EnsembleReport>,              // Votes and agreement in ensemble mode
+}
+
+// Struct for the outgoing JSON response of DELETE /admin/analysis-cache/{contract_address}
+#[derive(Serialize)]
+pub struct CacheInvalidationResponse {
+    pub status: String, // "success" or "error"
+    pub contract_address: Option<String>,
+    pub removed: Option<usize>, // Cached verdicts dropped
+    pub message: Option<String>,
+}
+
+// Struct for the incoming JSON request of the /history/confirm endpoint
+#[derive(Deserialize)]
+pub struct TransactionConfirmationRequest {
+    pub tx_hash: String,
+}
+
+// Struct for the outgoing JSON response of the /history/confirm endpoint
+#[derive(Serialize)]
+pub struct TransactionConfirmationResponse {
+    pub status: String, // "success" or "error"
+    pub wallet: Option<String>,
+    pub recorded: Option<usize>, // Counterparties added to the wallet history
+    pub message: Option<String>,
+}
+
+// Struct for the incoming JSON request of the /abi-diff endpoint
+#[derive(Deserialize)]
+pub struct AbiDiffRequest {
+    pub old_address: String, // e.g. the current implementation
+    pub new_address: String, // e.g. the implementation an upgrade installs
+}
+
+// Struct for the outgoing JSON response of the /abi-diff endpoint
+#[derive(Serialize)]
+pub struct AbiDiffResponse {
+    pub status: String, // "success" or "error"
+    pub diff: Option<ContractDiff>,
+    pub findings: Option<Vec<Finding>>,
+    pub message: Option<String>,
+    pub details: Option<String>,
+}
+
+// Struct for the incoming JSON request of the /contract-profile endpoint
+#[derive(Deserialize)]
+pub struct ContractRiskProfileRequest {
+    pub contract_address: String,
+    #[serde(default)]
+    pub refresh: bool, // Rebuild even when a fresh cached profile exists
+}
+
+// Struct for the outgoing JSON response of the /contract-profile endpoint
+#[derive(Serialize)]
+pub struct ContractRiskProfileResponse {
+    pub status: String, // "success" or "error"
+    pub profile: Option<ContractRiskProfile>,
+    pub message: Option<String>,
+    pub details: Option<String>,
 }
 
 // Struct for the incoming JSON request of the /chainlink-audit endpoint
@@ -81,6 +270,34 @@ pub struct ChainlinkAuditResponse {
 
 // Module declarations
 pub mod abi;
+pub mod abi_diff;
+pub mod analysis_cache;
+pub mod bytecode;
 pub mod config;
+pub mod contract_risk;
 pub mod decode;
+pub mod ensemble;
+pub mod eval;
 pub mod handlers;
+pub mod hidden_functions;
+pub mod history;
+pub mod honeypot;
+pub mod injection;
+pub mod llm;
+pub mod ownership;
+pub mod poisoning;
+pub mod profile;
+pub mod prompt_template;
+pub mod proxy;
+pub mod reputation;
+pub mod resilience;
+pub mod rpc;
+pub mod rules;
+pub mod simulation;
+pub mod source;
+pub mod source_scan;
+pub mod sse;
+pub mod token_metadata;
+pub mod valuation;
+pub mod verdict;
+pub mod wallet_exposure;
@@ -0,0 +1,481 @@
+use async_trait::async_trait;
+use log::info;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::Client;
+use std::sync::OnceLock;
+use std::time::Duration;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::env;
+use std::fmt;
+
+use crate::ModelSettings;
+
+// Anthropic Messages API version sent in the `anthropic-version` header
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// Anthropic requires max_tokens; used when `options` does not set it
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u64 = 4096;
+// Deadlines for a whole attempt are set per request in `resilience`
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    #[default]
+    Deepseek,
+    OpenaiCompatible,
+    Anthropic,
+    Ollama,
+    LlamaCpp,
+}
+
+impl ProviderKind {
+    fn label(&self) -> &'static str {
+        match self {
+            ProviderKind::Deepseek => "DeepSeek",
+            ProviderKind::OpenaiCompatible => "OpenAI-compatible",
+            ProviderKind::Anthropic => "Anthropic",
+            ProviderKind::Ollama => "Ollama",
+            ProviderKind::LlamaCpp => "llama.cpp",
+        }
+    }
+
+    fn default_base_url(&self) -> &'static str {
+        match self {
+            ProviderKind::Deepseek => "https://api.deepseek.com",
+            ProviderKind::OpenaiCompatible => "https://api.openai.com/v1",
+            ProviderKind::Anthropic => "https://api.anthropic.com/v1",
+            ProviderKind::Ollama => "http://127.0.0.1:11434",
+            // llama-server listens on 8080 by default, which this backend already uses
+            ProviderKind::LlamaCpp => "http://127.0.0.1:8081/v1",
+        }
+    }
+
+    // Environment variable holding the API key; local servers need none
+    fn default_api_key_env(&self) -> Option<&'static str> {
+        match self {
+            ProviderKind::Deepseek => Some("DEEPSEEK_API_KEY"),
+            ProviderKind::OpenaiCompatible => Some("OPENAI_API_KEY"),
+            ProviderKind::Anthropic => Some("ANTHROPIC_API_KEY"),
+            ProviderKind::Ollama | ProviderKind::LlamaCpp => None,
+        }
+    }
+
+    fn default_auth_header(&self) -> &'static str {
+        match self {
+            ProviderKind::Anthropic => "x-api-key",
+            _ => "Authorization",
+        }
+    }
+
+    // Hosted APIs reject unauthenticated calls, so a missing key is a configuration error
+    fn requires_api_key(&self) -> bool {
+        matches!(self, ProviderKind::Deepseek | ProviderKind::Anthropic)
+    }
+}
+
+#[derive(Debug)]
+pub enum LlmError {
+    Config(String),                       // Missing key, invalid URL or header
+    Request(String),                      // The endpoint could not be reached
+    Status { status: u16, body: String }, // Non-success HTTP status
+    Parse(String),                        // Unexpected response shape
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Config(e) => write!(f, "configuration error: {}", e),
+            LlmError::Request(e) => write!(f, "request failed: {}", e),
+            LlmError::Status { status, body } => write!(f, "HTTP status {}: {}", status, body),
+            LlmError::Parse(e) => write!(f, "unexpected response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+/// A chat model that turns the system message and the analysis prompt into text.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Human-readable name used in logs and error messages, e.g. "DeepSeek".
+    fn name(&self) -> &str;
+
+    /// Model identifier sent to the API, e.g. "deepseek-chat".
+    fn model(&self) -> &str;
+
+    async fn complete(&self, system_message: &str, prompt: &str) -> Result<String, LlmError>;
+
+    /// Like `complete`, asking for output that follows the JSON schema where the API supports it.
+    /// Providers without structured output rely on the schema given in the prompt.
+    async fn complete_json(&self, system_message: &str, prompt: &str, _schema: &Value) -> Result<String, LlmError> {
+        self.complete(system_message, prompt).await
+    }
+
+    /// Like `complete_json`, handing every token to `on_token` as it arrives. Returns the whole text.
+    async fn stream_json(
+        &self,
+        system_message: &str,
+        prompt: &str,
+        schema: &Value,
+        on_token: &(dyn for<'t> Fn(&'t str) + Send + Sync),
+    ) -> Result<String, LlmError> {
+        let content = self.complete_json(system_message, prompt, schema).await?;
+        on_token(&content);
+        Ok(content)
+    }
+}
+
+// Shared endpoint settings resolved from `ModelSettings`
+struct Endpoint {
+    kind: ProviderKind,
+    label: &'static str,
+    client: Client,
+    url: String,
+    headers: HeaderMap,
+    model: String,
+    stream: bool, // Stream even when the caller only wants the final text
+    options: Map<String, Value>,
+}
+
+impl Endpoint {
+    async fn send(&self, body: &Value) -> Result<reqwest::Response, LlmError> {
+        info!("📤 Sending request to {} ({}) - Model: {}", self.label, self.url, self.model);
+        let response = self
+            .client
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| LlmError::Request(e.to_string()))?;
+
+        let status = response.status();
+        info!("📥 {} response - Status: {}", self.label, status);
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(LlmError::Status {
+                status: status.as_u16(),
+                body: text,
+            });
+        }
+        Ok(response)
+    }
+
+    async fn post(&self, body: &Value) -> Result<Value, LlmError> {
+        let text = self
+            .send(body)
+            .await?
+            .text()
+            .await
+            .map_err(|e| LlmError::Request(e.to_string()))?;
+        serde_json::from_str(&text).map_err(|e| LlmError::Parse(e.to_string()))
+    }
+
+    /// Reads a Server-Sent Events (`data: {...}`) or newline-delimited JSON body line by line.
+    async fn post_streaming(
+        &self,
+        body: &Value,
+        token_of: fn(&Value) -> Option<&str>,
+        on_token: &(dyn Fn(&str) + Send + Sync),
+    ) -> Result<String, LlmError> {
+        let mut response = self.send(body).await?;
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut content = String::new();
+
+        while let Some(chunk) = response.chunk().await.map_err(|e| LlmError::Request(e.to_string()))? {
+            buffer.extend_from_slice(&chunk);
+            // Only complete lines are parsed, so multi-byte characters split across chunks stay intact
+            while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=newline).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim();
+                if line.is_empty() || line.starts_with("event:") || line.starts_with(':') {
+                    continue;
+                }
+                let data = line.strip_prefix("data:").map(str::trim).unwrap_or(line);
+                if data == "[DONE]" {
+                    continue;
+                }
+                let event: Value = serde_json::from_str(data).map_err(|e| LlmError::Parse(e.to_string()))?;
+                if let Some(error) = event.get("error").filter(|error| !error.is_null()) {
+                    return Err(LlmError::Parse(format!("error event in stream: {}", error)));
+                }
+                if let Some(token) = token_of(&event).filter(|token| !token.is_empty()) {
+                    content.push_str(token);
+                    on_token(token);
+                }
+            }
+        }
+        Ok(content)
+    }
+
+    // Per-provider options are merged into the top level of the request body
+    fn with_options(&self, mut body: Value) -> Value {
+        if let Some(object) = body.as_object_mut() {
+            for (key, value) in &self.options {
+                object.insert(key.clone(), value.clone());
+            }
+        }
+        body
+    }
+}
+
+// Request and response shape of one API family
+trait WireFormat: Send + Sync {
+    fn body(&self, endpoint: &Endpoint, system_message: &str, prompt: &str, schema: Option<&Value>, stream: bool)
+        -> Value;
+    fn content_of(&self, response: &Value) -> Result<String, LlmError>;
+    fn token_of(event: &Value) -> Option<&str>;
+}
+
+/// DeepSeek, OpenAI and llama.cpp's server all speak `/chat/completions`.
+struct ChatCompletions;
+
+impl WireFormat for ChatCompletions {
+    fn body(&self, endpoint: &Endpoint, system_message: &str, prompt: &str, schema: Option<&Value>, stream: bool) -> Value {
+        let mut body = endpoint.with_options(json!({
+            "model": endpoint.model,
+            "messages": [
+                {"role": "system", "content": system_message},
+                {"role": "user", "content": prompt}
+            ],
+            "stream": stream
+        }));
+        // DeepSeek only offers JSON mode; OpenAI and llama.cpp's server accept the schema itself
+        if let Some(schema) = schema {
+            body["response_format"] = match endpoint.kind {
+                ProviderKind::Deepseek => json!({"type": "json_object"}),
+                _ => json!({
+                    "type": "json_schema",
+                    "json_schema": {"name": "risk_verdict", "schema": schema}
+                }),
+            };
+        }
+        body
+    }
+
+    fn content_of(&self, response: &Value) -> Result<String, LlmError> {
+        response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|content| content.to_string())
+            .ok_or_else(|| LlmError::Parse(format!("no choices[0].message.content in {}", response)))
+    }
+
+    fn token_of(event: &Value) -> Option<&str> {
+        event["choices"][0]["delta"]["content"].as_str()
+    }
+}
+
+/// Anthropic Messages API: the system message is a top-level field and the text comes in content blocks.
+struct AnthropicMessages;
+
+impl WireFormat for AnthropicMessages {
+    fn body(&self, endpoint: &Endpoint, system_message: &str, prompt: &str, _schema: Option<&Value>, stream: bool) -> Value {
+        endpoint.with_options(json!({
+            "model": endpoint.model,
+            "system": system_message,
+            "messages": [{"role": "user", "content": prompt}],
+            "max_tokens": ANTHROPIC_DEFAULT_MAX_TOKENS,
+            "stream": stream
+        }))
+    }
+
+    fn content_of(&self, response: &Value) -> Result<String, LlmError> {
+        let blocks = response["content"]
+            .as_array()
+            .ok_or_else(|| LlmError::Parse(format!("no content blocks in {}", response)))?;
+        let text: Vec<&str> = blocks
+            .iter()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect();
+        if text.is_empty() {
+            return Err(LlmError::Parse(format!("no text block in {}", response)));
+        }
+        Ok(text.join(""))
+    }
+
+    fn token_of(event: &Value) -> Option<&str> {
+        match event["type"].as_str() {
+            Some("content_block_delta") => event["delta"]["text"].as_str(),
+            _ => None,
+        }
+    }
+}
+
+/// Ollama's native `/api/chat`; model parameters (temperature, num_ctx, ...) go under `options`.
+struct OllamaChat;
+
+impl WireFormat for OllamaChat {
+    fn body(&self, endpoint: &Endpoint, system_message: &str, prompt: &str, schema: Option<&Value>, stream: bool) -> Value {
+        let mut body = json!({
+            "model": endpoint.model,
+            "messages": [
+                {"role": "system", "content": system_message},
+                {"role": "user", "content": prompt}
+            ],
+            "stream": stream,
+            "options": endpoint.options
+        });
+        if let Some(schema) = schema {
+            body["format"] = schema.clone();
+        }
+        body
+    }
+
+    fn content_of(&self, response: &Value) -> Result<String, LlmError> {
+        response["message"]["content"]
+            .as_str()
+            .map(|content| content.to_string())
+            .ok_or_else(|| LlmError::Parse(format!("no message.content in {}", response)))
+    }
+
+    fn token_of(event: &Value) -> Option<&str> {
+        event["message"]["content"].as_str()
+    }
+}
+
+struct HttpProvider<F: WireFormat> {
+    endpoint: Endpoint,
+    format: F,
+}
+
+impl<F: WireFormat> HttpProvider<F> {
+    async fn run(
+        &self,
+        system_message: &str,
+        prompt: &str,
+        schema: Option<&Value>,
+        on_token: Option<&(dyn Fn(&str) + Send + Sync)>,
+    ) -> Result<String, LlmError> {
+        match on_token {
+            Some(on_token) => {
+                let body = self.format.body(&self.endpoint, system_message, prompt, schema, true);
+                self.endpoint.post_streaming(&body, F::token_of, on_token).await
+            }
+            None if self.endpoint.stream => {
+                let body = self.format.body(&self.endpoint, system_message, prompt, schema, true);
+                self.endpoint.post_streaming(&body, F::token_of, &|_| {}).await
+            }
+            None => {
+                let body = self.format.body(&self.endpoint, system_message, prompt, schema, false);
+                let response = self.endpoint.post(&body).await?;
+                self.format.content_of(&response)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<F: WireFormat> LlmProvider for HttpProvider<F> {
+    fn name(&self) -> &str {
+        self.endpoint.label
+    }
+
+    fn model(&self) -> &str {
+        &self.endpoint.model
+    }
+
+    async fn complete(&self, system_message: &str, prompt: &str) -> Result<String, LlmError> {
+        self.run(system_message, prompt, None, None).await
+    }
+
+    async fn complete_json(&self, system_message: &str, prompt: &str, schema: &Value) -> Result<String, LlmError> {
+        self.run(system_message, prompt, Some(schema), None).await
+    }
+
+    async fn stream_json(
+        &self,
+        system_message: &str,
+        prompt: &str,
+        schema: &Value,
+        on_token: &(dyn for<'t> Fn(&'t str) + Send + Sync),
+    ) -> Result<String, LlmError> {
+        self.run(system_message, prompt, Some(schema), Some(on_token)).await
+    }
+}
+
+/// One connection pool for every provider and request.
+fn shared_client() -> Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT
+        .get_or_init(|| {
+            Client::builder()
+                .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS))
+                .build()
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+/// Builds the provider selected by `model_settings.provider` in `prompt_config.json`.
+pub fn build_provider(settings: &ModelSettings) -> Result<Box<dyn LlmProvider>, LlmError> {
+    let kind = settings.provider;
+    let base_url = settings
+        .base_url
+        .as_deref()
+        .unwrap_or(kind.default_base_url())
+        .trim_end_matches('/');
+    let url = match kind {
+        ProviderKind::Deepseek | ProviderKind::OpenaiCompatible | ProviderKind::LlamaCpp => {
+            format!("{}/chat/completions", base_url)
+        }
+        ProviderKind::Anthropic => format!("{}/messages", base_url),
+        ProviderKind::Ollama => format!("{}/api/chat", base_url),
+    };
+    url::Url::parse(&url).map_err(|e| LlmError::Config(format!("invalid URL {}: {}", url, e)))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if kind == ProviderKind::Anthropic {
+        headers.insert("anthropic-version", HeaderValue::from_static(ANTHROPIC_VERSION));
+    }
+
+    let key_env = settings.api_key_env.as_deref().or(kind.default_api_key_env());
+    let api_key = key_env.and_then(|name| env::var(name).ok()).filter(|key| !key.trim().is_empty());
+    match (api_key, key_env) {
+        (Some(key), _) => {
+            let header_name = settings.auth_header.as_deref().unwrap_or(kind.default_auth_header());
+            let value = if header_name.eq_ignore_ascii_case("authorization") {
+                format!("Bearer {}", key)
+            } else {
+                key
+            };
+            let name = HeaderName::from_bytes(header_name.as_bytes())
+                .map_err(|e| LlmError::Config(format!("invalid auth header {}: {}", header_name, e)))?;
+            let value = HeaderValue::from_str(&value)
+                .map_err(|e| LlmError::Config(format!("invalid API key: {}", e)))?;
+            headers.insert(name, value);
+        }
+        (None, Some(name)) if kind.requires_api_key() => {
+            return Err(LlmError::Config(format!("{} not configured", name)));
+        }
+        _ => {}
+    }
+
+    let endpoint = Endpoint {
+        kind,
+        label: kind.label(),
+        client: shared_client(),
+        url,
+        headers,
+        model: settings.model.clone(),
+        stream: settings.stream,
+        options: settings.options.clone(),
+    };
+    Ok(match kind {
+        ProviderKind::Deepseek | ProviderKind::OpenaiCompatible | ProviderKind::LlamaCpp => Box::new(HttpProvider {
+            endpoint,
+            format: ChatCompletions,
+        }),
+        ProviderKind::Anthropic => Box::new(HttpProvider {
+            endpoint,
+            format: AnthropicMessages,
+        }),
+        ProviderKind::Ollama => Box::new(HttpProvider {
+            endpoint,
+            format: OllamaChat,
+        }),
+    })
+}
@@ -1,8 +1,18 @@
 use actix_web::{web, App, HttpServer};
 use actix_cors::Cors;
-use log::info;
+use log::{error, info};
+use std::sync::Arc;
 
-use rust_backend::handlers::{analysis_handler, chainlink_audit_handler, decode_handler};
+use rust_backend::analysis_cache::AnalysisCache;
+use rust_backend::config::{spawn_prompt_config_reload_task, PromptConfigStore};
+use rust_backend::handlers::{
+    abi_diff_handler, analysis_handler, analysis_stream_handler, chainlink_audit_handler, confirm_transaction_handler,
+    contract_profile_handler, decode_handler, invalidate_analysis_cache_handler,
+};
+use rust_backend::history::AddressHistoryStore;
+use rust_backend::reputation::{spawn_reload_task, ReputationStore};
+use rust_backend::resilience::CircuitBreakers;
+use rust_backend::AppState;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -11,16 +21,51 @@ async fn main() -> std::io::Result<()> {
 
     let server_address = "127.0.0.1:8080";
 
+    let reputation = Arc::new(ReputationStore::from_env());
+    spawn_reload_task(reputation.clone());
+
+    let history = Arc::new(AddressHistoryStore::from_env());
+
+    // An invalid prompt configuration stops the server here instead of failing every analysis
+    let prompt_config = match PromptConfigStore::from_env() {
+        Ok(store) => Arc::new(store),
+        Err(e) => {
+            error!("❌ Invalid prompt configuration: {}", e);
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()));
+        }
+    };
+    spawn_prompt_config_reload_task(prompt_config.clone());
+
+    let analysis_cache = Arc::new(AnalysisCache::from_env());
+    let llm_breakers = Arc::new(CircuitBreakers::new());
+
+    let state = web::Data::new(AppState {
+        reputation,
+        history,
+        prompt_config,
+        analysis_cache,
+        llm_breakers,
+    });
+
     info!("🚀 Server starting on http://{}", server_address);
 
-    HttpServer::new(|| {
+    HttpServer::new(move || {
         let cors = Cors::permissive();
 
         App::new()
             .wrap(cors)
+            .app_data(state.clone())
             .route("/decode", web::post().to(decode_handler))
             .route("/analysis", web::post().to(analysis_handler))
+            .route("/analysis/stream", web::post().to(analysis_stream_handler))
+            .route("/history/confirm", web::post().to(confirm_transaction_handler))
+            .route("/abi-diff", web::post().to(abi_diff_handler))
+            .route("/contract-profile", web::post().to(contract_profile_handler))
             .route("/chainlink-audit", web::post().to(chainlink_audit_handler))
+            .route(
+                "/admin/analysis-cache/{contract_address}",
+                web::delete().to(invalidate_analysis_cache_handler),
+            )
     })
     .bind(server_address)?
     .run()
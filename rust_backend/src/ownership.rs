@@ -0,0 +1,475 @@
+use chrono::{DateTime, TimeZone, Utc};
+use ethabi::{ParamType, Token};
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{Address, BlockNumber, Bytes, H256};
+use ethers::utils::{id, keccak256};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::proxy::{ProxyInfo, ProxyKind};
+use crate::rpc::{call_address, call_uint, encode_call, eth_call, get_code, raw_request};
+use crate::rules::{Finding, Severity};
+
+// How many owner() hops are followed from the first controller
+const MAX_CONTROL_DEPTH: usize = 4;
+// Blocks searched for operations scheduled on a timelock
+const TIMELOCK_LOG_LOOKBACK: u64 = 50_000;
+// Delays under a day leave users little time to exit before an upgrade lands
+const SHORT_TIMELOCK_SECS: u64 = 86_400;
+
+// Upgrade entry points of UUPS/transparent proxies and of the OpenZeppelin ProxyAdmin
+const UPGRADE_SIGNATURES: [&str; 4] = [
+    "upgradeTo(address)",
+    "upgradeToAndCall(address,bytes)",
+    "upgrade(address,address)",
+    "upgradeAndCall(address,address,bytes)",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControllerKind {
+    Eoa,
+    Multisig,
+    Timelock,
+    Contract,  // Any other contract (e.g. a ProxyAdmin), followed through its owner()
+    Renounced, // The zero address
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Controller {
+    pub address: Address,
+    pub kind: ControllerKind,
+    pub threshold: Option<u64>,      // Multisig signatures required
+    pub owners: Option<u64>,         // Multisig signers
+    pub min_delay_secs: Option<u64>, // Timelock delay
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingUpgrade {
+    pub timelock: Address,
+    pub operation_id: H256,
+    pub target: Address,
+    pub new_implementation: Option<Address>,
+    pub ready_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OwnershipReport {
+    pub owner_chain: Vec<Controller>, // owner() of the target, then owner() of each contract in between
+    pub pending_owner: Option<Address>,
+    pub upgradeable: bool,
+    pub upgrade_chain: Vec<Controller>, // Who can replace the implementation, resolved the same way
+    pub pending_upgrades: Vec<PendingUpgrade>,
+}
+
+impl OwnershipReport {
+    /// Whether the upgrade path goes through a timelock (so upgrades cannot land on the next block).
+    pub fn upgrade_delayed(&self) -> bool {
+        self.upgrade_chain.iter().any(|c| c.kind == ControllerKind::Timelock)
+    }
+}
+
+// Subset of an eth_getLogs entry
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LogEntry {
+    #[serde(default)]
+    topics: Vec<H256>,
+    data: Bytes,
+}
+
+/// Resolves who owns the target and who can upgrade it, down to the EOA, multisig or
+/// timelock at the end of the chain, and looks for upgrades queued on a timelock.
+pub async fn analyze_ownership(
+    provider: &Provider<Http>,
+    target: &Address,
+    proxy: Option<&ProxyInfo>,
+) -> Result<OwnershipReport, Box<dyn std::error::Error>> {
+    let owner_chain = match call_address(provider, target, "owner()").await {
+        Ok(owner) => resolve_chain(provider, owner).await,
+        Err(_) => Vec::new(),
+    };
+    let pending_owner = call_address(provider, target, "pendingOwner()")
+        .await
+        .ok()
+        .filter(|pending| !pending.is_zero());
+
+    let upgrade_chain = match proxy {
+        // Transparent proxy: the admin slot holds the upgrader (usually a ProxyAdmin)
+        Some(ProxyInfo { kind: ProxyKind::Eip1967, admin: Some(admin), .. }) => {
+            resolve_chain(provider, *admin).await
+        }
+        Some(ProxyInfo { kind: ProxyKind::Eip1967Beacon, beacon: Some(beacon), .. }) => {
+            resolve_chain(provider, *beacon).await
+        }
+        Some(ProxyInfo { kind: ProxyKind::Eip1967Beacon, .. }) => Vec::new(),
+        // UUPS: the implementation's own access control, usually owner()
+        Some(info) if info.kind != ProxyKind::Eip1167 => owner_chain.clone(),
+        _ => Vec::new(),
+    };
+    let upgradeable = matches!(proxy, Some(info) if info.kind != ProxyKind::Eip1167);
+
+    let mut pending_upgrades = Vec::new();
+    let mut upgrade_targets = vec![*target];
+    upgrade_targets.extend(upgrade_chain.iter().map(|c| c.address));
+    for timelock in upgrade_chain.iter().filter(|c| c.kind == ControllerKind::Timelock) {
+        match pending_timelock_upgrades(provider, &timelock.address, &upgrade_targets).await {
+            Ok(pending) => pending_upgrades.extend(pending),
+            Err(e) => warn!("⚠️ Could not read operations of timelock {:?}: {}", timelock.address, e),
+        }
+    }
+
+    info!(
+        "🔑 Ownership of {:?} - Owner: {:?}, Upgrader: {:?}, Pending upgrades: {}",
+        target,
+        owner_chain.last().map(|c| (c.kind, c.address)),
+        upgrade_chain.last().map(|c| (c.kind, c.address)),
+        pending_upgrades.len()
+    );
+
+    Ok(OwnershipReport {
+        owner_chain,
+        pending_owner,
+        upgradeable,
+        upgrade_chain,
+        pending_upgrades,
+    })
+}
+
+// Follows owner() from contract to contract until an EOA, multisig, timelock or a loop
+async fn resolve_chain(provider: &Provider<Http>, start: Address) -> Vec<Controller> {
+    let mut chain: Vec<Controller> = Vec::new();
+    let mut current = start;
+
+    for _ in 0..MAX_CONTROL_DEPTH {
+        let controller = classify(provider, current).await;
+        let next = if controller.kind == ControllerKind::Contract {
+            call_address(provider, &current, "owner()").await.ok()
+        } else {
+            None
+        };
+        chain.push(controller);
+        match next {
+            Some(next) if !chain.iter().any(|c| c.address == next) => current = next,
+            _ => break,
+        }
+    }
+    chain
+}
+
+async fn classify(provider: &Provider<Http>, address: Address) -> Controller {
+    let mut controller = Controller {
+        address,
+        kind: ControllerKind::Contract,
+        threshold: None,
+        owners: None,
+        min_delay_secs: None,
+    };
+
+    if address.is_zero() {
+        controller.kind = ControllerKind::Renounced;
+        return controller;
+    }
+    match get_code(provider, &address).await {
+        Ok(code) if code.is_empty() => {
+            controller.kind = ControllerKind::Eoa;
+            return controller;
+        }
+        Ok(_) => {}
+        Err(e) => warn!("⚠️ Could not fetch code of controller {:?}: {}", address, e),
+    }
+
+    // Safe-style multisig
+    if let Ok(threshold) = call_uint(provider, &address, "getThreshold()", &[]).await {
+        controller.kind = ControllerKind::Multisig;
+        controller.threshold = Some(threshold.low_u64());
+        controller.owners = multisig_owner_count(provider, &address).await;
+        return controller;
+    }
+
+    // OpenZeppelin TimelockController, then Compound-style Timelock
+    for signature in ["getMinDelay()", "delay()"] {
+        if let Ok(delay) = call_uint(provider, &address, signature, &[]).await {
+            controller.kind = ControllerKind::Timelock;
+            controller.min_delay_secs = Some(delay.low_u64());
+            return controller;
+        }
+    }
+
+    controller
+}
+
+async fn multisig_owner_count(provider: &Provider<Http>, address: &Address) -> Option<u64> {
+    let output = eth_call(provider, address, &encode_call("getOwners()", &[])).await.ok()?;
+    match ethabi::decode(&[ParamType::Array(Box::new(ParamType::Address))], &output)
+        .ok()?
+        .first()
+    {
+        Some(Token::Array(owners)) => Some(owners.len() as u64),
+        _ => None,
+    }
+}
+
+// CallScheduled operations on an OpenZeppelin TimelockController that upgrade one of `targets`
+// and are still pending
+async fn pending_timelock_upgrades(
+    provider: &Provider<Http>,
+    timelock: &Address,
+    targets: &[Address],
+) -> Result<Vec<PendingUpgrade>, Box<dyn std::error::Error>> {
+    let latest = provider.get_block_number().await?.as_u64();
+    let scheduled_topic = H256::from(keccak256(
+        "CallScheduled(bytes32,uint256,address,uint256,bytes,bytes32,uint256)",
+    ));
+    let logs = raw_request(
+        provider,
+        "eth_getLogs",
+        json!([{
+            "address": timelock,
+            "fromBlock": BlockNumber::Number(latest.saturating_sub(TIMELOCK_LOG_LOOKBACK).into()),
+            "toBlock": "latest",
+            "topics": [scheduled_topic],
+        }]),
+    )
+    .await?;
+    let logs: Vec<LogEntry> = serde_json::from_value(logs)?;
+
+    let upgrade_selectors: Vec<[u8; 4]> = UPGRADE_SIGNATURES.iter().map(id).collect();
+    let mut pending = Vec::new();
+
+    for log in logs {
+        let operation_id = match log.topics.get(1) {
+            Some(id) => *id,
+            None => continue,
+        };
+        let decoded = ethabi::decode(
+            &[
+                ParamType::Address,
+                ParamType::Uint(256),
+                ParamType::Bytes,
+                ParamType::FixedBytes(32),
+                ParamType::Uint(256),
+            ],
+            &log.data,
+        );
+        let (target, data) = match decoded.as_deref() {
+            Ok([Token::Address(target), _, Token::Bytes(data), ..]) => (*target, data.clone()),
+            _ => continue,
+        };
+        let selector = match data.get(..4) {
+            Some(selector) => selector,
+            None => continue,
+        };
+        if !targets.contains(&target) || !upgrade_selectors.iter().any(|s| s.as_slice() == selector) {
+            continue;
+        }
+
+        let is_pending = eth_call(
+            provider,
+            timelock,
+            &encode_call("isOperationPending(bytes32)", &[Token::FixedBytes(operation_id.as_bytes().to_vec())]),
+        )
+        .await
+        .map(|output| output.last() == Some(&1))
+        .unwrap_or(false);
+        if !is_pending {
+            continue;
+        }
+
+        let ready_at = call_uint(
+            provider,
+            timelock,
+            "getTimestamp(bytes32)",
+            &[Token::FixedBytes(operation_id.as_bytes().to_vec())],
+        )
+        .await
+        .ok()
+        .and_then(|ts| Utc.timestamp_opt(ts.low_u64() as i64, 0).single());
+
+        pending.push(PendingUpgrade {
+            timelock: *timelock,
+            operation_id,
+            target,
+            new_implementation: new_implementation(&data),
+            ready_at,
+        });
+    }
+
+    Ok(pending)
+}
+
+// The implementation argument of an upgrade call
+fn new_implementation(data: &[u8]) -> Option<Address> {
+    upgrade_call(data, &Address::zero()).map(|(_, implementation)| implementation)
+}
+
+/// For calldata of a recognized upgrade call sent to `target`, returns the proxy being
+/// upgraded and the new implementation.
+pub fn upgrade_call(data: &[u8], target: &Address) -> Option<(Address, Address)> {
+    let (selector, args) = (data.get(..4)?, data.get(4..)?);
+    let position = UPGRADE_SIGNATURES
+        .iter()
+        .position(|signature| id(signature).as_slice() == selector)?;
+    let word = |index: usize| args.get(index * 32..(index + 1) * 32).map(|w| Address::from_slice(&w[12..]));
+    // upgradeTo*(impl, ...) are sent to the proxy itself, ProxyAdmin upgrade*(proxy, impl, ...) name it
+    if position < 2 {
+        Some((*target, word(0)?))
+    } else {
+        Some((word(0)?, word(1)?))
+    }
+}
+
+fn describe_controller(controller: &Controller) -> String {
+    match controller.kind {
+        ControllerKind::Eoa => format!("EOA {:?}", controller.address),
+        ControllerKind::Multisig => format!(
+            "{}-of-{} multisig {:?}",
+            controller.threshold.unwrap_or_default(),
+            controller
+                .owners
+                .map(|o| o.to_string())
+                .unwrap_or_else(|| "?".to_string()),
+            controller.address
+        ),
+        ControllerKind::Timelock => format!(
+            "timelock {:?} ({}h delay)",
+            controller.address,
+            controller.min_delay_secs.unwrap_or_default() / 3600
+        ),
+        ControllerKind::Contract => format!("contract {:?}", controller.address),
+        ControllerKind::Renounced => "renounced (zero address)".to_string(),
+    }
+}
+
+fn describe_chain(chain: &[Controller]) -> String {
+    if chain.is_empty() {
+        return "unknown".to_string();
+    }
+    chain.iter().map(describe_controller).collect::<Vec<_>>().join(" -> ")
+}
+
+/// Turns the control analysis into rule findings.
+pub fn ownership_findings(report: &OwnershipReport) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if report.upgradeable && !report.upgrade_delayed() {
+        match report.upgrade_chain.last() {
+            Some(controller) if controller.kind == ControllerKind::Eoa => findings.push(
+                Finding::new(
+                    "ownership.eoa_upgrader",
+                    Severity::High,
+                    "A single key can upgrade the contract",
+                    format!(
+                        "The implementation can be replaced on the next block by {}; any approval or deposit can be redirected without notice",
+                        describe_chain(&report.upgrade_chain)
+                    ),
+                )
+                .at("upgrade control"),
+            ),
+            Some(controller) if controller.kind == ControllerKind::Multisig && controller.threshold == Some(1) => findings.push(
+                Finding::new(
+                    "ownership.single_signer_upgrader",
+                    Severity::Medium,
+                    "Upgrade multisig needs a single signature",
+                    format!(
+                        "Upgrades are controlled by {} without a timelock",
+                        describe_chain(&report.upgrade_chain)
+                    ),
+                )
+                .at("upgrade control"),
+            ),
+            _ => {}
+        }
+    }
+
+    for controller in report.upgrade_chain.iter().chain(report.owner_chain.iter()) {
+        if let Some(delay) = controller.min_delay_secs.filter(|d| *d < SHORT_TIMELOCK_SECS) {
+            findings.push(
+                Finding::new(
+                    "ownership.short_timelock",
+                    Severity::Low,
+                    "Short timelock delay",
+                    format!("Timelock {:?} only waits {} seconds before executing", controller.address, delay),
+                )
+                .at("upgrade control"),
+            );
+            break;
+        }
+    }
+
+    if let Some(controller) = report.owner_chain.last() {
+        if controller.kind == ControllerKind::Eoa && report.upgrade_chain.is_empty() {
+            findings.push(
+                Finding::new(
+                    "ownership.eoa_owner",
+                    Severity::Low,
+                    "Owned by a single key",
+                    format!("Owner-only functions are controlled by {}", describe_chain(&report.owner_chain)),
+                )
+                .at("owner"),
+            );
+        }
+    }
+
+    if let Some(pending_owner) = report.pending_owner {
+        findings.push(
+            Finding::new(
+                "ownership.pending_owner",
+                Severity::Medium,
+                "Ownership transfer in progress",
+                format!("{:?} can accept ownership of the contract at any time", pending_owner),
+            )
+            .at("owner"),
+        );
+    }
+
+    for upgrade in &report.pending_upgrades {
+        findings.push(
+            Finding::new(
+                "ownership.pending_upgrade",
+                Severity::High,
+                "Upgrade queued on the timelock",
+                format!(
+                    "Timelock {:?} has a pending upgrade of {:?}{}{}",
+                    upgrade.timelock,
+                    upgrade.target,
+                    upgrade
+                        .new_implementation
+                        .map(|i| format!(" to implementation {:?}", i))
+                        .unwrap_or_default(),
+                    upgrade
+                        .ready_at
+                        .map(|t| format!(", executable from {}", t.to_rfc3339()))
+                        .unwrap_or_default()
+                ),
+            )
+            .at("upgrade control"),
+        );
+    }
+
+    findings
+}
+
+/// Renders the control analysis as compact text for the LLM prompt.
+pub fn format_ownership_for_prompt(report: Option<&OwnershipReport>) -> String {
+    let report = match report {
+        Some(report) => report,
+        None => return "Not available".to_string(),
+    };
+
+    let mut lines = vec![format!("Owner: {}", describe_chain(&report.owner_chain))];
+    if let Some(pending_owner) = report.pending_owner {
+        lines.push(format!("Pending owner: {:?}", pending_owner));
+    }
+    if report.upgradeable {
+        lines.push(format!(
+            "Upgradeable by: {}{}",
+            describe_chain(&report.upgrade_chain),
+            if report.upgrade_delayed() { "" } else { " (no timelock, effective next block)" }
+        ));
+        lines.push(format!("Pending upgrades: {}", report.pending_upgrades.len()));
+    } else {
+        lines.push("Upgradeable: no".to_string());
+    }
+    lines.join("\n")
+}
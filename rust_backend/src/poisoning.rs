@@ -0,0 +1,124 @@
+use ethers::types::Address;
+use ethers::utils::to_checksum;
+
+use crate::rules::{Finding, Severity};
+
+// Wallet UIs usually abbreviate addresses to 0x1234…abcd, which is what poisoners grind for
+const COLLISION_CHARS: usize = 4;
+// Prefix and suffix both matching at least this much is reported as a near match
+const NEAR_MATCH_CHARS: usize = 3;
+// Addresses differing in at most this many hex characters are reported as a near match
+const NEAR_MATCH_MAX_DIFF: usize = 6;
+
+#[derive(Debug, Clone)]
+pub struct KnownAddress {
+    pub address: Address,
+    pub label: String, // e.g. "previous counterparty of the sender", "Multicall3"
+}
+
+/// Compares every candidate address against the known addresses and flags lookalikes.
+/// Only the closest known address is reported for each candidate.
+pub fn detect_lookalikes(candidates: &[(Address, String)], known: &[KnownAddress]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for (candidate, location) in candidates {
+        // An exact match with a known address is the expected case, not a lookalike
+        if known.iter().any(|k| k.address == *candidate) {
+            continue;
+        }
+
+        let candidate_hex = hex::encode(candidate.as_bytes());
+        let mut best: Option<(usize, &KnownAddress, Similarity)> = None;
+
+        for k in known {
+            let similarity = compare(&candidate_hex, &hex::encode(k.address.as_bytes()));
+            let score = similarity.prefix + similarity.suffix;
+            if best.as_ref().map(|(s, _, _)| score > *s).unwrap_or(true) {
+                best = Some((score, k, similarity));
+            }
+        }
+
+        let (_, known_address, similarity) = match best {
+            Some(best) => best,
+            None => continue,
+        };
+
+        if similarity.prefix >= COLLISION_CHARS && similarity.suffix >= COLLISION_CHARS {
+            findings.push(
+                Finding::new(
+                    "poisoning.prefix_suffix_collision",
+                    Severity::High,
+                    "Possible address poisoning",
+                    format!(
+                        "{:?} shares its first {} and last {} hex characters with {:?} ({}) but is a different address",
+                        candidate, similarity.prefix, similarity.suffix, known_address.address, known_address.label
+                    ),
+                )
+                .at(location.clone()),
+            );
+        } else if (similarity.prefix >= NEAR_MATCH_CHARS && similarity.suffix >= NEAR_MATCH_CHARS)
+            || similarity.differing <= NEAR_MATCH_MAX_DIFF
+        {
+            findings.push(
+                Finding::new(
+                    "poisoning.near_match",
+                    Severity::Medium,
+                    "Lookalike address",
+                    format!(
+                        "{:?} closely resembles {:?} ({}): {} differing hex characters",
+                        candidate, known_address.address, known_address.label, similarity.differing
+                    ),
+                )
+                .at(location.clone()),
+            );
+        }
+    }
+
+    findings
+}
+
+/// Flags user supplied address strings whose mixed case does not match their EIP-55 checksum.
+/// All-lowercase or all-uppercase strings carry no checksum and are accepted.
+pub fn check_checksum(input: &str, location: &str) -> Option<Finding> {
+    let hex_part = input.strip_prefix("0x").unwrap_or(input);
+    let has_lower = hex_part.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = hex_part.chars().any(|c| c.is_ascii_uppercase());
+    if !(has_lower && has_upper) {
+        return None;
+    }
+
+    let address = input.parse::<Address>().ok()?;
+    let checksummed = to_checksum(&address, None);
+    if checksummed[2..] == *hex_part {
+        return None;
+    }
+
+    Some(
+        Finding::new(
+            "poisoning.checksum_mismatch",
+            Severity::Medium,
+            "EIP-55 checksum mismatch",
+            format!(
+                "{} does not match its checksummed form {}; the address may have been altered or mistyped",
+                input, checksummed
+            ),
+        )
+        .at(location.to_string()),
+    )
+}
+
+struct Similarity {
+    prefix: usize,
+    suffix: usize,
+    differing: usize,
+}
+
+fn compare(a: &str, b: &str) -> Similarity {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    Similarity {
+        prefix: a.iter().zip(b).take_while(|(x, y)| x == y).count(),
+        suffix: a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count(),
+        differing: a.iter().zip(b).filter(|(x, y)| x != y).count(),
+    }
+}
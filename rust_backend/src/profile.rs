@@ -0,0 +1,415 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{Address, BlockId, BlockNumber, H256};
+use log::{info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+
+use crate::abi::{chain_name, explorer_request, read_cached, write_cached, EXPLORER_CHAIN_IDS};
+use crate::rules::{Finding, Severity};
+use crate::source::fetch_source;
+
+// Cache kind used next to the ABI file
+pub const PROFILE_CACHE_KIND: &str = "profile";
+// Upper bound of transactions counted through the explorer (one page)
+const TX_COUNT_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractProfile {
+    pub address: Address,
+    pub chain_id: Option<String>, // Explorer chain the contract was found on
+    pub code_size: Option<usize>,
+    pub deployer: Option<Address>,
+    pub creation_tx: Option<H256>,
+    pub creation_block: Option<u64>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub verified: Option<bool>,
+    pub contract_name: Option<String>,
+    pub compiler_version: Option<String>,
+    pub tx_count: Option<u64>,
+    pub tx_count_capped: bool, // true when tx_count hit the explorer page size
+    pub deployer_first_seen: Option<DateTime<Utc>>, // First transaction involving the deployer
+    pub deployer_nonce: Option<u64>,
+    pub sources: Vec<String>, // "explorer", "rpc"
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl ContractProfile {
+    fn empty(address: Address) -> Self {
+        ContractProfile {
+            address,
+            chain_id: None,
+            code_size: None,
+            deployer: None,
+            creation_tx: None,
+            creation_block: None,
+            created_at: None,
+            verified: None,
+            contract_name: None,
+            compiler_version: None,
+            tx_count: None,
+            tx_count_capped: false,
+            deployer_first_seen: None,
+            deployer_nonce: None,
+            sources: Vec::new(),
+            fetched_at: Utc::now(),
+        }
+    }
+
+    pub fn age_days(&self) -> Option<f64> {
+        self.created_at
+            .map(|created| (Utc::now() - created).num_seconds() as f64 / 86_400.0)
+    }
+}
+
+/// Returns the cached profile while it is younger than `CONTRACT_PROFILE_TTL_SECS`
+/// (default 6 hours), otherwise fetches a fresh one and caches it next to the ABI.
+pub async fn get_or_fetch_profile(
+    contract_address: &Address,
+    provider: Option<&Provider<Http>>,
+) -> Result<ContractProfile, Box<dyn std::error::Error>> {
+    let ttl = env::var("CONTRACT_PROFILE_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(6 * 3600);
+
+    if let Some(profile) = read_cached::<ContractProfile>(contract_address, PROFILE_CACHE_KIND) {
+        if Utc::now() - profile.fetched_at < Duration::seconds(ttl) {
+            info!("📖 Loading contract profile from local cache: {:?}", contract_address);
+            return Ok(profile);
+        }
+    }
+
+    let profile = fetch_profile(contract_address, provider).await?;
+    if let Err(e) = write_cached(contract_address, PROFILE_CACHE_KIND, &profile) {
+        warn!("⚠️ Failed to cache contract profile: {}", e);
+    }
+    Ok(profile)
+}
+
+/// Gathers creation, deployer, verification and activity data from the explorer,
+/// filling the gaps from the RPC node when one is configured.
+pub async fn fetch_profile(
+    contract_address: &Address,
+    provider: Option<&Provider<Http>>,
+) -> Result<ContractProfile, Box<dyn std::error::Error>> {
+    let mut profile = ContractProfile::empty(*contract_address);
+    let client = Client::new();
+
+    for chain_id in EXPLORER_CHAIN_IDS {
+        if fill_from_explorer(&client, chain_id, &mut profile).await {
+            profile.chain_id = Some(chain_id.to_string());
+            profile.sources.push("explorer".to_string());
+            break;
+        }
+    }
+
+    if let Some(provider) = provider {
+        match fill_from_rpc(provider, &mut profile).await {
+            Ok(()) => profile.sources.push("rpc".to_string()),
+            Err(e) => warn!("⚠️ RPC profile lookup failed for {:?}: {}", contract_address, e),
+        }
+    }
+
+    if profile.sources.is_empty() {
+        return Err("No explorer data and no RPC node available for the contract profile".into());
+    }
+
+    info!(
+        "🪪 Contract profile for {:?} - Verified: {:?}, Age (days): {:?}, Deployer: {:?}",
+        contract_address,
+        profile.verified,
+        profile.age_days().map(|d| d.round()),
+        profile.deployer
+    );
+    Ok(profile)
+}
+
+// Returns true when the contract is known to the explorer on this chain
+async fn fill_from_explorer(client: &Client, chain_id: &str, profile: &mut ContractProfile) -> bool {
+    let address = format!("{:?}", profile.address);
+
+    let creation = match explorer_request(
+        client,
+        chain_id,
+        &format!("module=contract&action=getcontractcreation&contractaddresses={}", address),
+    )
+    .await
+    {
+        Ok(result) => result[0].clone(),
+        Err(e) => {
+            info!("⚠️ No creation data on {}: {}", chain_name(chain_id), e);
+            return false;
+        }
+    };
+
+    profile.deployer = creation["contractCreator"].as_str().and_then(|s| s.parse().ok());
+    profile.creation_tx = creation["txHash"].as_str().and_then(|s| s.parse().ok());
+    profile.creation_block = value_as_u64(&creation["blockNumber"]);
+    profile.created_at = value_as_u64(&creation["timestamp"])
+        .and_then(|ts| Utc.timestamp_opt(ts as i64, 0).single());
+
+    // Shares the getsourcecode call with the source module, which also caches verified source
+    match fetch_source(client, chain_id, &profile.address).await {
+        Ok(Some(source)) => {
+            profile.verified = Some(true);
+            profile.contract_name = Some(source.contract_name).filter(|s| !s.is_empty());
+            profile.compiler_version = Some(source.compiler_version).filter(|s| !s.is_empty());
+        }
+        Ok(None) => profile.verified = Some(false),
+        Err(e) => info!("⚠️ No source data on {}: {}", chain_name(chain_id), e),
+    }
+
+    let txs = transaction_page(client, chain_id, &address, TX_COUNT_PAGE_SIZE).await;
+    if let Some(txs) = &txs {
+        profile.tx_count = Some(txs.len() as u64);
+        profile.tx_count_capped = txs.len() >= TX_COUNT_PAGE_SIZE;
+        if profile.created_at.is_none() {
+            // The first transaction of a contract is its creation
+            profile.created_at = txs.first().and_then(tx_timestamp);
+        }
+    }
+
+    if let Some(deployer) = profile.deployer {
+        let deployer = format!("{:?}", deployer);
+        profile.deployer_first_seen = transaction_page(client, chain_id, &deployer, 1)
+            .await
+            .and_then(|txs| txs.first().and_then(tx_timestamp));
+    }
+
+    true
+}
+
+// First page of normal transactions for an address, oldest first
+async fn transaction_page(client: &Client, chain_id: &str, address: &str, size: usize) -> Option<Vec<Value>> {
+    let query = format!(
+        "module=account&action=txlist&address={}&startblock=0&endblock=99999999&page=1&offset={}&sort=asc",
+        address, size
+    );
+    match explorer_request(client, chain_id, &query).await {
+        Ok(Value::Array(txs)) => Some(txs),
+        Ok(_) => None,
+        Err(e) if e.to_string().contains("No transactions found") => Some(Vec::new()),
+        Err(e) => {
+            warn!("⚠️ Failed to list transactions of {}: {}", address, e);
+            None
+        }
+    }
+}
+
+fn tx_timestamp(tx: &Value) -> Option<DateTime<Utc>> {
+    value_as_u64(&tx["timeStamp"]).and_then(|ts| Utc.timestamp_opt(ts as i64, 0).single())
+}
+
+fn value_as_u64(value: &Value) -> Option<u64> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_u64(),
+        _ => None,
+    }
+}
+
+/// Whether `RPC_URL` serves historical state (`RPC_ARCHIVE=true`).
+fn archive_node() -> bool {
+    env::var("RPC_ARCHIVE")
+        .map(|v| v.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+async fn fill_from_rpc(provider: &Provider<Http>, profile: &mut ContractProfile) -> Result<(), Box<dyn std::error::Error>> {
+    let code = provider.get_code(profile.address, None).await?;
+    profile.code_size = Some(code.len());
+    if code.is_empty() {
+        return Ok(());
+    }
+
+    // The binary search costs ~25 historical eth_getCode calls and only works on an archive node
+    if profile.creation_block.is_none() && archive_node() {
+        match find_creation_block(provider, &profile.address).await {
+            Ok(block) => profile.creation_block = Some(block),
+            Err(e) => warn!("⚠️ Could not locate creation block (archive node required): {}", e),
+        }
+    }
+
+    if profile.created_at.is_none() {
+        if let Some(number) = profile.creation_block {
+            if let Some(block) = provider.get_block(number).await? {
+                profile.created_at = Utc.timestamp_opt(block.timestamp.as_u64() as i64, 0).single();
+            }
+        }
+    }
+
+    if let Some(deployer) = profile.deployer {
+        profile.deployer_nonce = Some(provider.get_transaction_count(deployer, None).await?.as_u64());
+    }
+
+    Ok(())
+}
+
+/// Binary search for the first block at which the address has code.
+async fn find_creation_block(provider: &Provider<Http>, address: &Address) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut high = provider.get_block_number().await?.as_u64();
+    let mut low = 0u64;
+
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let block = BlockId::Number(BlockNumber::Number(mid.into()));
+        let code = provider.get_code(*address, Some(block)).await?;
+        if code.is_empty() {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    Ok(low)
+}
+
+/// Turns the profile into risk signals: fresh, unverified, quiet or freshly-funded-deployer contracts.
+pub fn profile_findings(profile: &ContractProfile) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    // Age only adds to the score: at High every call to a new contract would be floored at High
+    if let Some(age) = profile.age_days() {
+        let severity = if age < 1.0 {
+            Some(Severity::Medium)
+        } else if age < 7.0 {
+            Some(Severity::Low)
+        } else if age < 30.0 {
+            Some(Severity::Info)
+        } else {
+            None
+        };
+        if let Some(severity) = severity {
+            findings.push(
+                Finding::new(
+                    "profile.young_contract",
+                    severity,
+                    "Recently deployed contract",
+                    format!("The contract was deployed {:.1} day(s) ago", age),
+                )
+                .at("contract profile"),
+            );
+        }
+    }
+
+    if profile.verified == Some(false) {
+        findings.push(
+            Finding::new(
+                "profile.unverified",
+                Severity::Medium,
+                "Unverified source code",
+                "The contract source is not verified on the block explorer",
+            )
+            .at("contract profile"),
+        );
+    }
+
+    // A deployer whose first transaction happened shortly before the deployment was funded just for it
+    let fresh_deployer = match (profile.deployer_first_seen, profile.created_at) {
+        (Some(first_seen), Some(created)) => created - first_seen < Duration::days(2),
+        _ => profile.deployer_nonce.map(|n| n <= 2).unwrap_or(false),
+    };
+    if fresh_deployer {
+        findings.push(
+            Finding::new(
+                "profile.fresh_deployer",
+                Severity::Medium,
+                "Freshly funded deployer",
+                format!(
+                    "Deployer {:?} had no history before deploying this contract",
+                    profile.deployer.unwrap_or_default()
+                ),
+            )
+            .at("contract profile"),
+        );
+    }
+
+    if let Some(count) = profile.tx_count {
+        if count < 10 && !profile.tx_count_capped {
+            findings.push(
+                Finding::new(
+                    "profile.low_activity",
+                    Severity::Low,
+                    "Little on-chain activity",
+                    format!("Only {} transaction(s) have interacted with the contract", count),
+                )
+                .at("contract profile"),
+            );
+        }
+    }
+
+    if let Some(version) = &profile.compiler_version {
+        if is_pre_0_8(version) {
+            findings.push(
+                Finding::new(
+                    "profile.old_compiler",
+                    Severity::Low,
+                    "Compiler without overflow checks",
+                    format!("Compiled with {}, which predates Solidity 0.8 checked arithmetic", version),
+                )
+                .at("contract profile"),
+            );
+        }
+    }
+
+    findings
+}
+
+// "v0.7.6+commit.7338295f" -> true
+fn is_pre_0_8(version: &str) -> bool {
+    let mut parts = version.trim_start_matches('v').split(['.', '+']);
+    let major = parts.next().and_then(|p| p.parse::<u32>().ok());
+    let minor = parts.next().and_then(|p| p.parse::<u32>().ok());
+    matches!((major, minor), (Some(0), Some(minor)) if minor < 8)
+}
+
+/// Renders the profile as compact text for the LLM prompt.
+pub fn format_profile_for_prompt(profile: Option<&ContractProfile>) -> String {
+    let profile = match profile {
+        Some(profile) => profile,
+        None => return "Not available".to_string(),
+    };
+
+    let unknown = || "unknown".to_string();
+    [
+        format!("Name: {}", profile.contract_name.clone().unwrap_or_else(unknown)),
+        format!(
+            "Verified: {}",
+            profile.verified.map(|v| v.to_string()).unwrap_or_else(unknown)
+        ),
+        format!(
+            "Compiler: {}",
+            profile.compiler_version.clone().unwrap_or_else(unknown)
+        ),
+        format!(
+            "Age: {}",
+            profile
+                .age_days()
+                .map(|d| format!("{:.1} days", d))
+                .unwrap_or_else(unknown)
+        ),
+        format!(
+            "Deployer: {}",
+            profile
+                .deployer
+                .map(|d| format!("{:?}", d))
+                .unwrap_or_else(unknown)
+        ),
+        format!(
+            "Code size: {}",
+            profile
+                .code_size
+                .map(|s| format!("{} bytes", s))
+                .unwrap_or_else(unknown)
+        ),
+        format!(
+            "Transactions: {}{}",
+            profile.tx_count.map(|c| c.to_string()).unwrap_or_else(unknown),
+            if profile.tx_count_capped { "+" } else { "" }
+        ),
+    ]
+    .join("\n")
+}
@@ -0,0 +1,164 @@
+use ethabi::{Function, StateMutability, Token};
+use log::info;
+use minijinja::{Environment, UndefinedBehavior};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::contract_risk::{classify_function, Capability};
+use crate::injection::quote_untrusted;
+
+// Name of the template used when no category-specific one is configured
+pub const DEFAULT_TEMPLATE: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionCategory {
+    Approval,
+    Transfer,
+    Admin,
+    Upgrade,
+    Default,
+}
+
+impl FunctionCategory {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FunctionCategory::Approval => "approval",
+            FunctionCategory::Transfer => "transfer",
+            FunctionCategory::Admin => "admin",
+            FunctionCategory::Upgrade => "upgrade",
+            FunctionCategory::Default => DEFAULT_TEMPLATE,
+        }
+    }
+}
+
+// One entry of `prompt_templates` in prompt_config.json
+#[derive(Debug, Clone, Deserialize)]
+pub struct PromptTemplate {
+    pub version: String, // Recorded in every response, e.g. "approval-v2"
+    pub template: String, // Jinja syntax; category templates may {% extends "default" %}
+}
+
+// Which prompt produced a verdict
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptVersion {
+    pub category: FunctionCategory,
+    pub template: String, // Name of the template that was rendered
+    pub version: String,
+    pub sha256: String, // Of the rendered prompt
+}
+
+/// Category of the called function, reusing the capability heuristics of the contract risk profile.
+/// Upgrade wins over approval, approval over admin and admin over transfer.
+pub fn function_category(function_name: &str, state_mutability: StateMutability, upgrade: bool) -> FunctionCategory {
+    if upgrade {
+        return FunctionCategory::Upgrade;
+    }
+    let capabilities = classify_function(function_name, state_mutability);
+    let has = |capability: Capability| capabilities.contains(&capability);
+
+    if has(Capability::Upgrade) {
+        FunctionCategory::Upgrade
+    } else if has(Capability::Approval) {
+        FunctionCategory::Approval
+    } else if has(Capability::Admin) || has(Capability::Pause) || has(Capability::Mint) {
+        FunctionCategory::Admin
+    } else if has(Capability::ValueTransfer) {
+        FunctionCategory::Transfer
+    } else {
+        FunctionCategory::Default
+    }
+}
+
+/// Arguments with their ABI name and type next to the raw and formatted values.
+pub fn typed_arguments(
+    decoded: Option<(&Function, &[Token])>,
+    arguments: &[String],
+    formatted_arguments: &[String],
+) -> Vec<Value> {
+    arguments
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let input = decoded.and_then(|(function, _)| function.inputs.get(index));
+            json!({
+                "index": index,
+                "name": input.map(|input| input.name.clone()).unwrap_or_default(),
+                "type": input.map(|input| input.kind.to_string()).unwrap_or_default(),
+                "value": value,
+                "formatted": formatted_arguments.get(index).unwrap_or(value),
+            })
+        })
+        .collect()
+}
+
+/// Human-readable name of the chains this service is usually pointed at.
+pub fn chain_label(chain_id: u64) -> &'static str {
+    match chain_id {
+        1 => "Ethereum Mainnet",
+        11155111 => "Ethereum Sepolia",
+        42161 => "Arbitrum One",
+        421614 => "Arbitrum Sepolia",
+        8453 => "Base",
+        10 => "Optimism",
+        31337 => "Local (Anvil/Hardhat)",
+        _ => "Unknown chain",
+    }
+}
+
+/// Loads every configured template, so category templates can extend the default one.
+pub fn template_environment(
+    templates: &BTreeMap<String, PromptTemplate>,
+) -> Result<Environment<'_>, Box<dyn std::error::Error>> {
+    if !templates.contains_key(DEFAULT_TEMPLATE) {
+        return Err(format!("prompt_templates must define a \"{}\" template", DEFAULT_TEMPLATE).into());
+    }
+
+    let mut environment = Environment::new();
+    // A misspelled variable fails the render instead of silently sending an empty section
+    environment.set_undefined_behavior(UndefinedBehavior::Strict);
+    // `{{ value | untrusted }}` marks contract-controlled text as data for the model
+    environment.add_filter("untrusted", |value: minijinja::Value| quote_untrusted(&value.to_string()));
+    for (name, template) in templates {
+        environment
+            .add_template(name, &template.template)
+            .map_err(|e| format!("invalid prompt template \"{}\": {}", name, e))?;
+    }
+    Ok(environment)
+}
+
+/// Renders the template for `category` (or the default one) with `context`.
+pub fn render_prompt(
+    templates: &BTreeMap<String, PromptTemplate>,
+    category: FunctionCategory,
+    context: &Value,
+) -> Result<(String, PromptVersion), Box<dyn std::error::Error>> {
+    let environment = template_environment(templates)?;
+    let name = if templates.contains_key(category.label()) {
+        category.label()
+    } else {
+        DEFAULT_TEMPLATE
+    };
+
+    let prompt = environment
+        .get_template(name)?
+        .render(context)
+        .map_err(|e| format!("failed to render prompt template \"{}\": {}", name, e))?;
+
+    let version = PromptVersion {
+        category,
+        template: name.to_string(),
+        version: templates[name].version.clone(),
+        sha256: hex::encode(Sha256::digest(prompt.as_bytes())),
+    };
+    info!(
+        "📝 Rendered prompt template \"{}\" ({}) for category {} - {} chars",
+        name,
+        version.version,
+        category.label(),
+        prompt.len()
+    );
+    Ok((prompt, version))
+}
@@ -0,0 +1,123 @@
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, H256};
+use ethers::utils::keccak256;
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::rpc::{eth_call, get_storage_at};
+
+// EIP-1967: bytes32(uint256(keccak256('eip1967.proxy.implementation')) - 1)
+pub const EIP1967_IMPLEMENTATION_SLOT: &str =
+    "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+// EIP-1967: bytes32(uint256(keccak256('eip1967.proxy.admin')) - 1)
+pub const EIP1967_ADMIN_SLOT: &str =
+    "0xb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103";
+// EIP-1967: bytes32(uint256(keccak256('eip1967.proxy.beacon')) - 1)
+pub const EIP1967_BEACON_SLOT: &str =
+    "0xa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+// EIP-1822 (UUPS): keccak256('PROXIABLE')
+pub const EIP1822_PROXIABLE_SLOT: &str =
+    "0xc5f16f0fcc639fa48a6947836d9850f504798523bf8c9a3a87d5876cf622bcf7";
+
+// EIP-1167 minimal proxy: 363d3d373d3d3d363d73<address>5af43d82803e903d91602b57fd5bf3
+const EIP1167_PREFIX: [u8; 10] = [0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d, 0x3d, 0x36, 0x3d, 0x73];
+const EIP1167_SUFFIX: [u8; 15] = [
+    0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ProxyKind {
+    Eip1967,
+    Eip1967Beacon,
+    Eip1822,
+    Eip1167,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyInfo {
+    pub kind: ProxyKind,
+    pub implementation: Address,
+    pub admin: Option<Address>,
+    pub beacon: Option<Address>,
+}
+
+/// Detects the standard proxy layouts and resolves the current implementation.
+pub async fn detect_proxy(
+    provider: &Provider<Http>,
+    address: &Address,
+    code: &[u8],
+) -> Result<Option<ProxyInfo>, Box<dyn std::error::Error>> {
+    if let Some(implementation) = minimal_proxy_target(code) {
+        info!("🪞 {:?} is an EIP-1167 minimal proxy for {:?}", address, implementation);
+        return Ok(Some(ProxyInfo {
+            kind: ProxyKind::Eip1167,
+            implementation,
+            admin: None,
+            beacon: None,
+        }));
+    }
+
+    let admin = read_address_slot(provider, address, EIP1967_ADMIN_SLOT).await?;
+
+    if let Some(implementation) = read_address_slot(provider, address, EIP1967_IMPLEMENTATION_SLOT).await? {
+        info!("🪞 {:?} is an EIP-1967 proxy for {:?}", address, implementation);
+        return Ok(Some(ProxyInfo {
+            kind: ProxyKind::Eip1967,
+            implementation,
+            admin,
+            beacon: None,
+        }));
+    }
+
+    if let Some(beacon) = read_address_slot(provider, address, EIP1967_BEACON_SLOT).await? {
+        // UpgradeableBeacon exposes implementation()
+        let selector = &keccak256("implementation()".as_bytes())[..4];
+        match eth_call(provider, &beacon, selector).await {
+            Ok(output) if output.len() >= 32 => {
+                let implementation = Address::from_slice(&output[12..32]);
+                info!("🪞 {:?} is a beacon proxy ({:?}) for {:?}", address, beacon, implementation);
+                return Ok(Some(ProxyInfo {
+                    kind: ProxyKind::Eip1967Beacon,
+                    implementation,
+                    admin,
+                    beacon: Some(beacon),
+                }));
+            }
+            Ok(_) => warn!("⚠️ Beacon {:?} returned no implementation", beacon),
+            Err(e) => warn!("⚠️ Failed to query beacon {:?}: {}", beacon, e),
+        }
+    }
+
+    if let Some(implementation) = read_address_slot(provider, address, EIP1822_PROXIABLE_SLOT).await? {
+        info!("🪞 {:?} is an EIP-1822 proxy for {:?}", address, implementation);
+        return Ok(Some(ProxyInfo {
+            kind: ProxyKind::Eip1822,
+            implementation,
+            admin,
+            beacon: None,
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Reads an address stored in the low 20 bytes of a slot; None when the slot is empty.
+pub async fn read_address_slot(
+    provider: &Provider<Http>,
+    address: &Address,
+    slot: &str,
+) -> Result<Option<Address>, Box<dyn std::error::Error>> {
+    let value = get_storage_at(provider, address, slot.parse::<H256>()?).await?;
+    let stored = Address::from_slice(&value.as_bytes()[12..]);
+    Ok(if stored.is_zero() { None } else { Some(stored) })
+}
+
+fn minimal_proxy_target(code: &[u8]) -> Option<Address> {
+    if code.len() != EIP1167_PREFIX.len() + 20 + EIP1167_SUFFIX.len() {
+        return None;
+    }
+    if code[..10] != EIP1167_PREFIX || code[30..] != EIP1167_SUFFIX {
+        return None;
+    }
+    Some(Address::from_slice(&code[10..30]))
+}
@@ -0,0 +1,286 @@
+use ethers::types::Address;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::rules::{Finding, Severity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReputationCategory {
+    Drainer,
+    Phishing,
+    Sanctioned,
+    KnownProtocol,
+    Treasury,
+}
+
+impl ReputationCategory {
+    pub fn parse(value: &str) -> Option<ReputationCategory> {
+        match value.trim().to_lowercase().replace(['-', ' '], "_").as_str() {
+            "drainer" => Some(ReputationCategory::Drainer),
+            "phishing" => Some(ReputationCategory::Phishing),
+            "sanctioned" => Some(ReputationCategory::Sanctioned),
+            "known_protocol" | "protocol" => Some(ReputationCategory::KnownProtocol),
+            "treasury" => Some(ReputationCategory::Treasury),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReputationCategory::Drainer => "drainer",
+            ReputationCategory::Phishing => "phishing",
+            ReputationCategory::Sanctioned => "sanctioned",
+            ReputationCategory::KnownProtocol => "known_protocol",
+            ReputationCategory::Treasury => "treasury",
+        }
+    }
+
+    // Trusted categories are reported as informational findings only
+    pub fn is_trusted(&self) -> bool {
+        matches!(self, ReputationCategory::KnownProtocol | ReputationCategory::Treasury)
+    }
+
+    fn severity(&self) -> Severity {
+        match self {
+            ReputationCategory::Drainer | ReputationCategory::Sanctioned => Severity::Critical,
+            ReputationCategory::Phishing => Severity::High,
+            ReputationCategory::KnownProtocol | ReputationCategory::Treasury => Severity::Info,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReputationEntry {
+    pub address: Address,
+    pub label: String,
+    pub category: ReputationCategory,
+    pub source: String, // File the entry was loaded from
+}
+
+#[derive(Deserialize)]
+struct JsonEntry {
+    address: String,
+    label: Option<String>,
+    category: String,
+}
+
+/// Address reputation lists loaded from the CSV/JSON files of a local directory.
+/// Lookups are a single HashMap access; the whole map is swapped on reload.
+pub struct ReputationStore {
+    dir: PathBuf,
+    entries: RwLock<Arc<HashMap<Address, ReputationEntry>>>,
+    fingerprint: Mutex<Option<Vec<(PathBuf, SystemTime)>>>,
+}
+
+impl ReputationStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ReputationStore {
+            dir: dir.into(),
+            entries: RwLock::new(Arc::new(HashMap::new())),
+            fingerprint: Mutex::new(None),
+        }
+    }
+
+    /// Builds the store from `REPUTATION_DIR` (default: `reputation`) and loads it once.
+    pub fn from_env() -> Self {
+        let dir = env::var("REPUTATION_DIR").unwrap_or_else(|_| "reputation".to_string());
+        let store = ReputationStore::new(dir);
+        if let Err(e) = store.reload_if_changed() {
+            error!("❌ Failed to load reputation lists: {}", e);
+        }
+        store
+    }
+
+    pub fn lookup(&self, address: &Address) -> Option<ReputationEntry> {
+        self.entries.read().unwrap().get(address).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Snapshot of every entry, for detectors that need to scan the whole list.
+    pub fn snapshot(&self) -> Arc<HashMap<Address, ReputationEntry>> {
+        self.entries.read().unwrap().clone()
+    }
+
+    /// Reloads the lists when a file was added, removed or modified since the last load.
+    /// Returns true when a reload happened.
+    pub fn reload_if_changed(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let current = list_files(&self.dir)?;
+        {
+            let fingerprint = self.fingerprint.lock().unwrap();
+            if fingerprint.as_ref() == Some(&current) {
+                return Ok(false);
+            }
+        }
+
+        let mut entries = HashMap::new();
+        for (path, _) in &current {
+            match load_file(path) {
+                Ok(list) => {
+                    for entry in list {
+                        entries.insert(entry.address, entry);
+                    }
+                }
+                Err(e) => warn!("⚠️ Skipping reputation file {}: {}", path.display(), e),
+            }
+        }
+
+        info!(
+            "📒 Loaded {} reputation entries from {} file(s) in {}",
+            entries.len(),
+            current.len(),
+            self.dir.display()
+        );
+        *self.entries.write().unwrap() = Arc::new(entries);
+        *self.fingerprint.lock().unwrap() = Some(current);
+        Ok(true)
+    }
+
+    /// Checks each address against the lists and returns one finding per hit.
+    pub fn check_addresses(&self, addresses: &[(Address, String)]) -> Vec<Finding> {
+        let entries = self.snapshot();
+        let mut findings = Vec::new();
+        for (address, location) in addresses {
+            if let Some(entry) = entries.get(address) {
+                let rule_id = format!("reputation.{}", entry.category.as_str());
+                let title = if entry.category.is_trusted() {
+                    format!("Trusted address: {}", entry.label)
+                } else {
+                    format!("Flagged address: {}", entry.label)
+                };
+                findings.push(
+                    Finding::new(
+                        &rule_id,
+                        entry.category.severity(),
+                        title,
+                        format!(
+                            "{:?} is listed as {} in {}",
+                            address,
+                            entry.category.as_str(),
+                            entry.source
+                        ),
+                    )
+                    .at(location.clone()),
+                );
+            }
+        }
+        findings
+    }
+}
+
+/// Polls the reputation directory and reloads the lists while the server runs.
+pub fn spawn_reload_task(store: Arc<ReputationStore>) {
+    let secs = env::var("REPUTATION_RELOAD_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(30);
+
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(Duration::from_secs(secs.max(1)));
+        loop {
+            interval.tick().await;
+            if let Err(e) = store.reload_if_changed() {
+                error!("❌ Failed to reload reputation lists: {}", e);
+            }
+        }
+    });
+}
+
+fn list_files(dir: &Path) -> Result<Vec<(PathBuf, SystemTime)>, Box<dyn std::error::Error>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_list = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("csv") | Some("json")
+        );
+        if is_list {
+            let modified = fs::metadata(&path)?.modified()?;
+            files.push((path, modified));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn load_file(path: &Path) -> Result<Vec<ReputationEntry>, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path)?;
+    let source = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => parse_json(&content, &source),
+        _ => parse_csv(&content, &source),
+    }
+}
+
+// CSV format: address,label,category (header line and '#' comments are optional)
+fn parse_csv(content: &str, source: &str) -> Result<Vec<ReputationEntry>, Box<dyn std::error::Error>> {
+    let mut entries = Vec::new();
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.to_lowercase().starts_with("address,") {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if fields.len() < 3 {
+            warn!("⚠️ {}:{}: expected address,label,category", source, line_no + 1);
+            continue;
+        }
+
+        let label = fields[1..fields.len() - 1].join(",");
+        match build_entry(fields[0], &label, fields[fields.len() - 1], source) {
+            Some(entry) => entries.push(entry),
+            None => warn!("⚠️ {}:{}: invalid entry '{}'", source, line_no + 1, line),
+        }
+    }
+    Ok(entries)
+}
+
+// JSON format: [{"address": "0x..", "label": "..", "category": "drainer"}, ...]
+fn parse_json(content: &str, source: &str) -> Result<Vec<ReputationEntry>, Box<dyn std::error::Error>> {
+    let value: Value = serde_json::from_str(content)?;
+    let items: Vec<JsonEntry> = serde_json::from_value(value)?;
+
+    let mut entries = Vec::new();
+    for item in items {
+        let label = item.label.unwrap_or_default();
+        match build_entry(&item.address, &label, &item.category, source) {
+            Some(entry) => entries.push(entry),
+            None => warn!("⚠️ {}: invalid entry for address '{}'", source, item.address),
+        }
+    }
+    Ok(entries)
+}
+
+fn build_entry(address: &str, label: &str, category: &str, source: &str) -> Option<ReputationEntry> {
+    let address = address.parse::<Address>().ok()?;
+    let category = ReputationCategory::parse(category)?;
+    Some(ReputationEntry {
+        address,
+        label: label.to_string(),
+        category,
+        source: source.to_string(),
+    })
+}
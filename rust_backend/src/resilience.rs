@@ -0,0 +1,216 @@
+use async_trait::async_trait;
+use log::{info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::llm::{LlmError, LlmProvider};
+use crate::ResilienceSettings;
+
+// Upper bound of a single backoff, whatever the attempt number
+const MAX_BACKOFF_MS: u64 = 10_000;
+
+// Which path produced the verdict of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerdictPath {
+    Primary,
+    Fallback,
+    Ensemble,
+    RulesOnly, // No model answered; the verdict only reflects the local findings
+    Cache,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerdictSource {
+    pub path: VerdictPath,
+    pub model: Option<String>, // "<provider>/<model>"
+}
+
+/// `<provider>/<model>`, the unit a circuit breaker tracks.
+pub fn provider_id(llm: &dyn LlmProvider) -> String {
+    format!("{}/{}", llm.name(), llm.model())
+}
+
+/// Rate limits, server errors, timeouts and connection failures are worth another attempt.
+pub fn is_retryable(error: &LlmError) -> bool {
+    match error {
+        LlmError::Status { status, .. } => *status == 429 || *status >= 500,
+        LlmError::Request(_) => true,
+        LlmError::Config(_) | LlmError::Parse(_) => false,
+    }
+}
+
+/// Exponential backoff with full jitter: a random delay up to `base * 2^retry`.
+fn backoff(settings: &ResilienceSettings, retry: u32) -> Duration {
+    let ceiling = settings
+        .retry_backoff_ms
+        .saturating_mul(1u64 << retry.min(16))
+        .min(MAX_BACKOFF_MS);
+    Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling))
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    cooldown: Duration,
+}
+
+/// One circuit breaker per provider, shared by all requests. After `breaker_failure_threshold`
+/// failed verdict requests in a row the provider is skipped for `breaker_cooldown_secs`; the next
+/// request after the cooldown is let through as a single trial while the others keep skipping it.
+#[derive(Default)]
+pub struct CircuitBreakers {
+    states: Mutex<HashMap<String, BreakerState>>,
+}
+
+impl CircuitBreakers {
+    pub fn new() -> Self {
+        CircuitBreakers::default()
+    }
+
+    pub fn allows(&self, provider: &str) -> bool {
+        let mut states = self.states.lock().unwrap();
+        let Some(state) = states.get_mut(provider) else {
+            return true;
+        };
+        match state.open_until {
+            Some(open_until) if Instant::now() >= open_until => {
+                // Half-open: the trial holds the circuit for another cooldown until it reports back
+                state.open_until = Some(Instant::now() + state.cooldown);
+                info!("🟡 Circuit half-open for {}, sending a trial request", provider);
+                true
+            }
+            Some(_) => false,
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self, provider: &str) {
+        let mut states = self.states.lock().unwrap();
+        if let Some(state) = states.remove(provider) {
+            if state.open_until.is_some() {
+                info!("🟢 Circuit closed for {}", provider);
+            }
+        }
+    }
+
+    pub fn record_failure(&self, provider: &str, settings: &ResilienceSettings) {
+        let mut states = self.states.lock().unwrap();
+        let state = states.entry(provider.to_string()).or_default();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= settings.breaker_failure_threshold.max(1) {
+            state.cooldown = Duration::from_secs(settings.breaker_cooldown_secs);
+            state.open_until = Some(Instant::now() + state.cooldown);
+            warn!(
+                "🔴 Circuit open for {} after {} failure(s), retrying in {}s",
+                provider, state.consecutive_failures, settings.breaker_cooldown_secs
+            );
+        }
+    }
+}
+
+/// Wraps a provider with a deadline per attempt and jittered retries on transient errors.
+pub struct ResilientProvider<'a> {
+    pub inner: &'a dyn LlmProvider,
+    pub settings: &'a ResilienceSettings,
+}
+
+impl ResilientProvider<'_> {
+    fn attempt_timeout(&self) -> Duration {
+        Duration::from_secs(self.settings.attempt_timeout_secs.max(1))
+    }
+
+    async fn wait_before_retry(&self, retry: u32, error: &LlmError) {
+        let delay = backoff(self.settings, retry);
+        warn!(
+            "⏳ {} attempt failed ({}), retry {}/{} in {:?}",
+            self.inner.name(),
+            error,
+            retry + 1,
+            self.settings.max_retries,
+            delay
+        );
+        actix_web::rt::time::sleep(delay).await;
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ResilientProvider<'_> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    async fn complete(&self, system_message: &str, prompt: &str) -> Result<String, LlmError> {
+        let mut retry = 0;
+        loop {
+            let call = self.inner.complete(system_message, prompt);
+            let error = match actix_web::rt::time::timeout(self.attempt_timeout(), call).await {
+                Ok(Ok(content)) => return Ok(content),
+                Ok(Err(e)) => e,
+                Err(_) => LlmError::Request(format!("no answer within {:?}", self.attempt_timeout())),
+            };
+            if retry >= self.settings.max_retries || !is_retryable(&error) {
+                return Err(error);
+            }
+            self.wait_before_retry(retry, &error).await;
+            retry += 1;
+        }
+    }
+
+    async fn complete_json(&self, system_message: &str, prompt: &str, schema: &Value) -> Result<String, LlmError> {
+        let mut retry = 0;
+        loop {
+            let call = self.inner.complete_json(system_message, prompt, schema);
+            let error = match actix_web::rt::time::timeout(self.attempt_timeout(), call).await {
+                Ok(Ok(content)) => return Ok(content),
+                Ok(Err(e)) => e,
+                Err(_) => LlmError::Request(format!("no answer within {:?}", self.attempt_timeout())),
+            };
+            if retry >= self.settings.max_retries || !is_retryable(&error) {
+                return Err(error);
+            }
+            self.wait_before_retry(retry, &error).await;
+            retry += 1;
+        }
+    }
+
+    async fn stream_json(
+        &self,
+        system_message: &str,
+        prompt: &str,
+        schema: &Value,
+        on_token: &(dyn for<'t> Fn(&'t str) + Send + Sync),
+    ) -> Result<String, LlmError> {
+        // Once tokens reached the client a retry would repeat them, so only silent failures are retried
+        let emitted = AtomicBool::new(false);
+        let forward = |token: &str| {
+            emitted.store(true, Ordering::Relaxed);
+            on_token(token);
+        };
+
+        let mut retry = 0;
+        loop {
+            let call = self.inner.stream_json(system_message, prompt, schema, &forward);
+            let error = match actix_web::rt::time::timeout(self.attempt_timeout(), call).await {
+                Ok(Ok(content)) => return Ok(content),
+                Ok(Err(e)) => e,
+                Err(_) => LlmError::Request(format!("no answer within {:?}", self.attempt_timeout())),
+            };
+            if retry >= self.settings.max_retries || !is_retryable(&error) || emitted.load(Ordering::Relaxed) {
+                return Err(error);
+            }
+            self.wait_before_retry(retry, &error).await;
+            retry += 1;
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use ethabi::{ParamType, Token};
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, Bytes, H256, U256};
+use ethers::utils::id;
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use std::env;
+
+// Multicall3, deployed at the same address on mainnet, the major L2s and testnets
+pub const MULTICALL3_ADDRESS: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+
+/// JSON-RPC provider for `RPC_URL`, or None when no node is configured.
+/// A local anvil fork (`anvil --fork-url <mainnet-or-testnet-rpc>`) works as a stand-in.
+pub fn rpc_provider() -> Option<Provider<Http>> {
+    let url = env::var("RPC_URL").ok().filter(|u| !u.trim().is_empty())?;
+    match Provider::<Http>::try_from(url.as_str()) {
+        Ok(provider) => Some(provider),
+        Err(e) => {
+            error!("❌ Invalid RPC_URL '{}': {}", url, e);
+            None
+        }
+    }
+}
+
+/// Runtime code of an address at the latest block (empty for EOAs).
+pub async fn get_code(
+    provider: &Provider<Http>,
+    address: &Address,
+) -> Result<Bytes, Box<dyn std::error::Error>> {
+    info!("🌐 Fetching runtime code for {:?}", address);
+    let code: Bytes = provider.request("eth_getCode", (address, "latest")).await?;
+    Ok(code)
+}
+
+/// `eth_getStorageAt` at the latest block.
+pub async fn get_storage_at(
+    provider: &Provider<Http>,
+    address: &Address,
+    slot: H256,
+) -> Result<H256, Box<dyn std::error::Error>> {
+    let value: H256 = provider
+        .request("eth_getStorageAt", (address, slot, "latest"))
+        .await?;
+    Ok(value)
+}
+
+/// Plain `eth_call` against the latest block.
+pub async fn eth_call(
+    provider: &Provider<Http>,
+    to: &Address,
+    data: &[u8],
+) -> Result<Bytes, Box<dyn std::error::Error>> {
+    let tx = json!({
+        "to": to,
+        "data": Bytes::from(data.to_vec()),
+    });
+    let result: Bytes = provider.request("eth_call", (tx, "latest")).await?;
+    Ok(result)
+}
+
+/// `eth_call` from `from` with state overrides (the third `eth_call` parameter on geth/anvil).
+pub async fn eth_call_with_overrides(
+    provider: &Provider<Http>,
+    from: Option<&Address>,
+    to: &Address,
+    data: &[u8],
+    overrides: Value,
+) -> Result<Bytes, Box<dyn std::error::Error>> {
+    let tx = json!({
+        "from": from,
+        "to": to,
+        "data": Bytes::from(data.to_vec()),
+    });
+    let result: Bytes = provider.request("eth_call", (tx, "latest", overrides)).await?;
+    Ok(result)
+}
+
+/// Calldata for `signature` (e.g. "balanceOf(address)") with ABI-encoded arguments.
+pub fn encode_call(signature: &str, args: &[Token]) -> Vec<u8> {
+    let mut data = id(signature).to_vec();
+    data.extend(ethabi::encode(args));
+    data
+}
+
+/// Calls a view function returning a single uint256.
+pub async fn call_uint(
+    provider: &Provider<Http>,
+    to: &Address,
+    signature: &str,
+    args: &[Token],
+) -> Result<U256, Box<dyn std::error::Error>> {
+    let output = eth_call(provider, to, &encode_call(signature, args)).await?;
+    match ethabi::decode(&[ParamType::Uint(256)], &output)?.first() {
+        Some(Token::Uint(value)) => Ok(*value),
+        _ => Err(format!("{} returned no uint", signature).into()),
+    }
+}
+
+/// Calls a view function returning a single address.
+pub async fn call_address(
+    provider: &Provider<Http>,
+    to: &Address,
+    signature: &str,
+) -> Result<Address, Box<dyn std::error::Error>> {
+    let output = eth_call(provider, to, &encode_call(signature, &[])).await?;
+    match ethabi::decode(&[ParamType::Address], &output)?.first() {
+        Some(Token::Address(address)) => Ok(*address),
+        _ => Err(format!("{} returned no address", signature).into()),
+    }
+}
+
+/// Runs the calls in one `aggregate3` round trip, with failures allowed per call.
+/// Falls back to one `eth_call` each when Multicall3 is not deployed on the network.
+pub async fn multicall(
+    provider: &Provider<Http>,
+    calls: &[(Address, Vec<u8>)],
+) -> Result<Vec<Option<Bytes>>, Box<dyn std::error::Error>> {
+    if calls.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let multicall_address: Address = MULTICALL3_ADDRESS.parse()?;
+    let batch = Token::Array(
+        calls
+            .iter()
+            .map(|(to, data)| Token::Tuple(vec![Token::Address(*to), Token::Bool(true), Token::Bytes(data.clone())]))
+            .collect(),
+    );
+    let data = encode_call("aggregate3((address,bool,bytes)[])", &[batch]);
+    let output_type = ParamType::Array(Box::new(ParamType::Tuple(vec![ParamType::Bool, ParamType::Bytes])));
+
+    match eth_call(provider, &multicall_address, &data).await {
+        Ok(output) if !output.is_empty() => {
+            let results = match ethabi::decode(&[output_type], &output)?.pop() {
+                Some(Token::Array(results)) => results,
+                _ => return Err("aggregate3 returned no results".into()),
+            };
+            Ok(results
+                .into_iter()
+                .map(|result| match result {
+                    Token::Tuple(fields) => match (fields.first(), fields.get(1)) {
+                        (Some(Token::Bool(true)), Some(Token::Bytes(data))) => Some(Bytes::from(data.clone())),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect())
+        }
+        other => {
+            if let Err(e) = other {
+                warn!("⚠️ Multicall3 unavailable ({}), falling back to single calls", e);
+            }
+            let mut results = Vec::with_capacity(calls.len());
+            for (to, data) in calls {
+                results.push(eth_call(provider, to, data).await.ok());
+            }
+            Ok(results)
+        }
+    }
+}
+
+/// Raw request helper for methods without a typed wrapper (debug_traceCall, ...).
+pub async fn raw_request(
+    provider: &Provider<Http>,
+    method: &str,
+    params: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let result: Value = provider.request(method, params).await?;
+    Ok(result)
+}
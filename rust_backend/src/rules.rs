@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Severity attached to a single rule finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "Info",
+            Severity::Low => "Low",
+            Severity::Medium => "Medium",
+            Severity::High => "High",
+            Severity::Critical => "Critical",
+        }
+    }
+
+    // Weight used to accumulate the rule-based risk score (0-100)
+    fn weight(&self) -> u32 {
+        match self {
+            Severity::Info => 0,
+            Severity::Low => 5,
+            Severity::Medium => 15,
+            Severity::High => 35,
+            Severity::Critical => 60,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Overall verdict level, shared by the rule engine and the LLM output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl RiskLevel {
+    /// Parses the free-form level returned by the LLM ("High", "**HIGH**", "high/critical", ...).
+    pub fn parse(value: &str) -> Option<RiskLevel> {
+        let normalized = value
+            .trim()
+            .trim_matches(|c: char| !c.is_ascii_alphabetic())
+            .to_lowercase();
+        if normalized.starts_with("critical") {
+            Some(RiskLevel::Critical)
+        } else if normalized.starts_with("high") {
+            Some(RiskLevel::High)
+        } else if normalized.starts_with("medium") {
+            Some(RiskLevel::Medium)
+        } else if normalized.starts_with("low") {
+            Some(RiskLevel::Low)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RiskLevel::Low => "Low",
+            RiskLevel::Medium => "Medium",
+            RiskLevel::High => "High",
+            RiskLevel::Critical => "Critical",
+        }
+    }
+}
+
+impl From<Severity> for RiskLevel {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Info | Severity::Low => RiskLevel::Low,
+            Severity::Medium => RiskLevel::Medium,
+            Severity::High => RiskLevel::High,
+            Severity::Critical => RiskLevel::Critical,
+        }
+    }
+}
+
+impl fmt::Display for RiskLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Lines of a verified source file a finding points at (1-based, inclusive)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceSpan {
+    pub file: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start_line == self.end_line {
+            write!(f, "{}:{}", self.file, self.start_line)
+        } else {
+            write!(f, "{}:{}-{}", self.file, self.start_line, self.end_line)
+        }
+    }
+}
+
+// A single deterministic finding produced by one of the local checks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub rule_id: String,
+    pub severity: Severity,
+    pub title: String,
+    pub description: String,
+    pub location: Option<String>, // e.g. "target", "argument 1", "nested call 0 / argument 2"
+    #[serde(default)]
+    pub span: Option<SourceSpan>, // Set by the source scanner
+}
+
+impl Finding {
+    pub fn new(
+        rule_id: &str,
+        severity: Severity,
+        title: impl Into<String>,
+        description: impl Into<String>,
+    ) -> Self {
+        Finding {
+            rule_id: rule_id.to_string(),
+            severity,
+            title: title.into(),
+            description: description.into(),
+            location: None,
+            span: None,
+        }
+    }
+
+    pub fn at(mut self, location: impl Into<String>) -> Self {
+        self.location = Some(location.into());
+        self
+    }
+
+    pub fn in_source(mut self, file: &str, start_line: usize, end_line: usize) -> Self {
+        self.span = Some(SourceSpan {
+            file: file.to_string(),
+            start_line,
+            end_line,
+        });
+        self
+    }
+}
+
+/// Accumulates the findings into a 0-100 score.
+pub fn risk_score(findings: &[Finding]) -> u32 {
+    findings
+        .iter()
+        .map(|f| f.severity.weight())
+        .sum::<u32>()
+        .min(100)
+}
+
+/// The level implied by the findings alone: the most severe non-informational finding.
+pub fn rule_risk_level(findings: &[Finding]) -> Option<RiskLevel> {
+    findings
+        .iter()
+        .map(|f| f.severity)
+        .filter(|s| *s > Severity::Info)
+        .max()
+        .map(RiskLevel::from)
+}
+
+/// Combines the LLM verdict with the rule findings. Rules can only escalate the verdict,
+/// never lower it.
+pub fn combine_risk_level(llm_level: Option<String>, findings: &[Finding]) -> Option<String> {
+    let rule_level = match rule_risk_level(findings) {
+        Some(level) => level,
+        None => return llm_level,
+    };
+
+    match llm_level.as_deref().and_then(RiskLevel::parse) {
+        Some(llm) if llm >= rule_level => llm_level,
+        _ => Some(rule_level.to_string()),
+    }
+}
+
+/// Renders the findings as a compact list for the LLM prompt.
+pub fn format_findings_for_prompt(findings: &[Finding]) -> String {
+    if findings.is_empty() {
+        return "None".to_string();
+    }
+
+    findings
+        .iter()
+        .map(|f| match f.span.as_ref().map(|span| span.to_string()).or_else(|| f.location.clone()) {
+            Some(location) => format!(
+                "- [{}] {}: {} ({})",
+                f.severity, f.title, f.description, location
+            ),
+            None => format!("- [{}] {}: {}", f.severity, f.title, f.description),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,517 @@
+use ethabi::{ParamType, Token};
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, Bytes, H256, I256, U256};
+use ethers::utils::keccak256;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+use crate::rpc::raw_request;
+use crate::rules::{Finding, Severity};
+
+// Sender used when the request does not include a `from` address
+pub const DEFAULT_SIMULATION_SENDER: &str = "0x00000000000000000000000000000000005151aa";
+
+// Extra ETH granted to the sender through a state override so gas/value never fail the simulation
+const SENDER_BALANCE_HEADROOM_WEI: u128 = 100_000_000_000_000_000_000; // 100 ETH
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetKind {
+    Native,
+    Erc20,
+    Erc721,
+    Erc1155,
+}
+
+// Net balance change of one asset for one address
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetChange {
+    pub address: Address,
+    pub asset: AssetKind,
+    pub token: Option<Address>,   // None for the native asset
+    pub token_id: Option<String>, // ERC-721 / ERC-1155 id
+    pub delta: String,            // Signed decimal amount in base units
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulatedEvent {
+    pub address: Address,
+    pub name: Option<String>, // Set for the standard token events we recognize
+    pub topics: Vec<H256>,
+    pub data: Bytes,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InternalCall {
+    pub depth: usize,
+    pub call_type: String,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value: String, // Wei, decimal
+    pub selector: Option<String>,
+    pub reverted: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationReport {
+    pub sender: Address,
+    pub success: bool,
+    pub revert_reason: Option<String>,
+    pub gas_used: Option<String>,
+    pub traced: bool, // false when the node has no debug_traceCall and only eth_call ran
+    pub asset_changes: Vec<AssetChange>,
+    pub events: Vec<SimulatedEvent>,
+    pub internal_calls: Vec<InternalCall>,
+}
+
+// Subset of the geth/anvil `callTracer` output
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallFrame {
+    #[serde(rename = "type")]
+    call_type: String,
+    from: Address,
+    to: Option<Address>,
+    value: Option<U256>,
+    gas_used: Option<U256>,
+    input: Option<Bytes>,
+    output: Option<Bytes>,
+    error: Option<String>,
+    revert_reason: Option<String>,
+    #[serde(default)]
+    calls: Vec<CallFrame>,
+    #[serde(default)]
+    logs: Vec<CallLog>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallLog {
+    address: Address,
+    #[serde(default)]
+    topics: Vec<H256>,
+    #[serde(default)]
+    data: Bytes,
+}
+
+type AssetKey = (Address, AssetKind, Option<Address>, Option<U256>);
+
+/// Simulates the transaction with `debug_traceCall` (callTracer + logs) and a balance override
+/// for the sender. Falls back to a plain `eth_call` when tracing is not available.
+pub async fn simulate_transaction(
+    provider: &Provider<Http>,
+    sender: Address,
+    to: Address,
+    data: &[u8],
+    value: U256,
+) -> Result<SimulationReport, Box<dyn std::error::Error>> {
+    simulate_with_overrides(provider, sender, to, data, value, Map::new()).await
+}
+
+/// Same as `simulate_transaction`, with extra per-address state overrides
+/// (e.g. `{"0xtoken": {"stateDiff": {...}}}`) merged next to the sender balance.
+pub async fn simulate_with_overrides(
+    provider: &Provider<Http>,
+    sender: Address,
+    to: Address,
+    data: &[u8],
+    value: U256,
+    extra_overrides: Map<String, Value>,
+) -> Result<SimulationReport, Box<dyn std::error::Error>> {
+    let tx = json!({
+        "from": sender,
+        "to": to,
+        "data": Bytes::from(data.to_vec()),
+        "value": value,
+    });
+    let balance = value.saturating_add(U256::from(SENDER_BALANCE_HEADROOM_WEI));
+    let mut overrides = extra_overrides;
+    overrides.insert(format!("{:?}", sender), json!({ "balance": balance }));
+    let overrides = Value::Object(overrides);
+
+    info!("🧪 Simulating call to {:?} from {:?} (value: {} wei)", to, sender, value);
+
+    let trace_params = json!([
+        tx,
+        "latest",
+        {
+            "tracer": "callTracer",
+            "tracerConfig": { "withLog": true },
+            "stateOverrides": overrides,
+        }
+    ]);
+
+    match raw_request(provider, "debug_traceCall", trace_params).await {
+        Ok(trace) => {
+            let frame: CallFrame = serde_json::from_value(trace)?;
+            Ok(build_report(sender, &frame))
+        }
+        Err(e) => {
+            warn!("⚠️ debug_traceCall unavailable ({}), falling back to eth_call", e);
+            let call_params = json!([tx, "latest", overrides]);
+            let (success, revert_reason) = match raw_request(provider, "eth_call", call_params).await {
+                Ok(_) => (true, None),
+                Err(e) => (false, Some(e.to_string())),
+            };
+            Ok(SimulationReport {
+                sender,
+                success,
+                revert_reason,
+                gas_used: None,
+                traced: false,
+                asset_changes: Vec::new(),
+                events: Vec::new(),
+                internal_calls: Vec::new(),
+            })
+        }
+    }
+}
+
+fn build_report(sender: Address, root: &CallFrame) -> SimulationReport {
+    let mut deltas: BTreeMap<AssetKey, I256> = BTreeMap::new();
+    let mut events = Vec::new();
+    let mut internal_calls = Vec::new();
+
+    walk_frame(root, 0, false, &mut deltas, &mut events, &mut internal_calls);
+
+    let asset_changes = deltas
+        .into_iter()
+        .filter(|(_, delta)| !delta.is_zero())
+        .map(|((address, asset, token, token_id), delta)| AssetChange {
+            address,
+            asset,
+            token,
+            token_id: token_id.map(|id| id.to_string()),
+            delta: delta.to_string(),
+        })
+        .collect();
+
+    let success = root.error.is_none();
+    SimulationReport {
+        sender,
+        success,
+        revert_reason: if success {
+            None
+        } else {
+            root.revert_reason
+                .clone()
+                .or_else(|| root.output.as_ref().and_then(|o| decode_revert_reason(o)))
+                .or_else(|| root.error.clone())
+        },
+        gas_used: root.gas_used.map(|g| g.to_string()),
+        traced: true,
+        asset_changes,
+        events,
+        internal_calls,
+    }
+}
+
+fn walk_frame(
+    frame: &CallFrame,
+    depth: usize,
+    parent_reverted: bool,
+    deltas: &mut BTreeMap<AssetKey, I256>,
+    events: &mut Vec<SimulatedEvent>,
+    internal_calls: &mut Vec<InternalCall>,
+) {
+    let reverted = parent_reverted || frame.error.is_some();
+    let value = frame.value.unwrap_or_default();
+    let call_type = frame.call_type.to_uppercase();
+
+    if depth > 0 {
+        internal_calls.push(InternalCall {
+            depth,
+            call_type: call_type.clone(),
+            from: frame.from,
+            to: frame.to,
+            value: value.to_string(),
+            selector: frame
+                .input
+                .as_ref()
+                .filter(|i| i.len() >= 4)
+                .map(|i| format!("0x{}", hex::encode(&i[..4]))),
+            reverted,
+        });
+    }
+
+    // State changes of reverted frames are discarded by the EVM
+    if reverted {
+        return;
+    }
+
+    // DELEGATECALL / STATICCALL / CALLCODE do not move value between accounts
+    let moves_value = matches!(call_type.as_str(), "CALL" | "CREATE" | "CREATE2" | "SELFDESTRUCT");
+    if moves_value && !value.is_zero() {
+        if let Some(to) = frame.to {
+            add_delta(deltas, (frame.from, AssetKind::Native, None, None), value, false);
+            add_delta(deltas, (to, AssetKind::Native, None, None), value, true);
+        }
+    }
+
+    for log in &frame.logs {
+        events.push(SimulatedEvent {
+            address: log.address,
+            name: apply_token_log(log, deltas).map(|n| n.to_string()),
+            topics: log.topics.clone(),
+            data: log.data.clone(),
+        });
+    }
+
+    for call in &frame.calls {
+        walk_frame(call, depth + 1, reverted, deltas, events, internal_calls);
+    }
+}
+
+fn add_delta(deltas: &mut BTreeMap<AssetKey, I256>, key: AssetKey, amount: U256, incoming: bool) {
+    let amount = I256::from_raw(amount.min(I256::MAX.into_raw()));
+    let entry = deltas.entry(key).or_insert_with(I256::zero);
+    *entry = if incoming {
+        entry.saturating_add(amount)
+    } else {
+        entry.saturating_sub(amount)
+    };
+}
+
+fn event_topic(signature: &str) -> H256 {
+    H256::from(keccak256(signature.as_bytes()))
+}
+
+pub fn topic_address(topic: &H256) -> Address {
+    Address::from_slice(&topic.as_bytes()[12..])
+}
+
+/// Applies the balance effect of a standard token event and returns its name.
+fn apply_token_log(log: &CallLog, deltas: &mut BTreeMap<AssetKey, I256>) -> Option<&'static str> {
+    let topic0 = log.topics.first()?;
+    let token = log.address;
+
+    if *topic0 == event_topic("Transfer(address,address,uint256)") {
+        if log.topics.len() == 3 {
+            // ERC-20: amount in data
+            let amount = U256::from_big_endian(log.data.get(..32)?);
+            let (from, to) = (topic_address(&log.topics[1]), topic_address(&log.topics[2]));
+            add_delta(deltas, (from, AssetKind::Erc20, Some(token), None), amount, false);
+            add_delta(deltas, (to, AssetKind::Erc20, Some(token), None), amount, true);
+        } else if log.topics.len() == 4 {
+            // ERC-721: token id as third indexed topic
+            let id = U256::from_big_endian(log.topics[3].as_bytes());
+            let (from, to) = (topic_address(&log.topics[1]), topic_address(&log.topics[2]));
+            add_delta(deltas, (from, AssetKind::Erc721, Some(token), Some(id)), U256::one(), false);
+            add_delta(deltas, (to, AssetKind::Erc721, Some(token), Some(id)), U256::one(), true);
+        }
+        return Some("Transfer");
+    }
+
+    if *topic0 == event_topic("TransferSingle(address,address,address,uint256,uint256)") && log.topics.len() == 4 {
+        let tokens = ethabi::decode(&[ParamType::Uint(256), ParamType::Uint(256)], &log.data).ok()?;
+        if let [Token::Uint(id), Token::Uint(amount)] = tokens.as_slice() {
+            let (from, to) = (topic_address(&log.topics[2]), topic_address(&log.topics[3]));
+            add_delta(deltas, (from, AssetKind::Erc1155, Some(token), Some(*id)), *amount, false);
+            add_delta(deltas, (to, AssetKind::Erc1155, Some(token), Some(*id)), *amount, true);
+        }
+        return Some("TransferSingle");
+    }
+
+    if *topic0 == event_topic("TransferBatch(address,address,address,uint256[],uint256[])") && log.topics.len() == 4 {
+        let array = ParamType::Array(Box::new(ParamType::Uint(256)));
+        let tokens = ethabi::decode(&[array.clone(), array], &log.data).ok()?;
+        if let [Token::Array(ids), Token::Array(amounts)] = tokens.as_slice() {
+            let (from, to) = (topic_address(&log.topics[2]), topic_address(&log.topics[3]));
+            for (id, amount) in ids.iter().zip(amounts) {
+                if let (Token::Uint(id), Token::Uint(amount)) = (id, amount) {
+                    add_delta(deltas, (from, AssetKind::Erc1155, Some(token), Some(*id)), *amount, false);
+                    add_delta(deltas, (to, AssetKind::Erc1155, Some(token), Some(*id)), *amount, true);
+                }
+            }
+        }
+        return Some("TransferBatch");
+    }
+
+    if *topic0 == event_topic("Approval(address,address,uint256)") {
+        return Some("Approval");
+    }
+    if *topic0 == event_topic("ApprovalForAll(address,address,bool)") {
+        return Some("ApprovalForAll");
+    }
+
+    None
+}
+
+/// Decodes a Solidity `Error(string)` revert payload.
+fn decode_revert_reason(output: &[u8]) -> Option<String> {
+    if output.len() < 4 || output[..4] != [0x08, 0xc3, 0x79, 0xa0] {
+        return None;
+    }
+    match ethabi::decode(&[ParamType::String], &output[4..]).ok()?.pop()? {
+        Token::String(reason) => Some(reason),
+        _ => None,
+    }
+}
+
+/// Turns the asset-change report into rule findings.
+pub fn simulation_findings(report: &SimulationReport, target: &Address) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if !report.success {
+        findings.push(
+            Finding::new(
+                "simulation.reverted",
+                Severity::Low,
+                "Transaction reverts in simulation",
+                format!(
+                    "The call would fail: {}",
+                    report.revert_reason.as_deref().unwrap_or("unknown reason")
+                ),
+            )
+            .at("simulation"),
+        );
+    }
+
+    for change in &report.asset_changes {
+        let incoming = !change.delta.starts_with('-');
+        let is_party = change.address == report.sender || change.address == *target;
+
+        match change.asset {
+            AssetKind::Native if incoming && !is_party => findings.push(
+                Finding::new(
+                    "simulation.native_forwarded",
+                    Severity::High,
+                    "ETH forwarded to a third party",
+                    format!(
+                        "{} wei sent with this call ends up at {:?}, not at the called contract",
+                        change.delta, change.address
+                    ),
+                )
+                .at("simulation"),
+            ),
+            AssetKind::Erc20 | AssetKind::Erc721 | AssetKind::Erc1155
+                if !incoming && change.address == report.sender =>
+            {
+                findings.push(
+                    Finding::new(
+                        "simulation.token_outflow",
+                        Severity::Medium,
+                        "Tokens leave the sender",
+                        format!(
+                            "Sender balance of token {:?}{} changes by {}",
+                            change.token.unwrap_or_default(),
+                            change
+                                .token_id
+                                .as_ref()
+                                .map(|id| format!(" (id {})", id))
+                                .unwrap_or_default(),
+                            change.delta
+                        ),
+                    )
+                    .at("simulation"),
+                )
+            }
+            _ => {}
+        }
+    }
+
+    for event in &report.events {
+        let is_approval = matches!(event.name.as_deref(), Some("Approval") | Some("ApprovalForAll"));
+        if is_approval && event.topics.len() >= 3 && topic_address(&event.topics[1]) == report.sender {
+            findings.push(
+                Finding::new(
+                    "simulation.approval_granted",
+                    Severity::Medium,
+                    "Sender grants a token approval",
+                    format!(
+                        "{} on token {:?} lets {:?} move the sender's tokens",
+                        event.name.as_deref().unwrap_or("Approval"),
+                        event.address,
+                        topic_address(&event.topics[2])
+                    ),
+                )
+                .at("simulation"),
+            );
+        }
+    }
+
+    findings
+}
+
+/// Every address the simulation touched, for the reputation checks.
+pub fn simulation_addresses(report: &SimulationReport) -> Vec<(Address, String)> {
+    let mut addresses: Vec<(Address, String)> = Vec::new();
+    for call in report.internal_calls.iter().filter(|c| !c.reverted) {
+        if let Some(to) = call.to {
+            addresses.push((to, format!("simulation / internal {} at depth {}", call.call_type, call.depth)));
+        }
+    }
+    for change in &report.asset_changes {
+        addresses.push((change.address, "simulation / asset change".to_string()));
+    }
+    addresses.sort();
+    addresses.dedup_by(|a, b| a.0 == b.0);
+    addresses
+}
+
+/// Renders the report as compact text for the LLM prompt.
+pub fn format_simulation_for_prompt(report: Option<&SimulationReport>) -> String {
+    let report = match report {
+        Some(report) => report,
+        None => return "Not available (no RPC node configured)".to_string(),
+    };
+
+    let mut lines = vec![format!(
+        "Result: {}{}",
+        if report.success { "success" } else { "REVERTED" },
+        report
+            .revert_reason
+            .as_ref()
+            .map(|r| format!(" ({})", r))
+            .unwrap_or_default()
+    )];
+
+    if !report.traced {
+        lines.push("Trace: not available (eth_call only)".to_string());
+        return lines.join("\n");
+    }
+
+    lines.push("Asset changes:".to_string());
+    if report.asset_changes.is_empty() {
+        lines.push("  none".to_string());
+    }
+    for change in &report.asset_changes {
+        let asset = match (change.asset, change.token) {
+            (AssetKind::Native, _) => "ETH (wei)".to_string(),
+            (kind, Some(token)) => format!("{:?} {:?}", kind, token),
+            (kind, None) => format!("{:?}", kind),
+        };
+        let id = change.token_id.as_ref().map(|id| format!(" #{}", id)).unwrap_or_default();
+        lines.push(format!("  {:?}: {} {}{}", change.address, change.delta, asset, id));
+    }
+
+    lines.push("Internal calls:".to_string());
+    if report.internal_calls.is_empty() {
+        lines.push("  none".to_string());
+    }
+    for call in &report.internal_calls {
+        lines.push(format!(
+            "  {}{} {:?} -> {:?} value={} selector={}{}",
+            "  ".repeat(call.depth.saturating_sub(1)),
+            call.call_type,
+            call.from,
+            call.to.unwrap_or_default(),
+            call.value,
+            call.selector.as_deref().unwrap_or("-"),
+            if call.reverted { " (reverted)" } else { "" }
+        ));
+    }
+
+    let named: Vec<String> = report
+        .events
+        .iter()
+        .map(|e| format!("{}@{:?}", e.name.as_deref().unwrap_or("unknown"), e.address))
+        .collect();
+    lines.push(format!(
+        "Events: {}",
+        if named.is_empty() { "none".to_string() } else { named.join(", ") }
+    ));
+
+    lines.join("\n")
+}
@@ -0,0 +1,425 @@
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use log::{info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::abi::{explorer_request, read_cached, write_cached, EXPLORER_CHAIN_IDS};
+
+// Cache kind used next to the ABI file; verified source never changes, so it has no TTL
+pub const SOURCE_CACHE_KIND: &str = "source";
+
+// Rough chars-per-token ratio used for the prompt budget
+const CHARS_PER_TOKEN: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifiedSource {
+    pub address: Address,
+    pub chain_id: String,
+    pub contract_name: String,
+    pub compiler_version: String,
+    pub language: String, // "Solidity" or "Vyper"
+    pub optimization_used: bool,
+    pub runs: Option<u64>,
+    pub evm_version: Option<String>,
+    pub settings: Option<Value>, // Standard-JSON settings when the source was verified that way
+    pub files: BTreeMap<String, String>, // Path -> content
+    pub fetched_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceExcerpt {
+    pub file: String,
+    pub start_line: usize, // 1-based, inclusive
+    pub end_line: usize,
+    pub kind: String, // "function", "modifier" or "constant"
+    pub name: String,
+    pub code: String,
+}
+
+/// Returns the verified source from the cache, or fetches it with `getsourcecode`.
+/// Ok(None) means the contract is not verified on any explorer chain.
+pub async fn get_or_fetch_source(
+    contract_address: &Address,
+) -> Result<Option<VerifiedSource>, Box<dyn std::error::Error>> {
+    if let Some(source) = read_cached::<VerifiedSource>(contract_address, SOURCE_CACHE_KIND) {
+        info!("📖 Loading verified source from local cache: {:?}", contract_address);
+        return Ok(Some(source));
+    }
+
+    let client = Client::new();
+    for chain_id in EXPLORER_CHAIN_IDS {
+        match fetch_source(&client, chain_id, contract_address).await {
+            Ok(Some(source)) => return Ok(Some(source)),
+            Ok(None) => {}
+            Err(e) => info!("⚠️ No source on chain {}: {}", chain_id, e),
+        }
+    }
+    Ok(None)
+}
+
+/// Fetches and parses `getsourcecode` for one chain, caching the result when verified.
+pub async fn fetch_source(
+    client: &Client,
+    chain_id: &str,
+    contract_address: &Address,
+) -> Result<Option<VerifiedSource>, Box<dyn std::error::Error>> {
+    let result = explorer_request(
+        client,
+        chain_id,
+        &format!("module=contract&action=getsourcecode&address={:?}", contract_address),
+    )
+    .await?;
+
+    let source = parse_source_entry(contract_address, chain_id, &result[0]);
+    if let Some(source) = &source {
+        info!(
+            "📜 Verified source found for {:?}: {} ({} file(s), {})",
+            contract_address,
+            source.contract_name,
+            source.files.len(),
+            source.compiler_version
+        );
+        if let Err(e) = write_cached(contract_address, SOURCE_CACHE_KIND, source) {
+            warn!("⚠️ Failed to cache verified source: {}", e);
+        }
+    }
+    Ok(source)
+}
+
+fn parse_source_entry(address: &Address, chain_id: &str, entry: &Value) -> Option<VerifiedSource> {
+    let raw = entry["SourceCode"].as_str().unwrap_or_default().trim();
+    if raw.is_empty() {
+        return None;
+    }
+
+    let contract_name = entry["ContractName"].as_str().unwrap_or_default().to_string();
+    let mut settings = None;
+    let mut language = "Solidity".to_string();
+    let mut files = BTreeMap::new();
+
+    // Standard-JSON input is wrapped in an extra pair of braces: {{ ... }}
+    let json_text = if raw.starts_with("{{") && raw.ends_with("}}") {
+        Some(&raw[1..raw.len() - 1])
+    } else if raw.starts_with('{') {
+        Some(raw)
+    } else {
+        None
+    };
+
+    match json_text.and_then(|text| serde_json::from_str::<Value>(text).ok()) {
+        Some(json) => {
+            // Either {"language", "sources", "settings"} or a bare {"path": {"content"}} map
+            let sources = if json.get("sources").is_some() {
+                settings = json.get("settings").cloned();
+                if let Some(lang) = json["language"].as_str() {
+                    language = lang.to_string();
+                }
+                json["sources"].clone()
+            } else {
+                json
+            };
+            if let Some(map) = sources.as_object() {
+                for (path, file) in map {
+                    if let Some(content) = file["content"].as_str() {
+                        files.insert(path.clone(), content.to_string());
+                    }
+                }
+            }
+        }
+        None => {
+            files.insert(format!("{}.sol", contract_name), raw.to_string());
+        }
+    }
+
+    if entry["CompilerVersion"].as_str().unwrap_or_default().starts_with("vyper") {
+        language = "Vyper".to_string();
+    }
+
+    Some(VerifiedSource {
+        address: *address,
+        chain_id: chain_id.to_string(),
+        contract_name,
+        compiler_version: entry["CompilerVersion"].as_str().unwrap_or_default().to_string(),
+        language,
+        optimization_used: entry["OptimizationUsed"].as_str() == Some("1"),
+        runs: entry["Runs"].as_str().and_then(|r| r.parse().ok()),
+        evm_version: entry["EVMVersion"]
+            .as_str()
+            .filter(|v| !v.is_empty() && *v != "Default")
+            .map(|v| v.to_string()),
+        settings,
+        files,
+        fetched_at: Utc::now(),
+    })
+}
+
+/// Replaces comments and string contents with spaces, keeping byte offsets and newlines,
+/// so structural scanning (braces, keywords) is not fooled by them.
+pub fn mask_comments_and_strings(code: &str) -> String {
+    let bytes = code.as_bytes();
+    let mut out = bytes.to_vec();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    out[i] = b' ';
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    if bytes[i] != b'\n' {
+                        out[i] = b' ';
+                    }
+                    i += 1;
+                }
+                let close_end = (i + 2).min(bytes.len());
+                out[i..close_end].fill(b' ');
+                i += 2;
+            }
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    if bytes[i] == b'\\' && i + 1 < bytes.len() {
+                        out[i] = b' ';
+                        i += 1;
+                    }
+                    out[i] = b' ';
+                    i += 1;
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    // Only ASCII bytes were replaced, whole multi-byte characters included, so this stays UTF-8
+    String::from_utf8(out).unwrap_or_else(|_| code.to_string())
+}
+
+/// Byte offset of the brace closing the one at `open`, in masked code.
+pub fn matching_brace(masked: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, b) in masked.bytes().enumerate().skip(open) {
+        match b {
+            b'{' => depth += 1,
+            b'}' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+pub fn line_of(code: &str, offset: usize) -> usize {
+    code[..offset.min(code.len())].matches('\n').count() + 1
+}
+
+fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$'
+}
+
+/// Finds `<keyword> <name>` definitions with a body, returning (start, end) byte offsets
+/// covering the header through the closing brace.
+pub fn find_definitions(masked: &str, keyword: &str, name: Option<&str>) -> Vec<(String, usize, usize)> {
+    let bytes = masked.as_bytes();
+    let mut found = Vec::new();
+    let mut search_from = 0;
+
+    while let Some(pos) = masked[search_from..].find(keyword) {
+        let start = search_from + pos;
+        search_from = start + keyword.len();
+
+        // Whole-word keyword followed by whitespace
+        if start > 0 && is_ident_byte(bytes[start - 1]) {
+            continue;
+        }
+        let mut i = start + keyword.len();
+        if i >= bytes.len() || !bytes[i].is_ascii_whitespace() {
+            continue;
+        }
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let name_start = i;
+        while i < bytes.len() && is_ident_byte(bytes[i]) {
+            i += 1;
+        }
+        let found_name = &masked[name_start..i];
+        if found_name.is_empty() || name.map(|n| n != found_name).unwrap_or(false) {
+            continue;
+        }
+
+        // The body starts at the first '{' unless a ';' ends the declaration first
+        let rest = &masked[i..];
+        let brace = rest.find('{');
+        let semicolon = rest.find(';');
+        let open = match (brace, semicolon) {
+            (Some(b), Some(s)) if s < b => continue,
+            (Some(b), _) => i + b,
+            (None, _) => continue,
+        };
+        if let Some(close) = matching_brace(masked, open) {
+            found.push((found_name.to_string(), start, close + 1));
+            search_from = close + 1;
+        }
+    }
+
+    found
+}
+
+/// Extracts the source relevant to the decoded function: its body (every overload),
+/// the modifiers it uses and the constants it references, within a token budget.
+/// Files of the main contract come first.
+pub fn extract_function_excerpts(
+    source: &VerifiedSource,
+    function_name: &str,
+    token_budget: usize,
+) -> Vec<SourceExcerpt> {
+    let mut candidates: Vec<SourceExcerpt> = Vec::new();
+
+    // (path, content, masked content), the main contract first and libraries last
+    let mut files: Vec<(&String, &String, String)> = source
+        .files
+        .iter()
+        .map(|(path, content)| (path, content, mask_comments_and_strings(content)))
+        .collect();
+    let main_contract = format!("contract {}", source.contract_name);
+    files.sort_by_key(|(path, _, masked)| {
+        (!masked.contains(&main_contract), path.contains("@openzeppelin"))
+    });
+
+    for (path, content, masked) in &files {
+        for (name, start, end) in find_definitions(masked, "function", Some(function_name)) {
+            candidates.push(excerpt(path, content, "function", &name, start, end));
+
+            let header_end = masked[start..end].find('{').map(|b| start + b).unwrap_or(end);
+            let header = &masked[start..header_end];
+            let body = &masked[start..end];
+
+            for (file_path, file_content, file_masked) in &files {
+                // Modifiers named in the header
+                for (modifier, m_start, m_end) in find_definitions(file_masked, "modifier", None) {
+                    if contains_word(header, &modifier)
+                        && !candidates.iter().any(|c| c.kind == "modifier" && c.name == modifier)
+                    {
+                        candidates.push(excerpt(file_path, file_content, "modifier", &modifier, m_start, m_end));
+                    }
+                }
+
+                // Constants and immutables referenced in the body
+                for (line_no, (line, masked_line)) in file_content.lines().zip(file_masked.lines()).enumerate() {
+                    if !(masked_line.contains(" constant ") || masked_line.contains(" immutable ")) {
+                        continue;
+                    }
+                    let ident = masked_line
+                        .split('=')
+                        .next()
+                        .and_then(|decl| decl.split_whitespace().last())
+                        .unwrap_or_default()
+                        .trim_end_matches(';');
+                    if !ident.is_empty()
+                        && contains_word(body, ident)
+                        && !candidates.iter().any(|c| c.kind == "constant" && c.name == ident)
+                    {
+                        candidates.push(SourceExcerpt {
+                            file: file_path.to_string(),
+                            start_line: line_no + 1,
+                            end_line: line_no + 1,
+                            kind: "constant".to_string(),
+                            name: ident.to_string(),
+                            code: line.trim().to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Functions first, then constants (short and telling), then modifiers
+    candidates.sort_by_key(|c| match c.kind.as_str() {
+        "function" => 0,
+        "constant" => 1,
+        _ => 2,
+    });
+
+    let mut budget = token_budget * CHARS_PER_TOKEN;
+    let mut selected = Vec::new();
+    for mut candidate in candidates {
+        if budget == 0 {
+            break;
+        }
+        if candidate.code.len() > budget {
+            let cut = floor_char_boundary(&candidate.code, budget);
+            candidate.code = format!("{}\n// ... truncated", &candidate.code[..cut]);
+            budget = 0;
+        } else {
+            budget -= candidate.code.len();
+        }
+        selected.push(candidate);
+    }
+    selected
+}
+
+fn excerpt(path: &str, content: &str, kind: &str, name: &str, start: usize, end: usize) -> SourceExcerpt {
+    SourceExcerpt {
+        file: path.to_string(),
+        start_line: line_of(content, start),
+        end_line: line_of(content, end),
+        kind: kind.to_string(),
+        name: name.to_string(),
+        code: content[start..end].to_string(),
+    }
+}
+
+pub fn contains_word(text: &str, word: &str) -> bool {
+    let bytes = text.as_bytes();
+    text.match_indices(word).any(|(i, _)| {
+        let before = i == 0 || !is_ident_byte(bytes[i - 1]);
+        let after = i + word.len() >= bytes.len() || !is_ident_byte(bytes[i + word.len()]);
+        before && after
+    })
+}
+
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Renders the excerpts for the LLM prompt.
+pub fn format_source_for_prompt(source: Option<&VerifiedSource>, excerpts: &[SourceExcerpt]) -> String {
+    let source = match source {
+        Some(source) => source,
+        None => return "Not available (contract not verified)".to_string(),
+    };
+    if excerpts.is_empty() {
+        return format!(
+            "Verified as {} ({}), but no definition of the called function was found",
+            source.contract_name, source.compiler_version
+        );
+    }
+
+    let mut parts = vec![format!(
+        "Verified contract {} ({})",
+        source.contract_name, source.compiler_version
+    )];
+    for e in excerpts {
+        parts.push(format!(
+            "// {} {} - {}:{}-{}\n{}",
+            e.kind, e.name, e.file, e.start_line, e.end_line, e.code
+        ));
+    }
+    parts.join("\n\n")
+}
@@ -0,0 +1,346 @@
+use log::info;
+use std::collections::BTreeMap;
+
+use crate::rules::{Finding, Severity};
+use crate::source::{contains_word, find_definitions, line_of, mask_comments_and_strings, VerifiedSource};
+
+// Library code is audited upstream and would only add noise
+const SKIPPED_PATHS: [&str; 3] = ["@openzeppelin/", "node_modules/", "lib/forge-std/"];
+
+// Names that suggest funds stay with the user or in the contract
+const TRUSTED_NAME_HINTS: [&str; 10] = [
+    "deposit", "claim", "stake", "vault", "save", "secure", "airdrop", "reward", "verify", "protect",
+];
+
+// Fragments of a line that moves ETH or tokens
+const VALUE_TRANSFER_PATTERNS: [&str; 7] = [
+    ".transfer(",
+    ".send(",
+    ".call{",
+    ".call(",
+    "transferFrom(",
+    "safeTransfer",
+    "sendValue(",
+];
+
+const BLACKLIST_NAME_HINTS: [&str; 6] = ["blacklist", "blocklist", "denylist", "bot", "ban", "freeze"];
+const FEE_NAME_HINTS: [&str; 3] = ["fee", "tax", "slippage"];
+
+// One function of a scanned file, with byte offsets into the file
+struct FunctionDef<'a> {
+    name: String,
+    header: &'a str, // Masked, up to the opening brace
+    body: &'a str,   // Masked, whole definition
+    start: usize,
+}
+
+// An `address constant X = 0x...` (or immutable) declaration
+struct AddressConstant {
+    name: String,
+    line: usize,
+}
+
+/// Scans verified Solidity for patterns typical of drainers and honeypot tokens.
+/// `called_function` escalates findings in the function the analyzed call targets.
+pub fn scan_source(source: &VerifiedSource, called_function: Option<&str>) -> Vec<Finding> {
+    if source.language != "Solidity" {
+        info!("ℹ️ Source scanner skipped for {} contract", source.language);
+        return Vec::new();
+    }
+
+    let mut findings = Vec::new();
+    for (path, content) in &source.files {
+        if SKIPPED_PATHS.iter().any(|skipped| path.contains(skipped)) {
+            continue;
+        }
+        findings.extend(scan_file(path, content, called_function));
+    }
+
+    info!(
+        "🔬 Source scan of {} finished - {} finding(s)",
+        source.contract_name,
+        findings.len()
+    );
+    findings
+}
+
+fn scan_file(path: &str, content: &str, called_function: Option<&str>) -> Vec<Finding> {
+    let masked = mask_comments_and_strings(content);
+    let constants = address_constants(&masked);
+    let functions: Vec<FunctionDef> = find_definitions(&masked, "function", None)
+        .into_iter()
+        .map(|(name, start, end)| {
+            let header_end = masked[start..end].find('{').map(|b| start + b).unwrap_or(end);
+            FunctionDef {
+                name,
+                header: &masked[start..header_end],
+                body: &masked[start..end],
+                start,
+            }
+        })
+        .collect();
+
+    let mut findings = Vec::new();
+    findings.extend(tx_origin_auth(path, &masked));
+    findings.extend(hardcoded_recipients(path, &masked, &constants));
+
+    for function in &functions {
+        let start_line = line_of(content, function.start);
+        let end_line = start_line + function.body.matches('\n').count();
+        let is_called = called_function == Some(function.name.as_str());
+        let restricted = is_restricted(function);
+        let lower_name = function.name.to_lowercase();
+
+        if function.body.contains("selfdestruct(") {
+            let finding = if restricted {
+                Finding::new(
+                    "source.selfdestruct",
+                    Severity::Medium,
+                    "Contract can be destroyed",
+                    format!("`{}` calls selfdestruct behind an access check", function.name),
+                )
+            } else {
+                Finding::new(
+                    "source.unrestricted_selfdestruct",
+                    Severity::Critical,
+                    "Anyone can destroy the contract",
+                    format!("`{}` calls selfdestruct without any access check", function.name),
+                )
+            };
+            findings.push(finding.in_source(path, start_line, end_line));
+        }
+
+        if restricted && pulls_user_tokens(function.body) {
+            findings.push(
+                Finding::new(
+                    "source.owner_transfer_from",
+                    Severity::High,
+                    "Privileged function moves user tokens",
+                    format!(
+                        "`{}` is restricted to a privileged caller and calls transferFrom on an arbitrary holder, so approvals given to this contract can be drained",
+                        function.name
+                    ),
+                )
+                .in_source(path, start_line, end_line),
+            );
+        }
+
+        if restricted && BLACKLIST_NAME_HINTS.iter().any(|hint| lower_name.contains(hint)) {
+            findings.push(
+                Finding::new(
+                    "source.blacklist_control",
+                    Severity::High,
+                    "Owner can block addresses",
+                    format!(
+                        "`{}` lets a privileged caller block or freeze addresses, a common honeypot mechanism to stop holders from selling",
+                        function.name
+                    ),
+                )
+                .in_source(path, start_line, end_line),
+            );
+        }
+
+        let changes_fee = ["set", "update", "change"].iter().any(|p| lower_name.starts_with(p))
+            && FEE_NAME_HINTS.iter().any(|hint| lower_name.contains(hint));
+        if restricted && changes_fee {
+            // A require with an upper bound at least caps what the owner can set
+            let bounded = function
+                .body
+                .lines()
+                .any(|line| line.contains("require(") && line.contains('<'));
+            findings.push(
+                Finding::new(
+                    "source.fee_control",
+                    if bounded { Severity::Medium } else { Severity::High },
+                    "Owner can change transfer fees",
+                    format!(
+                        "`{}` lets a privileged caller change fees or taxes{}",
+                        function.name,
+                        if bounded { " (bounded by a require)" } else { " without an upper bound, up to 100%" }
+                    ),
+                )
+                .in_source(path, start_line, end_line),
+            );
+        }
+
+        if TRUSTED_NAME_HINTS.iter().any(|hint| lower_name.contains(hint)) {
+            if let Some(recipient) = hardcoded_transfer_target(function.body, &constants) {
+                findings.push(
+                    Finding::new(
+                        "source.misleading_name",
+                        if is_called { Severity::Critical } else { Severity::High },
+                        "Function name hides where funds go",
+                        format!(
+                            "`{}` sounds like funds stay with you or the contract, but it sends them to the hardcoded address {}{}",
+                            function.name,
+                            recipient,
+                            if is_called { ". The analyzed call targets this function" } else { "" }
+                        ),
+                    )
+                    .in_source(path, start_line, end_line),
+                );
+            }
+        }
+    }
+
+    findings
+}
+
+// `tx.origin` used in a comparison for authorization (the `tx.origin == msg.sender` anti-contract check is left alone)
+fn tx_origin_auth(path: &str, masked: &str) -> Vec<Finding> {
+    masked
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            line.contains("tx.origin")
+                && (line.contains("==") || line.contains("!="))
+                && !line.contains("msg.sender")
+        })
+        .map(|(index, _)| {
+            Finding::new(
+                "source.tx_origin_auth",
+                Severity::High,
+                "Authorization through tx.origin",
+                "Access is checked against tx.origin, so any contract the owner interacts with can act on their behalf (phishing relay)",
+            )
+            .in_source(path, index + 1, index + 1)
+        })
+        .collect()
+}
+
+fn address_constants(masked: &str) -> Vec<AddressConstant> {
+    masked
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let trimmed = line.trim_start();
+            if !trimmed.starts_with("address")
+                || !(contains_word(line, "constant") || contains_word(line, "immutable"))
+            {
+                return None;
+            }
+            let (declaration, value) = line.split_once('=')?;
+            literal_address(value)?;
+            let name = declaration.split_whitespace().last()?.to_string();
+            Some(AddressConstant { name, line: index + 1 })
+        })
+        .collect()
+}
+
+// First 0x-prefixed 40 hex digit literal in the text
+fn literal_address(text: &str) -> Option<&str> {
+    text.match_indices("0x").find_map(|(i, _)| {
+        let candidate = text.get(i..i + 42)?;
+        let well_formed = candidate[2..].chars().all(|c| c.is_ascii_hexdigit())
+            && !text[i + 42..].starts_with(|c: char| c.is_ascii_hexdigit());
+        well_formed.then_some(candidate)
+    })
+}
+
+fn hardcoded_recipients(path: &str, masked: &str, constants: &[AddressConstant]) -> Vec<Finding> {
+    constants
+        .iter()
+        .filter_map(|constant| {
+            let uses: Vec<usize> = masked
+                .lines()
+                .enumerate()
+                .filter(|(index, line)| {
+                    index + 1 != constant.line && contains_word(line, &constant.name) && moves_value(line)
+                })
+                .map(|(index, _)| index + 1)
+                .collect();
+            if uses.is_empty() {
+                return None;
+            }
+            Some(
+                Finding::new(
+                    "source.hardcoded_recipient",
+                    Severity::Medium,
+                    "Funds sent to a hardcoded address",
+                    format!(
+                        "The constant `{}` receives ETH or tokens (line(s) {})",
+                        constant.name,
+                        uses.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", ")
+                    ),
+                )
+                .in_source(path, constant.line, constant.line),
+            )
+        })
+        .collect()
+}
+
+fn moves_value(line: &str) -> bool {
+    line.contains("payable(") || VALUE_TRANSFER_PATTERNS.iter().any(|pattern| line.contains(pattern))
+}
+
+// The constant name or literal address a function body sends value to, if any
+fn hardcoded_transfer_target(body: &str, constants: &[AddressConstant]) -> Option<String> {
+    body.lines().filter(|line| moves_value(line)).find_map(|line| {
+        constants
+            .iter()
+            .find(|constant| contains_word(line, &constant.name))
+            .map(|constant| constant.name.clone())
+            .or_else(|| literal_address(line).map(|address| address.to_string()))
+    })
+}
+
+// An `only*` modifier or an explicit check on msg.sender
+fn is_restricted(function: &FunctionDef) -> bool {
+    access_guard(function.header, function.body).is_some()
+}
+
+// Describes how a function is restricted: the `only*` modifier, or the kind of inline check
+fn access_guard(header: &str, body: &str) -> Option<String> {
+    // UUPS upgrades are authorized inside the body, onlyProxy only checks the call context
+    // `body` starts with the header, which for _authorizeUpgrade itself contains the name
+    if body.get(header.len()..).unwrap_or_default().contains("_authorizeUpgrade(") {
+        return Some("_authorizeUpgrade".to_string());
+    }
+    if let Some(modifier) = header
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .find(|word| (word.starts_with("only") && word.len() > 4) || *word == "ifAdmin")
+    {
+        return Some(modifier.to_string());
+    }
+    if body.contains("hasRole(") {
+        return Some("role check".to_string());
+    }
+    ["msg.sender ==", "== msg.sender", "msg.sender !=", "!= msg.sender", "_checkOwner("]
+        .iter()
+        .any(|check| body.contains(check))
+        .then(|| "msg.sender check".to_string())
+}
+
+/// Every function defined in the verified source with its access guard, if any, by function name.
+/// Library files count too, inherited functions such as `transferOwnership` are guarded there.
+/// A name is guarded when any of its definitions is.
+pub fn access_guards(source: &VerifiedSource) -> BTreeMap<String, Option<String>> {
+    let mut guards: BTreeMap<String, Option<String>> = BTreeMap::new();
+    for content in source.files.values() {
+        let masked = mask_comments_and_strings(content);
+        for (name, start, end) in find_definitions(&masked, "function", None) {
+            let header_end = masked[start..end].find('{').map(|b| start + b).unwrap_or(end);
+            let guard = access_guard(&masked[start..header_end], &masked[start..end]);
+            let entry = guards.entry(name).or_default();
+            if entry.is_none() {
+                *entry = guard;
+            }
+        }
+    }
+    guards
+}
+
+// transferFrom/safeTransferFrom whose `from` is neither the caller nor the contract
+fn pulls_user_tokens(body: &str) -> bool {
+    ["transferFrom(", "safeTransferFrom("].iter().any(|call| {
+        body.match_indices(call).any(|(i, _)| {
+            let args = &body[i + call.len()..];
+            let mut first = args.split(',').next().unwrap_or_default().trim();
+            // safeTransferFrom(token, from, ...) from SafeERC20 takes the token first
+            if *call == "safeTransferFrom(" && body[..i].ends_with("SafeERC20.") {
+                first = args.split(',').nth(1).unwrap_or_default().trim();
+            }
+            !first.is_empty() && first != "msg.sender" && first != "address(this)" && first != "_msgSender()"
+        })
+    })
+}
@@ -0,0 +1,90 @@
+use actix_web::web::Bytes;
+use futures_util::stream::{self, Stream};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::rules::Finding;
+use crate::token_metadata::TokenAmount;
+use crate::AnalysisResponse;
+
+// Events of the /analysis/stream endpoint, in the order they are sent
+pub enum AnalysisEvent {
+    Decode {
+        function_name: String,
+        arguments: Vec<String>,
+        formatted_arguments: Vec<String>,
+        token_amounts: Vec<TokenAmount>,
+    },
+    Findings {
+        findings: Vec<Finding>,
+        risk_score: u32,
+    },
+    Token {
+        text: String,
+    },
+    Retry {
+        attempt: usize,
+        error: String,
+    },
+    Fallback {
+        model: String,
+        reason: String,
+    },
+    Verdict(Box<AnalysisResponse>), // Same body as /analysis
+    Error(Box<AnalysisResponse>),
+}
+
+impl AnalysisEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnalysisEvent::Decode { .. } => "decode",
+            AnalysisEvent::Findings { .. } => "findings",
+            AnalysisEvent::Token { .. } => "token",
+            AnalysisEvent::Retry { .. } => "retry",
+            AnalysisEvent::Fallback { .. } => "fallback",
+            AnalysisEvent::Verdict(_) => "verdict",
+            AnalysisEvent::Error(_) => "error",
+        }
+    }
+
+    fn data(&self) -> Value {
+        match self {
+            AnalysisEvent::Decode {
+                function_name,
+                arguments,
+                formatted_arguments,
+                token_amounts,
+            } => json!({
+                "function_name": function_name,
+                "arguments": arguments,
+                "formatted_arguments": formatted_arguments,
+                "token_amounts": token_amounts,
+            }),
+            AnalysisEvent::Findings { findings, risk_score } => json!({
+                "findings": findings,
+                "risk_score": risk_score,
+            }),
+            AnalysisEvent::Token { text } => json!({ "text": text }),
+            AnalysisEvent::Retry { attempt, error } => json!({ "attempt": attempt, "error": error }),
+            AnalysisEvent::Fallback { model, reason } => json!({ "model": model, "reason": reason }),
+            AnalysisEvent::Verdict(response) | AnalysisEvent::Error(response) => {
+                serde_json::to_value(response).unwrap_or(Value::Null)
+            }
+        }
+    }
+
+    /// Encodes the event as one `event:`/`data:` block of a `text/event-stream` body.
+    pub fn to_sse(&self) -> Bytes {
+        Bytes::from(format!("event: {}\ndata: {}\n\n", self.name(), self.data()))
+    }
+}
+
+/// Body stream that ends once the sending analysis task drops its sender.
+pub fn event_stream(
+    receiver: UnboundedReceiver<AnalysisEvent>,
+) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        Some((Ok(event.to_sse()), receiver))
+    })
+}
@@ -0,0 +1,282 @@
+use chrono::{DateTime, Utc};
+use ethabi::{Function, ParamType, Token};
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, U256};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::abi::{read_cached, write_cached};
+use crate::rpc::{encode_call, eth_call};
+
+// Cache kind used next to the ABI file; token metadata does not change
+const TOKEN_CACHE_KIND: &str = "token";
+
+// Fractional digits kept when rendering amounts
+const MAX_FRACTION_DIGITS: usize = 6;
+
+// ERC-20 functions and the position of their amount argument, which is denominated in the target token
+const ERC20_AMOUNT_ARGUMENTS: [(&str, usize); 9] = [
+    ("transfer(address,uint256)", 1),
+    ("approve(address,uint256)", 1),
+    ("transferFrom(address,address,uint256)", 2),
+    ("increaseAllowance(address,uint256)", 1),
+    ("decreaseAllowance(address,uint256)", 1),
+    ("permit(address,address,uint256,uint256,uint8,bytes32,bytes32)", 2),
+    ("burn(uint256)", 0),
+    ("burnFrom(address,uint256)", 1),
+    ("mint(address,uint256)", 1),
+];
+
+// Functions whose amount argument is an allowance rather than a transfer
+const APPROVAL_FUNCTIONS: [&str; 3] = ["approve", "increaseAllowance", "permit"];
+// Functions whose amount argument does not leave the sender
+const NON_TRANSFER_FUNCTIONS: [&str; 2] = ["mint", "decreaseAllowance"];
+
+// Parameter names (lowercase, without leading underscores) that usually hold a token amount
+const AMOUNT_PARAMETER_NAMES: [&str; 16] = [
+    "amount",
+    "value",
+    "wad",
+    "amountin",
+    "amountout",
+    "amountinmax",
+    "amountoutmin",
+    "amountinmaximum",
+    "amountoutminimum",
+    "amountadesired",
+    "amountbdesired",
+    "amountamin",
+    "amountbmin",
+    "amounttokendesired",
+    "amounttokenmin",
+    "amountethmin",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub address: Address,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>, // None when the address does not answer decimals()
+    pub fetched_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenAmount {
+    pub argument: usize,
+    pub parameter: String,
+    pub token: Option<Address>, // None for native ETH amounts
+    pub symbol: String,
+    pub decimals: u8,
+    pub raw: String,
+    pub formatted: String, // e.g. "1,250.5 USDC" or "unlimited USDC"
+    pub unlimited: bool,
+}
+
+/// Returns the cached metadata, or reads `name()`, `symbol()` and `decimals()` from the node.
+pub async fn get_or_fetch_token_metadata(provider: &Provider<Http>, token: &Address) -> TokenMetadata {
+    if let Some(metadata) = read_cached::<TokenMetadata>(token, TOKEN_CACHE_KIND) {
+        return metadata;
+    }
+
+    info!("🪙 Fetching token metadata for {:?}", token);
+    let metadata = TokenMetadata {
+        address: *token,
+        name: call_string(provider, token, "name()").await,
+        symbol: call_string(provider, token, "symbol()").await,
+        decimals: call_decimals(provider, token).await,
+        fetched_at: Utc::now(),
+    };
+    // A failed decimals() may be a transient RPC error, so only complete metadata is kept
+    if metadata.decimals.is_none() {
+        warn!("⚠️ No decimals() for {:?}, token metadata not cached", token);
+    } else if let Err(e) = write_cached(token, TOKEN_CACHE_KIND, &metadata) {
+        warn!("⚠️ Failed to cache token metadata: {}", e);
+    }
+    metadata
+}
+
+async fn call_decimals(provider: &Provider<Http>, token: &Address) -> Option<u8> {
+    let output = eth_call(provider, token, &encode_call("decimals()", &[])).await.ok()?;
+    match ethabi::decode(&[ParamType::Uint(8)], &output).ok()?.first() {
+        Some(Token::Uint(decimals)) if *decimals <= U256::from(77) => Some(decimals.low_u32() as u8),
+        _ => None,
+    }
+}
+
+// string return value, or bytes32 for older tokens such as MKR
+async fn call_string(provider: &Provider<Http>, token: &Address, signature: &str) -> Option<String> {
+    let output = eth_call(provider, token, &encode_call(signature, &[])).await.ok()?;
+    let text = match ethabi::decode(&[ParamType::String], &output) {
+        Ok(tokens) => match tokens.first() {
+            Some(Token::String(text)) => text.clone(),
+            _ => return None,
+        },
+        Err(_) => {
+            let word = output.get(..32)?;
+            String::from_utf8_lossy(word).trim_end_matches('\0').to_string()
+        }
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Finds the token amounts among the decoded arguments, through the ERC-20 selectors and
+/// well-known parameter names, and renders them with the token's decimals and symbol.
+pub async fn detect_token_amounts(
+    provider: &Provider<Http>,
+    function: &Function,
+    args: &[Token],
+    target: &Address,
+) -> Vec<TokenAmount> {
+    let signature = function.signature();
+    let signature = signature.split(':').next().unwrap_or_default();
+    let erc20_position = ERC20_AMOUNT_ARGUMENTS
+        .iter()
+        .find(|(known, _)| *known == signature)
+        .map(|(_, position)| *position);
+
+    let mut amounts = Vec::new();
+    for (index, (param, arg)) in function.inputs.iter().zip(args).enumerate() {
+        let raw = match arg {
+            Token::Uint(raw) => *raw,
+            _ => continue,
+        };
+        let name = param.name.trim_start_matches('_').to_lowercase();
+
+        let token = if erc20_position == Some(index) {
+            Some(*target)
+        } else if AMOUNT_PARAMETER_NAMES.contains(&name.as_str()) {
+            if name.contains("eth") {
+                amounts.push(token_amount(index, &param.name, None, "ETH", 18, raw));
+                continue;
+            }
+            amount_token(function, args, &name).or(Some(*target))
+        } else {
+            None
+        };
+
+        let token = match token {
+            Some(token) => token,
+            None => continue,
+        };
+        let metadata = get_or_fetch_token_metadata(provider, &token).await;
+        if let Some(decimals) = metadata.decimals {
+            let symbol = metadata.symbol.clone().unwrap_or_else(|| format!("{:?}", token));
+            amounts.push(token_amount(index, &param.name, Some(token), &symbol, decimals, raw));
+        }
+    }
+    amounts
+}
+
+// The token an amount parameter refers to, from a swap path or a token/tokenA/tokenB argument
+fn amount_token(function: &Function, args: &[Token], amount_name: &str) -> Option<Address> {
+    let arg_named = |wanted: &[&str]| {
+        function
+            .inputs
+            .iter()
+            .zip(args)
+            .find(|(param, _)| wanted.contains(&param.name.trim_start_matches('_').to_lowercase().as_str()))
+            .map(|(_, arg)| arg)
+    };
+
+    if let Some(Token::Array(path)) = arg_named(&["path"]) {
+        let hop = if amount_name.starts_with("amountin") { path.first() } else { path.last() };
+        if let Some(Token::Address(token)) = hop {
+            return Some(*token);
+        }
+    }
+
+    let candidates: &[&str] = if amount_name.starts_with("amounta") {
+        &["tokena"]
+    } else if amount_name.starts_with("amountb") {
+        &["tokenb"]
+    } else {
+        &["token", "asset", "tokenin", "tokenaddress"]
+    };
+    match arg_named(candidates) {
+        Some(Token::Address(token)) => Some(*token),
+        _ => None,
+    }
+}
+
+fn token_amount(index: usize, parameter: &str, token: Option<Address>, symbol: &str, decimals: u8, raw: U256) -> TokenAmount {
+    let unlimited = is_unlimited(raw);
+    TokenAmount {
+        argument: index,
+        parameter: parameter.to_string(),
+        token,
+        symbol: symbol.to_string(),
+        decimals,
+        raw: raw.to_string(),
+        formatted: if unlimited {
+            format!("unlimited {}", symbol)
+        } else {
+            format!("{} {}", format_units(raw, decimals), symbol)
+        },
+        unlimited,
+    }
+}
+
+/// Amounts of 2^128 and above are far beyond any real supply: `type(uint256).max`,
+/// Permit2's `type(uint160).max` and similar "infinite approval" values.
+pub fn is_unlimited(raw: U256) -> bool {
+    raw >= U256::one() << 128
+}
+
+/// Renders base units with thousands separators, e.g. 1250500000 at 6 decimals -> "1,250.5".
+pub fn format_units(raw: U256, decimals: u8) -> String {
+    let digits = raw.to_string();
+    let decimals = decimals as usize;
+    let (integer, fraction) = if digits.len() > decimals {
+        let split = digits.len() - decimals;
+        (digits[..split].to_string(), digits[split..].to_string())
+    } else {
+        ("0".to_string(), format!("{:0>width$}", digits, width = decimals))
+    };
+
+    let mut grouped = String::new();
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+
+    let fraction: String = fraction.chars().take(MAX_FRACTION_DIGITS).collect();
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() && grouped == "0" && !raw.is_zero() {
+        // Dust below the shown precision must not read as zero
+        format!("<0.{:0>width$}", 1, width = MAX_FRACTION_DIGITS)
+    } else if fraction.is_empty() {
+        grouped
+    } else {
+        format!("{}.{}", grouped, fraction)
+    }
+}
+
+/// The decoded arguments with every detected amount replaced by its readable value.
+pub fn formatted_arguments(arguments: &[String], amounts: &[TokenAmount]) -> Vec<String> {
+    arguments
+        .iter()
+        .enumerate()
+        .map(|(index, argument)| match amounts.iter().find(|a| a.argument == index) {
+            Some(amount) if amount.unlimited => format!("{} (max approval, raw {})", amount.formatted, amount.raw),
+            Some(amount) => format!("{} (raw {})", amount.formatted, amount.raw),
+            None => argument.clone(),
+        })
+        .collect()
+}
+
+pub fn is_approval_function(function_name: &str) -> bool {
+    APPROVAL_FUNCTIONS.contains(&function_name)
+}
+
+/// Whether the amount leaves the sender: not an allowance, a mint, a minimum output or an expected amount out.
+pub fn is_outgoing_amount(function_name: &str, amount: &TokenAmount) -> bool {
+    let name = amount.parameter.to_lowercase();
+    !is_approval_function(function_name)
+        && !NON_TRANSFER_FUNCTIONS.contains(&function_name)
+        && !name.contains("out")
+        && !name.contains("min")
+}
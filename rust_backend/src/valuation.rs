@@ -0,0 +1,427 @@
+use chrono::{DateTime, TimeZone, Utc};
+use ethabi::{ParamType, Token};
+use ethers::providers::{Http, Provider};
+use ethers::types::{Address, I256, U256};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::rpc::{call_uint, encode_call, eth_call};
+use crate::rules::{Finding, Severity};
+use crate::simulation::{AssetKind, SimulationReport};
+use crate::token_metadata::{
+    format_units, get_or_fetch_token_metadata, is_approval_function, is_outgoing_amount, TokenAmount,
+};
+use crate::wallet_exposure::WalletExposure;
+
+// Below this the value-bearing findings are lowered one level
+const DUST_VALUE_USD: f64 = 10.0;
+// From this value on the value-bearing findings are raised one level
+const LARGE_VALUE_USD: f64 = 100_000.0;
+const HUGE_VALUE_USD: f64 = 1_000_000.0;
+
+// Findings whose impact depends on how much value moves
+const VALUE_BEARING_RULES: [&str; 4] = [
+    "simulation.native_forwarded",
+    "simulation.token_outflow",
+    "simulation.approval_granted",
+    "exposure.entire_balance",
+];
+
+// One entry of the price feed file: a Chainlink aggregator and/or a static USD price
+#[derive(Debug, Clone, Deserialize)]
+struct PriceFeedEntry {
+    asset: String,               // "native" or a token address
+    aggregator: Option<Address>, // <ASSET>/USD Chainlink Data Feed on the RPC network
+    usd: Option<f64>,            // Fallback when offline or when the feed is stale
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    Chainlink,
+    PriceFile,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AssetPrice {
+    pub usd: f64,
+    pub source: PriceSource,
+    pub updated_at: Option<DateTime<Utc>>, // Round timestamp for Chainlink prices
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExposureKind {
+    NativeTransfer,
+    TokenTransfer,
+    Approval,
+}
+
+// One native transfer, token transfer or approval with its USD value
+#[derive(Debug, Clone, Serialize)]
+pub struct Exposure {
+    pub kind: ExposureKind,
+    pub token: Option<Address>, // None for the native asset
+    pub symbol: String,
+    pub amount: String, // Readable units; for approvals the part of the allowance backed by the balance
+    pub unlimited: bool,
+    pub usd: Option<f64>, // None when no price or balance is available
+    pub price: Option<AssetPrice>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValueAtRisk {
+    pub total_usd: f64,
+    pub exposures: Vec<Exposure>,
+    pub unpriced: usize, // Exposures left out of the total
+}
+
+// Read once per process, the file only changes with a deployment
+static PRICE_FEEDS: OnceLock<HashMap<String, PriceFeedEntry>> = OnceLock::new();
+
+/// The price feed file from `PRICE_FEEDS_FILE` (default: `prices/price_feeds.json` in the crate).
+fn price_feeds() -> &'static HashMap<String, PriceFeedEntry> {
+    PRICE_FEEDS.get_or_init(|| {
+        let path = env::var("PRICE_FEEDS_FILE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| Path::new(env!("CARGO_MANIFEST_DIR")).join("prices").join("price_feeds.json"));
+        let entries: Vec<PriceFeedEntry> = match fs::read_to_string(&path).map(|c| serde_json::from_str(&c)) {
+            Ok(Ok(entries)) => entries,
+            Ok(Err(e)) => {
+                warn!("⚠️ Ignoring invalid price feed file {}: {}", path.display(), e);
+                Vec::new()
+            }
+            Err(e) => {
+                warn!("⚠️ No price feed file at {}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+        info!("💲 Loaded {} price feed(s) from {}", entries.len(), path.display());
+        entries
+            .into_iter()
+            .map(|entry| (entry.asset.to_lowercase(), entry))
+            .collect()
+    })
+}
+
+// Maximum age of a Chainlink round before the price file is preferred
+fn max_price_age_secs() -> i64 {
+    env::var("PRICE_MAX_AGE_SECS")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(86_400)
+}
+
+fn asset_key(token: Option<Address>) -> String {
+    match token {
+        Some(token) => format!("{:?}", token),
+        None => "native".to_string(),
+    }
+}
+
+/// Reads `latestRoundData()` and `decimals()` from a Chainlink aggregator.
+async fn chainlink_price(
+    provider: &Provider<Http>,
+    aggregator: &Address,
+) -> Result<AssetPrice, Box<dyn std::error::Error>> {
+    let output = eth_call(provider, aggregator, &encode_call("latestRoundData()", &[])).await?;
+    let round = ethabi::decode(
+        &[
+            ParamType::Uint(80),
+            ParamType::Int(256),
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Uint(80),
+        ],
+        &output,
+    )?;
+    let (answer, updated_at) = match (&round[1], &round[3]) {
+        (Token::Int(answer), Token::Uint(updated_at)) => (I256::from_raw(*answer), *updated_at),
+        _ => return Err("unexpected latestRoundData output".into()),
+    };
+    if answer <= I256::zero() {
+        return Err(format!("non-positive answer {}", answer).into());
+    }
+
+    let decimals = call_uint(provider, aggregator, "decimals()", &[]).await?;
+    let updated_at = Utc
+        .timestamp_opt(updated_at.low_u64() as i64, 0)
+        .single()
+        .ok_or("invalid round timestamp")?;
+    if (Utc::now() - updated_at).num_seconds() > max_price_age_secs() {
+        return Err(format!("stale round from {}", updated_at).into());
+    }
+
+    Ok(AssetPrice {
+        usd: to_units(answer.into_raw(), decimals.low_u32() as u8),
+        source: PriceSource::Chainlink,
+        updated_at: Some(updated_at),
+    })
+}
+
+// Resolves and memoizes USD prices for the assets of one analysis
+struct PriceOracle<'a> {
+    provider: Option<&'a Provider<Http>>,
+    feeds: &'static HashMap<String, PriceFeedEntry>,
+    prices: HashMap<String, Option<AssetPrice>>,
+}
+
+impl PriceOracle<'_> {
+    async fn price(&mut self, token: Option<Address>) -> Option<AssetPrice> {
+        let key = asset_key(token).to_lowercase();
+        if let Some(price) = self.prices.get(&key) {
+            return *price;
+        }
+
+        let entry = self.feeds.get(&key).cloned();
+        let mut price = None;
+        if let (Some(provider), Some(aggregator)) = (self.provider, entry.as_ref().and_then(|e| e.aggregator)) {
+            match chainlink_price(provider, &aggregator).await {
+                Ok(chainlink) => price = Some(chainlink),
+                Err(e) => warn!("⚠️ Chainlink feed {:?} unusable for {}: {}", aggregator, key, e),
+            }
+        }
+        if price.is_none() {
+            price = entry.and_then(|e| e.usd).map(|usd| AssetPrice {
+                usd,
+                source: PriceSource::PriceFile,
+                updated_at: None,
+            });
+        }
+
+        self.prices.insert(key, price);
+        price
+    }
+}
+
+/// Base units to a floating-point amount, precise enough for USD estimates.
+fn to_units(raw: U256, decimals: u8) -> f64 {
+    raw.to_string().parse::<f64>().unwrap_or(f64::MAX) / 10f64.powi(decimals as i32)
+}
+
+/// Values every native transfer, token transfer and approval of the call in USD.
+/// Transfers come from the simulated sender outflows when a trace is available, otherwise
+/// from the call value and the decoded amounts. Approvals count up to the sender's balance
+/// as read for the wallet exposure.
+pub async fn value_at_risk(
+    provider: Option<&Provider<Http>>,
+    wallet: Option<&WalletExposure>,
+    native_value: U256,
+    function_name: &str,
+    token_amounts: &[TokenAmount],
+    simulation: Option<&SimulationReport>,
+) -> ValueAtRisk {
+    let mut oracle = PriceOracle {
+        provider,
+        feeds: price_feeds(),
+        prices: HashMap::new(),
+    };
+    let mut exposures = Vec::new();
+    let is_approval = is_approval_function(function_name);
+
+    match simulation.filter(|report| report.traced) {
+        Some(report) => {
+            for change in report.asset_changes.iter().filter(|c| c.address == report.sender) {
+                let delta = match I256::from_dec_str(&change.delta) {
+                    Ok(delta) if delta.is_negative() => delta.unsigned_abs(),
+                    _ => continue,
+                };
+                let (kind, symbol, decimals) = match (change.asset, change.token, provider) {
+                    (AssetKind::Native, _, _) => (ExposureKind::NativeTransfer, "ETH".to_string(), 18),
+                    (AssetKind::Erc20, Some(token), Some(provider)) => {
+                        let metadata = get_or_fetch_token_metadata(provider, &token).await;
+                        match metadata.decimals {
+                            Some(decimals) => (
+                                ExposureKind::TokenTransfer,
+                                metadata.symbol.unwrap_or_else(|| format!("{:?}", token)),
+                                decimals,
+                            ),
+                            None => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+                let token = change.token.filter(|_| kind == ExposureKind::TokenTransfer);
+                let price = oracle.price(token).await;
+                exposures.push(exposure(kind, token, &symbol, delta, decimals, false, price));
+            }
+        }
+        None => {
+            if !native_value.is_zero() {
+                let price = oracle.price(None).await;
+                exposures.push(exposure(ExposureKind::NativeTransfer, None, "ETH", native_value, 18, false, price));
+            }
+            for amount in token_amounts.iter().filter(|a| is_outgoing_amount(function_name, a)) {
+                let kind = match amount.token {
+                    Some(_) => ExposureKind::TokenTransfer,
+                    None => ExposureKind::NativeTransfer,
+                };
+                let raw = U256::from_dec_str(&amount.raw).unwrap_or_default();
+                let price = oracle.price(amount.token).await;
+                exposures.push(exposure(kind, amount.token, &amount.symbol, raw, amount.decimals, amount.unlimited, price));
+            }
+        }
+    }
+
+    if is_approval {
+        for amount in token_amounts.iter().filter(|a| a.token.is_some()) {
+            let raw = U256::from_dec_str(&amount.raw).unwrap_or_default();
+            let balance = wallet.zip(amount.token).and_then(|(wallet, token)| wallet.balance_of(&token));
+            let price = oracle.price(amount.token).await;
+            let mut approved = exposure(
+                ExposureKind::Approval,
+                amount.token,
+                &amount.symbol,
+                balance.map_or(raw, |balance| raw.min(balance)),
+                amount.decimals,
+                amount.unlimited,
+                price,
+            );
+            // Without the balance an unlimited allowance cannot be valued
+            if balance.is_none() && amount.unlimited {
+                approved.amount = "unlimited".to_string();
+                approved.usd = None;
+            }
+            exposures.push(approved);
+        }
+    }
+
+    let total_usd = exposures.iter().filter_map(|e| e.usd).sum();
+    let unpriced = exposures.iter().filter(|e| e.usd.is_none()).count();
+    info!(
+        "💵 Value at risk: ${:.2} across {} exposure(s), {} unpriced",
+        total_usd,
+        exposures.len(),
+        unpriced
+    );
+    ValueAtRisk {
+        total_usd,
+        exposures,
+        unpriced,
+    }
+}
+
+fn exposure(
+    kind: ExposureKind,
+    token: Option<Address>,
+    symbol: &str,
+    raw: U256,
+    decimals: u8,
+    unlimited: bool,
+    price: Option<AssetPrice>,
+) -> Exposure {
+    Exposure {
+        kind,
+        token,
+        symbol: symbol.to_string(),
+        amount: format_units(raw, decimals),
+        unlimited,
+        usd: price.map(|price| to_units(raw, decimals) * price.usd),
+        price,
+    }
+}
+
+/// Raises or lowers the value-bearing findings according to the USD value at risk.
+/// Dust is only lowered when every exposure could be priced.
+pub fn scale_findings_by_value(findings: &mut [Finding], report: &ValueAtRisk) {
+    for finding in findings
+        .iter_mut()
+        .filter(|f| VALUE_BEARING_RULES.contains(&f.rule_id.as_str()))
+    {
+        let scaled = if report.total_usd >= LARGE_VALUE_USD {
+            match finding.severity {
+                Severity::Info => Severity::Info,
+                Severity::Low => Severity::Medium,
+                Severity::Medium => Severity::High,
+                Severity::High | Severity::Critical => Severity::Critical,
+            }
+        } else if report.total_usd < DUST_VALUE_USD && report.unpriced == 0 {
+            match finding.severity {
+                Severity::Critical => Severity::High,
+                Severity::High => Severity::Medium,
+                Severity::Medium | Severity::Low => Severity::Low,
+                Severity::Info => Severity::Info,
+            }
+        } else {
+            continue;
+        };
+        if scaled != finding.severity {
+            finding.description = format!(
+                "{} (severity {} -> {} for ${:.2} at risk)",
+                finding.description, finding.severity, scaled, report.total_usd
+            );
+            finding.severity = scaled;
+        }
+    }
+}
+
+/// Turns the value at risk into rule findings.
+pub fn value_findings(report: &ValueAtRisk) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if report.total_usd >= LARGE_VALUE_USD {
+        findings.push(
+            Finding::new(
+                "value.large_exposure",
+                if report.total_usd >= HUGE_VALUE_USD { Severity::High } else { Severity::Medium },
+                "Large value at risk",
+                format!("This call moves or approves about ${:.0} worth of assets", report.total_usd),
+            )
+            .at("valuation"),
+        );
+    }
+
+    if report.unpriced > 0 {
+        findings.push(
+            Finding::new(
+                "value.unpriced",
+                Severity::Info,
+                "Value at risk is incomplete",
+                format!(
+                    "{} exposure(s) have no price feed or balance and are not included in the total",
+                    report.unpriced
+                ),
+            )
+            .at("valuation"),
+        );
+    }
+
+    findings
+}
+
+/// Renders the value at risk as compact text for the LLM prompt.
+pub fn format_value_for_prompt(report: Option<&ValueAtRisk>) -> String {
+    let report = match report {
+        Some(report) if !report.exposures.is_empty() => report,
+        _ => return "None detected".to_string(),
+    };
+
+    let mut lines = vec![format!("Total: ${:.2}", report.total_usd)];
+    for exposure in &report.exposures {
+        let kind = match exposure.kind {
+            ExposureKind::NativeTransfer => "native transfer",
+            ExposureKind::TokenTransfer => "token transfer",
+            ExposureKind::Approval if exposure.unlimited => "unlimited approval, backed by balance",
+            ExposureKind::Approval => "approval",
+        };
+        let value = match (exposure.usd, exposure.price) {
+            (Some(usd), Some(price)) => format!(
+                "${:.2} ({} price ${:.4})",
+                usd,
+                match price.source {
+                    PriceSource::Chainlink => "Chainlink",
+                    PriceSource::PriceFile => "price file",
+                },
+                price.usd
+            ),
+            _ => "unpriced".to_string(),
+        };
+        lines.push(format!("- {}: {} {} = {}", kind, exposure.amount, exposure.symbol, value));
+    }
+    lines.join("\n")
+}
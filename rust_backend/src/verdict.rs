@@ -0,0 +1,172 @@
+use log::{info, warn};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+
+use crate::llm::{LlmError, LlmProvider};
+use crate::rules::{format_findings_for_prompt, rule_risk_level, Finding, RiskLevel, Severity};
+
+// A finding reported by the model, next to the deterministic rule findings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelFinding {
+    pub title: String,
+    pub severity: Severity,
+    pub description: String,
+}
+
+// The JSON object the model must answer with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LlmVerdict {
+    #[serde(deserialize_with = "lenient_risk_level")]
+    pub risk_level: RiskLevel,
+    pub explanation: String, // Markdown
+    pub findings: Vec<ModelFinding>,
+    pub dangerous_functions: Vec<String>,
+    pub confidence: f64, // 0.0 - 1.0
+}
+
+// Returned instead of a verdict when every attempt failed validation
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct UnparseableOutput {
+    pub attempts: usize,
+    pub validation_error: String,
+    pub last_response: String,
+}
+
+// Progress reported while a verdict is being requested
+#[derive(Debug, Clone)]
+pub enum VerdictProgress {
+    Token(String),
+    Rejected { attempt: usize, error: String },
+    Switched { model: String, reason: String }, // The next tokens come from another provider, or none from rules only
+}
+
+#[derive(Debug)]
+pub enum VerdictError {
+    Llm(LlmError),
+    Unparseable(UnparseableOutput),
+}
+
+// Accepts "High", "HIGH" or "**High**"; the schema still lists the canonical spelling
+fn lenient_risk_level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RiskLevel, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    RiskLevel::parse(&value)
+        .ok_or_else(|| serde::de::Error::custom(format!("unknown risk_level '{}', expected Low, Medium, High or Critical", value)))
+}
+
+/// JSON schema of `LlmVerdict`, sent to providers with structured output and embedded in the prompt.
+pub fn verdict_schema() -> Value {
+    json!({
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["risk_level", "explanation", "findings", "dangerous_functions", "confidence"],
+        "properties": {
+            "risk_level": {"type": "string", "enum": ["Low", "Medium", "High", "Critical"]},
+            "explanation": {"type": "string", "description": "Detailed technical analysis in Markdown"},
+            "findings": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["title", "severity", "description"],
+                    "properties": {
+                        "title": {"type": "string"},
+                        "severity": {"type": "string", "enum": ["Info", "Low", "Medium", "High", "Critical"]},
+                        "description": {"type": "string"}
+                    }
+                }
+            },
+            "dangerous_functions": {"type": "array", "items": {"type": "string"}},
+            "confidence": {"type": "number", "minimum": 0, "maximum": 1}
+        }
+    })
+}
+
+/// The JSON object inside the response, without Markdown fences or surrounding prose.
+fn json_candidate(content: &str) -> &str {
+    match (content.find('{'), content.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &content[start..=end],
+        _ => content.trim(),
+    }
+}
+
+/// Deserializes and validates the model output; the error is sent back to the model on retry.
+pub fn parse_verdict(content: &str) -> Result<LlmVerdict, String> {
+    let verdict: LlmVerdict = serde_json::from_str(json_candidate(content)).map_err(|e| e.to_string())?;
+
+    if verdict.explanation.trim().is_empty() {
+        return Err("explanation must not be empty".to_string());
+    }
+    if !(0.0..=1.0).contains(&verdict.confidence) {
+        return Err(format!("confidence must be between 0 and 1, got {}", verdict.confidence));
+    }
+    if let Some(finding) = verdict.findings.iter().find(|f| f.title.trim().is_empty()) {
+        return Err(format!("finding without a title: {:?}", finding.description));
+    }
+    Ok(verdict)
+}
+
+/// Asks the model for a verdict, re-prompting with the validation error until the output parses
+/// or `max_attempts` responses were rejected. With `on_progress` the response is streamed.
+pub async fn request_verdict(
+    llm: &dyn LlmProvider,
+    system_message: &str,
+    prompt: &str,
+    max_attempts: usize,
+    on_progress: Option<&(dyn Fn(VerdictProgress) + Send + Sync)>,
+) -> Result<(LlmVerdict, usize), VerdictError> {
+    let schema = verdict_schema();
+    let mut current_prompt = prompt.to_string();
+    let mut rejected = UnparseableOutput::default();
+
+    for attempt in 1..=max_attempts.max(1) {
+        let content = match on_progress {
+            Some(on_progress) => {
+                let on_token = |token: &str| on_progress(VerdictProgress::Token(token.to_string()));
+                llm.stream_json(system_message, &current_prompt, &schema, &on_token).await
+            }
+            None => llm.complete_json(system_message, &current_prompt, &schema).await,
+        }
+        .map_err(VerdictError::Llm)?;
+        info!("📄 LLM response (attempt {}): {}", attempt, content);
+
+        match parse_verdict(&content) {
+            Ok(verdict) => return Ok((verdict, attempt)),
+            Err(e) => {
+                warn!("⚠️ Rejected LLM output on attempt {}/{}: {}", attempt, max_attempts, e);
+                if let Some(on_progress) = on_progress {
+                    on_progress(VerdictProgress::Rejected {
+                        attempt,
+                        error: e.clone(),
+                    });
+                }
+                current_prompt = format!(
+                    "{}\n\nYour previous response could not be used: {}\nPrevious response:\n{}\n\nAnswer again with only the JSON object described by the schema, without Markdown code fences.",
+                    prompt, e, content
+                );
+                rejected = UnparseableOutput {
+                    attempts: attempt,
+                    validation_error: e,
+                    last_response: content,
+                };
+            }
+        }
+    }
+
+    Err(VerdictError::Unparseable(rejected))
+}
+
+/// Verdict from the local findings alone, for when no model could answer. Without findings the
+/// call is reported as Medium, since nothing reviewed it.
+pub fn rules_only_verdict(findings: &[Finding], reason: &str) -> LlmVerdict {
+    LlmVerdict {
+        risk_level: rule_risk_level(findings).unwrap_or(RiskLevel::Medium),
+        explanation: format!(
+            "**No model verdict available** ({}). This assessment is based on the local rule findings only:\n\n{}",
+            reason,
+            format_findings_for_prompt(findings)
+        ),
+        findings: Vec::new(),
+        dangerous_functions: Vec::new(),
+        confidence: 0.0,
+    }
+}
@@ -0,0 +1,391 @@
+use ethabi::{Function, ParamType, Token};
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{Address, Bytes, I256, U256};
+use log::info;
+use serde::Serialize;
+
+use crate::rpc::{encode_call, multicall};
+use crate::rules::{Finding, Severity};
+use crate::simulation::{topic_address, AssetKind, SimulationReport};
+use crate::token_metadata::{
+    format_units, get_or_fetch_token_metadata, is_approval_function, is_outgoing_amount, is_unlimited, TokenAmount,
+};
+
+// Share of the balance from which an approval or transfer counts as exposing all of it
+const ENTIRE_BALANCE_PERCENT: f64 = 90.0;
+
+// Parameter names (lowercase, without leading underscores) that hold the approved spender
+const SPENDER_PARAMETER_NAMES: [&str; 4] = ["spender", "guy", "usr", "operator"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldingAction {
+    Approval,
+    Transfer,
+}
+
+// One token the call approves or sends, against what the sender holds right now
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenHolding {
+    pub token: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    pub action: HoldingAction,
+    pub balance: String,     // Readable units
+    pub balance_raw: String, // Base units, decimal
+    pub spender: Option<Address>,
+    pub allowance_before: Option<String>, // Existing allowance of the spender, readable units or "unlimited"
+    pub requested: String,                // Amount approved or sent by the call, readable units or "unlimited"
+    pub at_risk: String,                  // Part of the balance that could leave the wallet
+    pub share_of_balance: Option<f64>,    // Percent of the balance, None for an empty balance
+    pub exceeds_balance: bool,            // A transfer larger than the balance reverts
+    pub summary: String,                  // e.g. "this approval exposes 42,000 USDC currently held"
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletExposure {
+    pub owner: Address,
+    pub native_balance: Option<String>, // ETH, readable units
+    pub holdings: Vec<TokenHolding>,
+}
+
+impl WalletExposure {
+    pub fn balance_of(&self, token: &Address) -> Option<U256> {
+        self.holdings
+            .iter()
+            .find(|holding| holding.token == *token)
+            .and_then(|holding| U256::from_dec_str(&holding.balance_raw).ok())
+    }
+}
+
+// A token the call touches, before its balance and allowance are read
+struct HoldingTarget {
+    token: Address,
+    action: HoldingAction,
+    spender: Option<Address>,
+    amount: U256,
+}
+
+/// The spender argument of an approval: a parameter named like one, or the first address.
+fn approval_spender(function: &Function, args: &[Token]) -> Option<Address> {
+    let named = function.inputs.iter().zip(args).find_map(|(param, arg)| {
+        let name = param.name.trim_start_matches('_').to_lowercase();
+        match arg {
+            Token::Address(address) if SPENDER_PARAMETER_NAMES.contains(&name.as_str()) => Some(*address),
+            _ => None,
+        }
+    });
+    // permit(owner, spender, ...) names its parameters; unnamed approvals start with the spender
+    named.or_else(|| {
+        args.iter().find_map(|arg| match arg {
+            Token::Address(address) => Some(*address),
+            _ => None,
+        })
+    })
+}
+
+/// Reads the sender's current balances and allowances for every token the call approves or
+/// sends (decoded arguments and simulated events) in one Multicall3 batch.
+pub async fn wallet_exposure(
+    provider: &Provider<Http>,
+    owner: Address,
+    function_name: &str,
+    decoded: Option<(&Function, &[Token])>,
+    token_amounts: &[TokenAmount],
+    simulation: Option<&SimulationReport>,
+) -> Result<WalletExposure, Box<dyn std::error::Error>> {
+    let mut targets: Vec<HoldingTarget> = Vec::new();
+    let spender = decoded.and_then(|(function, args)| approval_spender(function, args));
+
+    for amount in token_amounts {
+        let token = match amount.token {
+            Some(token) => token,
+            None => continue,
+        };
+        let raw = U256::from_dec_str(&amount.raw).unwrap_or_default();
+        if is_approval_function(function_name) {
+            targets.push(HoldingTarget {
+                token,
+                action: HoldingAction::Approval,
+                spender,
+                amount: raw,
+            });
+        } else if is_outgoing_amount(function_name, amount) {
+            targets.push(HoldingTarget {
+                token,
+                action: HoldingAction::Transfer,
+                spender: None,
+                amount: raw,
+            });
+        }
+    }
+
+    // Approvals and outflows the decoder cannot see, e.g. inside a multicall or a router
+    if let Some(report) = simulation {
+        for event in &report.events {
+            if event.name.as_deref() == Some("Approval")
+                && event.topics.len() == 3
+                && topic_address(&event.topics[1]) == owner
+                && !targets.iter().any(|t| t.token == event.address && t.action == HoldingAction::Approval)
+            {
+                targets.push(HoldingTarget {
+                    token: event.address,
+                    action: HoldingAction::Approval,
+                    spender: Some(topic_address(&event.topics[2])),
+                    amount: U256::from_big_endian(event.data.get(..32).unwrap_or_default()),
+                });
+            }
+        }
+        for change in report.asset_changes.iter().filter(|c| c.address == owner && c.asset == AssetKind::Erc20) {
+            let (token, delta) = match (change.token, I256::from_dec_str(&change.delta)) {
+                (Some(token), Ok(delta)) if delta.is_negative() => (token, delta.unsigned_abs()),
+                _ => continue,
+            };
+            if !targets.iter().any(|t| t.token == token && t.action == HoldingAction::Transfer) {
+                targets.push(HoldingTarget {
+                    token,
+                    action: HoldingAction::Transfer,
+                    spender: None,
+                    amount: delta,
+                });
+            }
+        }
+    }
+
+    // balanceOf for each token, then allowance for each approval, in a single batch
+    let mut tokens: Vec<Address> = targets.iter().map(|t| t.token).collect();
+    tokens.sort();
+    tokens.dedup();
+    let mut calls: Vec<(Address, Vec<u8>)> = tokens
+        .iter()
+        .map(|token| (*token, encode_call("balanceOf(address)", &[Token::Address(owner)])))
+        .collect();
+    for target in &targets {
+        if let Some(spender) = target.spender {
+            calls.push((
+                target.token,
+                encode_call("allowance(address,address)", &[Token::Address(owner), Token::Address(spender)]),
+            ));
+        }
+    }
+    let results = multicall(provider, &calls).await?;
+    let (balances, allowances) = results.split_at(tokens.len());
+    let mut allowances = allowances.iter();
+
+    let native_balance = provider
+        .get_balance(owner, None)
+        .await
+        .ok()
+        .map(|balance| format_units(balance, 18));
+
+    let mut holdings = Vec::new();
+    for target in &targets {
+        let allowance_before = match target.spender {
+            Some(_) => allowances.next().and_then(|result| decode_uint(result.as_ref())),
+            None => None,
+        };
+        let balance = tokens
+            .iter()
+            .position(|token| *token == target.token)
+            .and_then(|index| decode_uint(balances[index].as_ref()));
+        let balance = match balance {
+            Some(balance) => balance,
+            None => continue, // Not an ERC-20 we can read
+        };
+
+        let metadata = get_or_fetch_token_metadata(provider, &target.token).await;
+        let decimals = metadata.decimals.unwrap_or(0);
+        let symbol = metadata.symbol.unwrap_or_else(|| format!("{:?}", target.token));
+        holdings.push(holding(target, &symbol, decimals, balance, allowance_before, function_name));
+    }
+
+    info!(
+        "👛 Wallet exposure for {:?}: {} token(s) touched, {} batched call(s)",
+        owner,
+        holdings.len(),
+        calls.len()
+    );
+    Ok(WalletExposure {
+        owner,
+        native_balance,
+        holdings,
+    })
+}
+
+fn decode_uint(output: Option<&Bytes>) -> Option<U256> {
+    match ethabi::decode(&[ParamType::Uint(256)], output?).ok()?.first() {
+        Some(Token::Uint(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+fn readable(raw: U256, decimals: u8) -> String {
+    if is_unlimited(raw) {
+        "unlimited".to_string()
+    } else {
+        format_units(raw, decimals)
+    }
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    value.to_string().parse().unwrap_or(f64::MAX)
+}
+
+fn holding(
+    target: &HoldingTarget,
+    symbol: &str,
+    decimals: u8,
+    balance: U256,
+    allowance_before: Option<U256>,
+    function_name: &str,
+) -> TokenHolding {
+    // increaseAllowance adds to the existing allowance, approve and permit replace it
+    let allowance_after = match (function_name, allowance_before) {
+        ("increaseAllowance", Some(before)) => before.saturating_add(target.amount),
+        _ => target.amount,
+    };
+    let requested = match target.action {
+        HoldingAction::Approval => allowance_after,
+        HoldingAction::Transfer => target.amount,
+    };
+    let at_risk = requested.min(balance);
+    // In f64, the token decides balanceOf and a scaled U256 product could overflow
+    let share_of_balance = (!balance.is_zero()).then(|| {
+        let share = u256_to_f64(at_risk) / u256_to_f64(balance) * 100.0;
+        (share * 100.0).floor() / 100.0
+    });
+
+    let held = format!("{} {}", format_units(balance, decimals), symbol);
+    let summary = match target.action {
+        HoldingAction::Approval if balance.is_zero() => {
+            format!("this approval exposes no {} yet, the sender holds none", symbol)
+        }
+        HoldingAction::Approval if at_risk == balance => {
+            format!("this approval exposes {} currently held", held)
+        }
+        HoldingAction::Approval => format!(
+            "this approval exposes {} of {} currently held",
+            format_units(at_risk, decimals),
+            held
+        ),
+        HoldingAction::Transfer if requested > balance => format!(
+            "this transfer of {} {} exceeds the {} held and would revert",
+            format_units(requested, decimals),
+            symbol,
+            held
+        ),
+        HoldingAction::Transfer => format!(
+            "this transfer sends {} of {} held ({:.1}%)",
+            format_units(requested, decimals),
+            held,
+            share_of_balance.unwrap_or_default()
+        ),
+    };
+
+    TokenHolding {
+        token: target.token,
+        symbol: symbol.to_string(),
+        decimals,
+        action: target.action,
+        balance: format_units(balance, decimals),
+        balance_raw: balance.to_string(),
+        spender: target.spender,
+        allowance_before: allowance_before.map(|allowance| readable(allowance, decimals)),
+        requested: readable(requested, decimals),
+        at_risk: format_units(at_risk, decimals),
+        share_of_balance,
+        exceeds_balance: target.action == HoldingAction::Transfer && requested > balance,
+        summary,
+    }
+}
+
+/// Turns the wallet exposure into rule findings.
+pub fn wallet_exposure_findings(report: &WalletExposure) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for holding in &report.holdings {
+        let location = format!("wallet / {}", holding.symbol);
+        let share = holding.share_of_balance.unwrap_or_default();
+
+        if holding.action == HoldingAction::Approval && share >= ENTIRE_BALANCE_PERCENT {
+            findings.push(
+                Finding::new(
+                    "exposure.entire_balance",
+                    Severity::Medium,
+                    "Approval exposes the sender's whole balance",
+                    format!(
+                        "{}; the spender {:?} could move all of it",
+                        capitalize(&holding.summary),
+                        holding.spender.unwrap_or_default()
+                    ),
+                )
+                .at(location.clone()),
+            );
+        }
+
+        if let Some(before) = holding.allowance_before.as_deref().filter(|before| *before != "0") {
+            findings.push(
+                Finding::new(
+                    "exposure.existing_allowance",
+                    Severity::Low,
+                    "Spender already holds an allowance",
+                    format!(
+                        "{:?} can already spend {} {} of the sender's tokens",
+                        holding.spender.unwrap_or_default(),
+                        before,
+                        holding.symbol
+                    ),
+                )
+                .at(location.clone()),
+            );
+        }
+
+        if holding.exceeds_balance {
+            findings.push(
+                Finding::new(
+                    "exposure.insufficient_balance",
+                    Severity::Low,
+                    "Transfer exceeds the sender's balance",
+                    capitalize(&holding.summary),
+                )
+                .at(location),
+            );
+        }
+    }
+
+    findings
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Renders the wallet exposure as compact text for the LLM prompt.
+pub fn format_wallet_exposure_for_prompt(report: Option<&WalletExposure>) -> String {
+    let report = match report {
+        Some(report) => report,
+        None => return "Not available (no sender address or RPC node)".to_string(),
+    };
+
+    let mut lines = vec![format!(
+        "Sender {:?}, ETH balance: {}",
+        report.owner,
+        report.native_balance.as_deref().unwrap_or("unknown")
+    )];
+    if report.holdings.is_empty() {
+        lines.push("No ERC-20 approvals or transfers by the sender".to_string());
+    }
+    for holding in &report.holdings {
+        let allowance = holding
+            .allowance_before
+            .as_ref()
+            .map(|before| format!(", existing allowance {} {}", before, holding.symbol))
+            .unwrap_or_default();
+        lines.push(format!("- {}{}", holding.summary, allowance));
+    }
+    lines.join("\n")
+}